@@ -0,0 +1,79 @@
+//! A small HTTP/JSON API for inspecting the network
+
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use protocol::NodeId;
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Response, Server, StatusCode};
+
+use crate::nodes::NodeTable;
+
+type JsonResponse = Response<Cursor<Vec<u8>>>;
+
+/// Serve requests forever
+pub fn serve(server: &Server, table: &Mutex<NodeTable>) {
+    for request in server.incoming_requests() {
+        let response = route(request.method(), request.url(), table);
+
+        // The client may have hung up, there is nobody to report this to
+        let _ = request.respond(response);
+    }
+}
+
+fn route(method: &Method, url: &str, table: &Mutex<NodeTable>) -> JsonResponse {
+    if *method != Method::Get {
+        return error(405, "method not allowed");
+    }
+
+    let path: Vec<&str> = url
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let table = table.lock().unwrap();
+
+    match path.as_slice() {
+        ["nodes"] => {
+            let nodes: Vec<_> = table.nodes().collect();
+            json_response(200, &nodes)
+        }
+        ["nodes", id] => match parse_id(id) {
+            Some(id) => match table.get(id) {
+                Some(node) => json_response(200, node),
+                None => error(404, "unknown node"),
+            },
+            None => error(400, "invalid node id"),
+        },
+        ["stats"] => json_response(200, &table.stats),
+        _ => error(404, "not found"),
+    }
+}
+
+/// Parse a node id, in either decimal or `0x` prefixed hex
+fn parse_id(id: &str) -> Option<NodeId> {
+    let val = match id.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => id.parse(),
+    };
+
+    val.ok().map(NodeId)
+}
+
+fn json_response<T: Serialize + ?Sized>(status: u16, body: &T) -> JsonResponse {
+    let body = serde_json::to_vec(body).expect("json serialization failed");
+    let content_type =
+        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+    Response::from_data(body)
+        .with_status_code(StatusCode(status))
+        .with_header(content_type)
+}
+
+fn error(status: u16, msg: &str) -> JsonResponse {
+    json_response(status, &json!({ "error": msg }))
+}
@@ -0,0 +1,12 @@
+//! Host side gateway, bridging a radio "passthrough modem" connected over
+//! a serial port to a local HTTP/JSON API
+//!
+//! The modem comms run on their own thread (see `modem::run`), and record
+//! every node heard into a shared `NodeTable`, which is then served by
+//! `api::serve`.
+
+pub mod api;
+pub mod modem;
+pub mod nodes;
+
+pub use crate::nodes::NodeTable;
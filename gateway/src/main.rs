@@ -0,0 +1,59 @@
+use std::env;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serialport::SerialPortSettings;
+use tiny_http::Server;
+
+use gateway::{api, modem, NodeTable};
+
+const DEFAULT_BAUD: u32 = 115_200;
+const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:8080";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <serial-device> [baud] [http-addr]", args[0]);
+        process::exit(1);
+    }
+
+    let device = &args[1];
+    let baud_rate = match args.get(2).map(|b| b.parse()) {
+        None => DEFAULT_BAUD,
+        Some(Ok(baud)) => baud,
+        Some(Err(_)) => fail("invalid baud rate"),
+    };
+    let http_addr = args.get(3).map(String::as_str).unwrap_or(DEFAULT_HTTP_ADDR);
+
+    let settings = SerialPortSettings {
+        baud_rate,
+        timeout: Duration::from_millis(100),
+        ..Default::default()
+    };
+
+    let port = serialport::open_with_settings(device, &settings)
+        .unwrap_or_else(|e| fail(&format!("failed to open {}: {}", device, e)));
+
+    let server = Server::http(http_addr)
+        .unwrap_or_else(|e| fail(&format!("failed to bind {}: {}", http_addr, e)));
+
+    let table = Arc::new(Mutex::new(NodeTable::new()));
+
+    let modem_table = table.clone();
+    thread::spawn(move || {
+        match modem::run(port, &modem_table) {
+            Ok(()) => fail("modem disconnected"),
+            Err(e) => fail(&format!("modem error: {}", e)),
+        }
+    });
+
+    println!("gateway: listening on http://{}", http_addr);
+    api::serve(&server, &table);
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("gateway: {}", msg);
+    process::exit(1);
+}
@@ -0,0 +1,119 @@
+//! Communication with the passthrough modem over a serial port
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use protocol::framing::{consts::U1024, encode_frame, FeedResult, FrameDecoder};
+use protocol::time::{TimeSyncResponse, Timestamp};
+use protocol::{decode, decode_header, Envelope, Message, NodeId};
+
+use crate::nodes::NodeTable;
+
+/// The address used by the gateway when replying to nodes
+pub const GATEWAY_ID: NodeId = NodeId(0x0000);
+
+/// Read frames from the modem until the port is closed, recording every
+/// node heard into the table, and replying to any requests made to the
+/// gateway. Read timeouts are ignored, any other I/O error is returned
+pub fn run<P: Read + Write>(mut port: P, table: &Mutex<NodeTable>) -> io::Result<()> {
+    let mut decoder: FrameDecoder<U1024> = FrameDecoder::new();
+    let mut buf = [0u8; 256];
+    let mut seq: u32 = 0;
+
+    loop {
+        let len = match port.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(ref e) if is_transient(e.kind()) => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut data = &buf[..len];
+        loop {
+            data = match decoder.feed(data) {
+                FeedResult::Consumed => break,
+                FeedResult::Frame { payload, remaining } => {
+                    if let Some((dst, reply)) = handle_payload(payload, table) {
+                        // Counted before sending, so the reply is never seen
+                        // by a node before it shows up in the stats
+                        table.lock().unwrap().stats.replies += 1;
+                        send_reply(&mut port, seq, dst, reply)?;
+                        seq = seq.wrapping_add(1);
+                    }
+                    remaining
+                }
+                FeedResult::Error { remaining, .. } => {
+                    table.lock().unwrap().stats.frame_errors += 1;
+                    remaining
+                }
+            };
+        }
+    }
+}
+
+/// Record a received payload, returning a reply to be sent (if any)
+fn handle_payload(payload: &[u8], table: &Mutex<NodeTable>) -> Option<(NodeId, Message<'static>)> {
+    let receive = timestamp_now();
+    let mut table = table.lock().unwrap();
+    table.stats.frames += 1;
+
+    match decode_header(payload) {
+        Ok((header, _body)) => table.record(&header, SystemTime::now()),
+        Err(_) => {
+            table.stats.decode_errors += 1;
+            return None;
+        }
+    }
+
+    match decode(payload) {
+        Ok(Envelope { src, dst, message: Message::TimeSyncRequest(req), .. })
+            if dst == GATEWAY_ID || dst.is_broadcast() =>
+        {
+            // The transmit time is filled in immediately before sending
+            let resp = TimeSyncResponse {
+                originate: req.originate,
+                receive,
+                transmit: receive,
+            };
+            Some((src, Message::TimeSyncResponse(resp)))
+        }
+        Ok(_) => None,
+        Err(_) => {
+            table.stats.decode_errors += 1;
+            None
+        }
+    }
+}
+
+fn send_reply<P: Write>(port: &mut P, seq: u32, dst: NodeId, mut message: Message) -> io::Result<()> {
+    if let Message::TimeSyncResponse(ref mut resp) = message {
+        resp.transmit = timestamp_now();
+    }
+
+    let env = Envelope::new(seq, GATEWAY_ID, dst, message);
+    let mut buf = [0u8; 512];
+    let frame = encode_frame(&env, &mut buf)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{:?}", e)))?;
+
+    port.write_all(frame)?;
+    port.flush()
+}
+
+fn timestamp_now() -> Timestamp {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Timestamp {
+        seconds: now.as_secs() as i64,
+        nanos: now.subsec_nanos(),
+    }
+}
+
+fn is_transient(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted
+    )
+}
@@ -0,0 +1,126 @@
+//! Tracking of the nodes heard by the gateway
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use protocol::{Header, MessageKind, NodeId};
+use serde::Serialize;
+
+/// Everything the gateway knows about a single node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeInfo {
+    pub id: u16,
+    /// Unix time (in seconds) the node was first heard
+    pub first_seen: u64,
+    /// Unix time (in seconds) the node was last heard
+    pub last_seen: u64,
+    pub last_seq: u32,
+    pub last_kind: String,
+    pub messages: u64,
+}
+
+/// Counters for the health of the modem link
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// Frames received with a valid CRC
+    pub frames: u64,
+    /// Frames discarded due to framing, COBS, or CRC errors
+    pub frame_errors: u64,
+    /// Valid frames that did not contain a decodable envelope
+    pub decode_errors: u64,
+    /// Replies sent back to nodes through the modem
+    pub replies: u64,
+}
+
+/// A table of all nodes heard by the gateway, sorted by address
+#[derive(Debug, Default)]
+pub struct NodeTable {
+    nodes: BTreeMap<u16, NodeInfo>,
+    pub stats: Stats,
+}
+
+impl NodeTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a message received from a node
+    pub fn record(&mut self, header: &Header, now: SystemTime) {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let info = self.nodes.entry(header.src.0).or_insert_with(|| NodeInfo {
+            id: header.src.0,
+            first_seen: now,
+            last_seen: now,
+            last_seq: header.seq,
+            last_kind: String::new(),
+            messages: 0,
+        });
+
+        info.last_seen = now;
+        info.last_seq = header.seq;
+        info.last_kind = kind_name(header.kind);
+        info.messages += 1;
+    }
+
+    /// Look up a single node by address
+    pub fn get(&self, id: NodeId) -> Option<&NodeInfo> {
+        self.nodes.get(&id.0)
+    }
+
+    /// All known nodes, in order of address
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeInfo> {
+        self.nodes.values()
+    }
+}
+
+fn kind_name(kind: u8) -> String {
+    match MessageKind::try_from(kind) {
+        Ok(kind) => format!("{:?}", kind),
+        Err(_) => format!("Unknown({})", kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::PROTOCOL_VERSION;
+    use std::time::Duration;
+
+    fn header(src: u16, seq: u32, kind: u8) -> Header {
+        Header {
+            version: PROTOCOL_VERSION,
+            kind,
+            seq,
+            src: NodeId(src),
+            dst: NodeId::BROADCAST,
+        }
+    }
+
+    #[test]
+    fn record_updates() {
+        let mut table = NodeTable::new();
+        let t0 = UNIX_EPOCH + Duration::from_secs(1000);
+        let t1 = UNIX_EPOCH + Duration::from_secs(1010);
+
+        table.record(&header(0x0002, 5, MessageKind::Demo as u8), t0);
+        table.record(&header(0x0001, 1, MessageKind::Demo as u8), t0);
+        table.record(&header(0x0002, 6, 0xEE), t1);
+
+        let ids: Vec<u16> = table.nodes().map(|n| n.id).collect();
+        assert_eq!(ids, vec![0x0001, 0x0002]);
+
+        let node = table.get(NodeId(0x0002)).unwrap();
+        assert_eq!(node.first_seen, 1000);
+        assert_eq!(node.last_seen, 1010);
+        assert_eq!(node.last_seq, 6);
+        assert_eq!(node.last_kind, "Unknown(238)");
+        assert_eq!(node.messages, 2);
+
+        assert!(table.get(NodeId(0x0003)).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! Drive the gateway through a pseudo terminal, in place of a real modem
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gateway::modem::{self, GATEWAY_ID};
+use gateway::{api, NodeTable};
+use protocol::framing::{consts::U256, encode_frame, FeedResult, FrameDecoder};
+use protocol::time::{TimeSyncRequest, Timestamp};
+use protocol::{decode, DemoMessage, Envelope, Message, NodeId};
+use serde_json::Value;
+use serialport::posix::TTYPort;
+use tiny_http::Server;
+
+fn frame(env: &Envelope) -> Vec<u8> {
+    let mut buf = [0u8; 128];
+    encode_frame(env, &mut buf).unwrap().to_vec()
+}
+
+fn demo_frame(src: u16, seq: u32) -> Vec<u8> {
+    frame(&Envelope::new(
+        seq,
+        NodeId(src),
+        NodeId::BROADCAST,
+        Message::Demo(DemoMessage {
+            small: 1,
+            medium: 2,
+            large: 3,
+            text_bytes: "hello gateway",
+        }),
+    ))
+}
+
+/// Start the modem and HTTP threads, returning the modem side of the
+/// serial link and the HTTP address
+fn start_gateway() -> (TTYPort, SocketAddr) {
+    let (modem_side, gateway_side) = TTYPort::pair().unwrap();
+    let table = Arc::new(Mutex::new(NodeTable::new()));
+
+    let modem_table = table.clone();
+    thread::spawn(move || modem::run(gateway_side, &modem_table));
+
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let addr = server.server_addr();
+    thread::spawn(move || api::serve(&server, &table));
+
+    (modem_side, addr)
+}
+
+fn http_get(addr: SocketAddr, path: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn nodes_over_pty() {
+    let (mut modem_side, addr) = start_gateway();
+
+    // Line noise, then three valid frames from two nodes
+    modem_side.write_all(&[0x12, 0x34, 0x00]).unwrap();
+    modem_side.write_all(&demo_frame(0x1234, 7)).unwrap();
+    modem_side.write_all(&demo_frame(0x0042, 1)).unwrap();
+    modem_side.write_all(&demo_frame(0x1234, 8)).unwrap();
+    modem_side.flush().unwrap();
+
+    let start = Instant::now();
+    let nodes = loop {
+        let (status, nodes) = http_get(addr, "/nodes");
+        assert_eq!(status, 200);
+        if nodes.as_array().unwrap().len() == 2 && nodes[1]["messages"] == 2 {
+            break nodes;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out: {}", nodes);
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    assert_eq!(nodes[0]["id"], 0x0042);
+    assert_eq!(nodes[0]["messages"], 1);
+    assert_eq!(nodes[1]["id"], 0x1234);
+    assert_eq!(nodes[1]["last_seq"], 8);
+    assert_eq!(nodes[1]["last_kind"], "Demo");
+
+    let (status, node) = http_get(addr, "/nodes/0x1234");
+    assert_eq!(status, 200);
+    assert_eq!(node["id"], 0x1234);
+
+    let (status, _) = http_get(addr, "/nodes/4321");
+    assert_eq!(status, 404);
+
+    let (status, _) = http_get(addr, "/nodes/bogus");
+    assert_eq!(status, 400);
+
+    let (status, stats) = http_get(addr, "/stats");
+    assert_eq!(status, 200);
+    assert_eq!(stats["frames"], 3);
+    assert_eq!(stats["frame_errors"], 1);
+    assert_eq!(stats["decode_errors"], 0);
+    assert_eq!(stats["replies"], 0);
+}
+
+#[test]
+fn time_sync_over_pty() {
+    let (mut modem_side, addr) = start_gateway();
+
+    let originate = Timestamp {
+        seconds: 1554041486,
+        nanos: 500,
+    };
+    let request = Envelope::new(
+        9,
+        NodeId(0x0042),
+        GATEWAY_ID,
+        Message::TimeSyncRequest(TimeSyncRequest { originate }),
+    );
+    modem_side.write_all(&frame(&request)).unwrap();
+    modem_side.flush().unwrap();
+
+    let mut decoder: FrameDecoder<U256> = FrameDecoder::new();
+    let mut buf = [0u8; 64];
+    let start = Instant::now();
+    let payload = loop {
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        let len = match modem_side.read(&mut buf) {
+            Ok(len) => len,
+            Err(_) => continue,
+        };
+
+        // The gateway only sends a single frame
+        match decoder.feed(&buf[..len]) {
+            FeedResult::Consumed => {}
+            FeedResult::Frame { payload, .. } => break payload.to_vec(),
+            FeedResult::Error { error, .. } => panic!("bad frame: {:?}", error),
+        }
+    };
+
+    let reply = decode(&payload).unwrap();
+    assert_eq!(reply.src, GATEWAY_ID);
+    assert_eq!(reply.dst, NodeId(0x0042));
+
+    let resp = match reply.message {
+        Message::TimeSyncResponse(resp) => resp,
+        other => panic!("unexpected reply: {:?}", other),
+    };
+    assert_eq!(resp.originate, originate);
+    assert!(resp.receive.seconds > originate.seconds);
+    assert!(
+        (resp.transmit.seconds, resp.transmit.nanos) >= (resp.receive.seconds, resp.receive.nanos)
+    );
+
+    let (_, stats) = http_get(addr, "/stats");
+    assert_eq!(stats["replies"], 1);
+}
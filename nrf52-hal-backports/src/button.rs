@@ -0,0 +1,342 @@
+//! Debouncing a button, and recognising presses, long presses and double
+//! presses
+//!
+//! `Debouncer` is driven by the raw level of the button, reported with
+//! `on_edge` from the GPIOTE interrupt, and by time. A level counts once it
+//! has been stable for the debounce time. `poll` returns the gestures
+//! recognised up to an instant, and `next_deadline` tells when to poll
+//! again, such as with a timer of `Timers`. The interrupt handler polls
+//! before reporting each edge.
+//!
+//! Gestures are timed from the first edge of each stable level, so they do
+//! not depend on how late `poll` is called.
+
+use core::time::Duration;
+
+use crate::monotonic::{duration_to_ticks, Instant};
+
+/// Recognised gestures
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Gesture {
+    /// Pressed and released, without a second press soon after
+    Press,
+    /// Held down for the long press time, reported while still held
+    LongPress,
+    /// Pressed a second time soon after a press, reported on the second press
+    DoublePress,
+}
+
+/// Timings of a `Debouncer`, in ticks of the RTC
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Config {
+    /// How long a level must be stable
+    pub debounce: u64,
+    /// How long the button is held for a long press
+    pub long_press: u64,
+    /// How soon after a release a second press makes a double press
+    pub double_press: u64,
+}
+
+impl Config {
+    /// Timings from durations, at a tick rate of `32_768 / divider`
+    pub fn from_durations(
+        divider: u32,
+        debounce: Duration,
+        long_press: Duration,
+        double_press: Duration,
+    ) -> Self {
+        Config {
+            debounce: duration_to_ticks(debounce, divider),
+            long_press: duration_to_ticks(long_press, divider),
+            double_press: duration_to_ticks(double_press, divider),
+        }
+    }
+
+    /// 20 ms debounce, 1 s long press and 300 ms double press timings, at a
+    /// tick rate of `32_768 / divider`
+    pub fn with_divider(divider: u32) -> Self {
+        Config::from_durations(
+            divider,
+            Duration::from_millis(20),
+            Duration::from_millis(1_000),
+            Duration::from_millis(300),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    Idle,
+    /// Pressed at an instant. `reported` once a gesture of the press is
+    Pressed { at: Instant, reported: bool },
+    /// Released at an instant, waiting for a second press
+    Released { at: Instant },
+}
+
+/// Recognises gestures of a button from its raw level
+pub struct Debouncer {
+    config: Config,
+    /// The last level reported
+    raw: bool,
+    /// When the last level was first reported
+    raw_at: Instant,
+    /// The debounced level
+    stable: bool,
+    state: State,
+}
+
+impl Debouncer {
+    /// A debouncer of a released button
+    pub fn new(config: Config) -> Self {
+        Debouncer {
+            config,
+            raw: false,
+            raw_at: Instant::ZERO,
+            stable: false,
+            state: State::Idle,
+        }
+    }
+
+    /// Report the raw level of the button, `pressed` or not, such as after
+    /// an edge of its pin. Reporting the same level again has no effect
+    ///
+    /// The gestures recognised by `now` must be taken with `poll` first, or a
+    /// level that became stable since the last `poll` is lost
+    pub fn on_edge(&mut self, now: Instant, pressed: bool) {
+        if pressed != self.raw {
+            self.raw = pressed;
+            self.raw_at = now;
+        }
+    }
+
+    /// The next gesture recognised by `now`, if any. To be called until it
+    /// returns `None`
+    pub fn poll(&mut self, now: Instant) -> Option<Gesture> {
+        loop {
+            let edge = self.pending_edge().filter(|_| self.debounced_at() <= now);
+            let timeout = self.timeout().filter(|&timeout| timeout <= now);
+
+            match (edge, timeout) {
+                (Some(edge), Some(timeout)) if timeout <= edge => return Some(self.on_timeout()),
+                (Some(edge), _) => {
+                    if let Some(gesture) = self.on_stable(edge) {
+                        return Some(gesture);
+                    }
+                }
+                (None, Some(_)) => return Some(self.on_timeout()),
+                (None, None) => return None,
+            }
+        }
+    }
+
+    /// When `poll` may next recognise a gesture, if the level doesn't change
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let edge = self.pending_edge().map(|_| self.debounced_at());
+
+        match (edge, self.timeout()) {
+            (Some(edge), Some(timeout)) => Some(edge.min(timeout)),
+            (edge, timeout) => edge.or(timeout),
+        }
+    }
+
+    /// Is the button pressed, after debouncing?
+    pub fn is_pressed(&self) -> bool {
+        self.stable
+    }
+
+    /// When the last level started, if it differs from the stable level
+    fn pending_edge(&self) -> Option<Instant> {
+        if self.raw != self.stable {
+            Some(self.raw_at)
+        } else {
+            None
+        }
+    }
+
+    fn debounced_at(&self) -> Instant {
+        self.raw_at.checked_add(self.config.debounce).unwrap_or(self.raw_at)
+    }
+
+    /// When the current state ends without a change of level
+    fn timeout(&self) -> Option<Instant> {
+        match self.state {
+            State::Pressed { at, reported: false } => at.checked_add(self.config.long_press),
+            State::Released { at } => at.checked_add(self.config.double_press),
+            _ => None,
+        }
+    }
+
+    fn on_timeout(&mut self) -> Gesture {
+        match self.state {
+            State::Pressed { at, .. } => {
+                self.state = State::Pressed { at, reported: true };
+                Gesture::LongPress
+            }
+            _ => {
+                self.state = State::Idle;
+                Gesture::Press
+            }
+        }
+    }
+
+    /// Take the raw level as the stable level, that started at `at`
+    fn on_stable(&mut self, at: Instant) -> Option<Gesture> {
+        self.stable = self.raw;
+
+        match (self.state, self.stable) {
+            (State::Idle, true) => {
+                self.state = State::Pressed { at, reported: false };
+                None
+            }
+            (State::Released { .. }, true) => {
+                self.state = State::Pressed { at, reported: true };
+                Some(Gesture::DoublePress)
+            }
+            (State::Pressed { reported: false, .. }, false) => {
+                self.state = State::Released { at };
+                None
+            }
+            (State::Pressed { reported: true, .. }, false) => {
+                self.state = State::Idle;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        debounce: 10,
+        long_press: 1_000,
+        double_press: 300,
+    };
+
+    /// Feed raw levels at instants, polling at every tick, and collect the
+    /// gestures with when they were recognised
+    fn run(edges: &[(u64, bool)], until: u64) -> [Option<(u64, Gesture)>; 4] {
+        let mut debouncer = Debouncer::new(CONFIG);
+        let mut gestures = [None; 4];
+        let mut count = 0;
+        let mut edges = edges.iter().peekable();
+
+        for tick in 0..=until {
+            let now = Instant::from_ticks(tick);
+            while let Some(gesture) = debouncer.poll(now) {
+                gestures[count] = Some((tick, gesture));
+                count += 1;
+            }
+            while let Some(&&(at, pressed)) = edges.peek() {
+                if at > tick {
+                    break;
+                }
+                debouncer.on_edge(now, pressed);
+                edges.next();
+            }
+        }
+
+        gestures
+    }
+
+    #[test]
+    fn press() {
+        // Bouncing on both edges
+        let edges = [
+            (100, true), (102, false), (104, true),
+            (300, false), (303, true), (305, false),
+        ];
+        assert_eq!(
+            run(&edges, 2_000),
+            [Some((305 + 300, Gesture::Press)), None, None, None]
+        );
+    }
+
+    #[test]
+    fn glitches_are_ignored() {
+        let edges = [(100, true), (105, false), (500, true), (509, false)];
+        assert_eq!(run(&edges, 2_000), [None; 4]);
+    }
+
+    #[test]
+    fn long_press() {
+        let edges = [(100, true), (2_000, false), (2_200, true), (2_250, false)];
+        assert_eq!(
+            run(&edges, 3_000),
+            [
+                Some((1_100, Gesture::LongPress)),
+                Some((2_250 + 300, Gesture::Press)),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn double_press() {
+        let edges = [
+            (100, true), (200, false), (400, true), (450, false),
+            (1_000, true), (1_050, false), (1_340, true), (2_500, false),
+        ];
+        assert_eq!(
+            run(&edges, 3_000),
+            [
+                Some((410, Gesture::DoublePress)),
+                Some((1_350, Gesture::DoublePress)),
+                None,
+                None,
+            ]
+        );
+
+        // Too late for a double press
+        let edges = [(100, true), (200, false), (500, true), (550, false)];
+        assert_eq!(
+            run(&edges, 2_000),
+            [
+                Some((500, Gesture::Press)),
+                Some((550 + 300, Gesture::Press)),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn polled_late() {
+        // Gestures are recognised in order, timed from the edges
+        let mut debouncer = Debouncer::new(CONFIG);
+        debouncer.on_edge(Instant::from_ticks(100), true);
+        assert_eq!(debouncer.next_deadline(), Some(Instant::from_ticks(110)));
+        assert_eq!(debouncer.poll(Instant::from_ticks(109)), None);
+        assert_eq!(debouncer.next_deadline(), Some(Instant::from_ticks(110)));
+        assert_eq!(debouncer.poll(Instant::from_ticks(200)), None);
+        debouncer.on_edge(Instant::from_ticks(200), false);
+
+        let now = Instant::from_ticks(5_000);
+        assert_eq!(debouncer.poll(now), Some(Gesture::Press));
+        assert_eq!(debouncer.poll(now), None);
+        debouncer.on_edge(now, true);
+        assert!(!debouncer.is_pressed());
+        assert_eq!(debouncer.next_deadline(), Some(Instant::from_ticks(5_010)));
+
+        let now = Instant::from_ticks(9_000);
+        assert_eq!(debouncer.poll(now), Some(Gesture::LongPress));
+        assert_eq!(debouncer.poll(now), None);
+        assert!(debouncer.is_pressed());
+        assert_eq!(debouncer.next_deadline(), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            Config::with_divider(1),
+            Config {
+                debounce: 656,
+                long_press: 32_768,
+                double_press: 9_831,
+            }
+        );
+    }
+}
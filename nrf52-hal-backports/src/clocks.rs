@@ -3,6 +3,10 @@
 
 #![allow(dead_code)]
 
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use nrf52832_pac::clock::{hfclkstat, lfclkstat};
 use nrf52832_pac::CLOCK;
 
 // ZST Type States
@@ -13,6 +17,24 @@ pub struct Internal;
 /// External Crystal Oscillator
 pub struct ExternalOscillator;
 
+/// Internal/RC Oscillator, periodically calibrated against the external
+/// high frequency crystal oscillator
+pub struct CalibratedRc {
+    state: CalibrationState,
+}
+
+/// Progress of a periodic calibration
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum CalibrationState {
+    /// Waiting for the calibration timer
+    Waiting,
+    /// Waiting for the high frequency crystal oscillator, to calibrate against
+    StartingHfxo,
+    /// Calibrating, the crystal oscillator is stopped afterwards if it was
+    /// started for the calibration
+    Calibrating { stop_hfxo: bool },
+}
+
 /// Low Frequency Clock synthesize from High Frequency Clock
 pub struct LfOscSynthesized;
 
@@ -22,6 +44,43 @@ pub struct LfOscStarted;
 /// Low Frequency Clock Stopped
 pub struct LfOscStopped;
 
+/// Proof that the Low Frequency Clock has been started, required to use the
+/// RTC peripherals. Borrows the `Clocks` it was taken from, so the clock
+/// can't be stopped, or the `Clocks` freed, while the proof is held
+#[derive(Debug)]
+pub struct LfClkRunning<'a> {
+    _clocks: PhantomData<&'a ()>,
+}
+
+/// Sources of the High Frequency Clock
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HfClkSource {
+    /// Internal/RC Oscillator
+    Rc,
+    /// External Crystal Oscillator
+    Xtal,
+}
+
+/// Sources of the Low Frequency Clock
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LfClkSource {
+    /// Internal/RC Oscillator
+    Rc,
+    /// External Crystal Oscillator
+    Xtal,
+    /// Synthesized from the High Frequency Clock
+    Synth,
+}
+
+/// The status of a clock, as reported by the CLOCK peripheral
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ClockStatus<S> {
+    /// The source the clock is running from
+    pub source: S,
+    /// Is the clock running?
+    pub running: bool,
+}
+
 /// Extension trait for the CLOCK peripheral
 pub trait ClocksExt {
     fn constrain(self) -> Clocks<Internal, Internal, LfOscStopped>;
@@ -32,6 +91,30 @@ pub const HFCLK_FREQ: u32 = 64_000_000;
 /// Low Frequency Clock Frequency (in Hz)
 pub const LFCLK_FREQ: u32 =     32_768;
 
+/// The calibration timer counts in quarter seconds
+const CTIV_UNIT_MS: u64 = 250;
+/// The calibration timer has 7 bits of range
+const CTIV_MAX: u64 = 127;
+/// The calibration interval used unless set with `set_calibration_interval`
+const CTIV_DEFAULT: u8 = 16;
+
+/// Error types associated with the CLOCK peripheral interface
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    CalibrationIntervalOutOfRange,
+}
+
+/// The calibration timer interval for a duration, rounded down to a quarter
+/// of a second
+fn calibration_interval(interval: Duration) -> Result<u8, Error> {
+    let ctiv = interval.as_millis() / u128::from(CTIV_UNIT_MS);
+    if ctiv == 0 || ctiv > u128::from(CTIV_MAX) {
+        return Err(Error::CalibrationIntervalOutOfRange);
+    }
+
+    Ok(ctiv as u8)
+}
+
 /// A high level abstraction for the CLOCK peripheral
 pub struct Clocks<H, L, LSTAT> {
     hfclk: H,
@@ -41,6 +124,42 @@ pub struct Clocks<H, L, LSTAT> {
 }
 
 impl<H, L, LSTAT> Clocks<H, L, LSTAT> {
+    /// The status of the High Frequency Clock
+    pub fn hfclk_status(&self) -> ClockStatus<HfClkSource> {
+        let stat = self.periph.hfclkstat.read();
+        let source = match stat.src() {
+            hfclkstat::SRCR::RC => HfClkSource::Rc,
+            hfclkstat::SRCR::XTAL => HfClkSource::Xtal,
+        };
+
+        ClockStatus {
+            source,
+            running: stat.state().is_running(),
+        }
+    }
+
+    /// The status of the Low Frequency Clock
+    pub fn lfclk_status(&self) -> ClockStatus<LfClkSource> {
+        let stat = self.periph.lfclkstat.read();
+        let source = match stat.src() {
+            lfclkstat::SRCR::XTAL => LfClkSource::Xtal,
+            lfclkstat::SRCR::SYNTH => LfClkSource::Synth,
+            // The reserved value is never reported
+            lfclkstat::SRCR::RC | lfclkstat::SRCR::_Reserved(_) => LfClkSource::Rc,
+        };
+
+        ClockStatus {
+            source,
+            running: stat.state().is_running(),
+        }
+    }
+
+    /// Destructure the high level interface. Does not reset any configuration
+    /// made to the CLOCK peripheral, the clocks keep running
+    pub fn free(self) -> CLOCK {
+        self.periph
+    }
+
     /// Use an external oscillator as the high frequency clock source
     pub fn enable_ext_hfosc(self) -> Clocks<ExternalOscillator, L, LSTAT> {
         self.periph.tasks_hfclkstart.write(|w| unsafe { w.bits(1) });
@@ -68,7 +187,9 @@ impl<H, L, LSTAT> Clocks<H, L, LSTAT> {
         }
     }
 
-    /// Stop the Low Frequency clock
+    /// Stop the Low Frequency clock. Any `LfClkRunning` proofs must have been
+    /// used up, but RTC peripherals already constructed with them stop
+    /// counting
     pub fn stop_lfclk(self) -> Clocks<H, L, LfOscStopped> {
         self.periph.tasks_lfclkstop.write(|w| unsafe { w.bits(1) });
         Clocks {
@@ -97,6 +218,16 @@ impl<H, L, LSTAT> Clocks<H, L, LSTAT> {
     }
 }
 
+impl<H, L> Clocks<H, L, LfOscStarted> {
+    /// Proof that the Low Frequency clock has been started, to use the RTC
+    /// peripherals with
+    pub fn lfclk_running(&self) -> LfClkRunning<'_> {
+        LfClkRunning {
+            _clocks: PhantomData,
+        }
+    }
+}
+
 /// Allowable configuration options for the low frequency oscillator when
 /// driven fron an external crystal
 pub enum LfOscConfiguration {
@@ -167,3 +298,169 @@ impl ClocksExt for CLOCK {
         }
     }
 }
+
+impl<H, LSTAT> Clocks<H, Internal, LSTAT> {
+    /// Set the interval of periodic calibrations of the internal RC oscillator,
+    /// from a quarter of a second to 31.75 seconds. The interval is rounded
+    /// down to a quarter of a second
+    pub fn set_calibration_interval(&mut self, interval: Duration) -> Result<(), Error> {
+        let ctiv = calibration_interval(interval)?;
+        self.periph.ctiv.write(|w| unsafe { w.ctiv().bits(ctiv) });
+
+        Ok(())
+    }
+}
+
+impl Clocks<ExternalOscillator, Internal, LfOscStarted> {
+    /// Calibrate the internal RC oscillator against the external crystal
+    /// oscillator, blocking until the calibration is done
+    pub fn calibrate(&mut self) {
+        self.periph.events_done.write(|w| unsafe { w.bits(0) });
+        self.periph.tasks_cal.write(|w| unsafe { w.bits(1) });
+
+        while self.periph.events_done.read().bits() != 1 {}
+        self.periph.events_done.write(|w| unsafe { w.bits(0) });
+    }
+}
+
+impl<H> Clocks<H, Internal, LfOscStarted> {
+    /// Calibrate the internal RC oscillator periodically. The POWER_CLOCK
+    /// interrupt must be enabled, and its handler must call
+    /// `on_calibration_interrupt`.
+    ///
+    /// The external crystal oscillator is started for each calibration, if it
+    /// is not already running, and stopped once the calibration is done
+    pub fn enable_auto_calibration(self) -> Clocks<H, CalibratedRc, LfOscStarted> {
+        if self.periph.ctiv.read().ctiv().bits() == 0 {
+            self.periph.ctiv.write(|w| unsafe { w.ctiv().bits(CTIV_DEFAULT) });
+        }
+
+        self.periph.events_ctto.write(|w| unsafe { w.bits(0) });
+        self.periph.events_done.write(|w| unsafe { w.bits(0) });
+        self.periph.intenset.write(|w| w.ctto().set().done().set());
+        self.periph.tasks_ctstart.write(|w| unsafe { w.bits(1) });
+
+        Clocks {
+            hfclk: self.hfclk,
+            lfclk: CalibratedRc {
+                state: CalibrationState::Waiting,
+            },
+            lfstat: self.lfstat,
+            periph: self.periph,
+        }
+    }
+}
+
+impl<H> Clocks<H, CalibratedRc, LfOscStarted> {
+    /// Set the interval of periodic calibrations, from a quarter of a second
+    /// to 31.75 seconds. Takes effect from the next calibration
+    pub fn set_calibration_interval(&mut self, interval: Duration) -> Result<(), Error> {
+        let ctiv = calibration_interval(interval)?;
+        self.periph.ctiv.write(|w| unsafe { w.ctiv().bits(ctiv) });
+
+        Ok(())
+    }
+
+    /// Handle the calibration events, to be called from the POWER_CLOCK
+    /// interrupt handler. Returns whether a calibration has completed
+    pub fn on_calibration_interrupt(&mut self) -> bool {
+        let mut done = false;
+
+        if self.periph.events_ctto.read().bits() == 1 {
+            self.periph.events_ctto.write(|w| unsafe { w.bits(0) });
+
+            if self.hfxo_running() {
+                self.periph.tasks_cal.write(|w| unsafe { w.bits(1) });
+                self.lfclk.state = CalibrationState::Calibrating { stop_hfxo: false };
+            } else {
+                self.periph.events_hfclkstarted.write(|w| unsafe { w.bits(0) });
+                self.periph.intenset.write(|w| w.hfclkstarted().set());
+                self.periph.tasks_hfclkstart.write(|w| unsafe { w.bits(1) });
+                self.lfclk.state = CalibrationState::StartingHfxo;
+            }
+        }
+
+        if self.lfclk.state == CalibrationState::StartingHfxo
+            && self.periph.events_hfclkstarted.read().bits() == 1
+        {
+            self.periph.events_hfclkstarted.write(|w| unsafe { w.bits(0) });
+            self.periph.intenclr.write(|w| w.hfclkstarted().clear());
+            self.periph.tasks_cal.write(|w| unsafe { w.bits(1) });
+            self.lfclk.state = CalibrationState::Calibrating { stop_hfxo: true };
+        }
+
+        if self.periph.events_done.read().bits() == 1 {
+            self.periph.events_done.write(|w| unsafe { w.bits(0) });
+            self.finish_calibration();
+
+            // The calibration timer stops once it times out
+            self.periph.tasks_ctstart.write(|w| unsafe { w.bits(1) });
+            done = true;
+        }
+
+        done
+    }
+
+    /// Stop calibrating the internal RC oscillator. Blocks until any
+    /// calibration in progress is done
+    pub fn disable_auto_calibration(mut self) -> Clocks<H, Internal, LfOscStarted> {
+        self.periph.tasks_ctstop.write(|w| unsafe { w.bits(1) });
+        self.periph.intenclr.write(|w| w.ctto().clear().done().clear().hfclkstarted().clear());
+        self.periph.events_ctto.write(|w| unsafe { w.bits(0) });
+
+        match self.lfclk.state {
+            CalibrationState::Waiting => {}
+            CalibrationState::StartingHfxo => {
+                self.periph.tasks_hfclkstop.write(|w| unsafe { w.bits(1) });
+                self.periph.events_hfclkstarted.write(|w| unsafe { w.bits(0) });
+            }
+            CalibrationState::Calibrating { .. } => {
+                while self.periph.events_done.read().bits() != 1 {}
+                self.periph.events_done.write(|w| unsafe { w.bits(0) });
+                self.finish_calibration();
+            }
+        }
+
+        Clocks {
+            hfclk: self.hfclk,
+            lfclk: Internal,
+            lfstat: self.lfstat,
+            periph: self.periph,
+        }
+    }
+
+    /// Is the external crystal oscillator running, for any reason?
+    fn hfxo_running(&self) -> bool {
+        let stat = self.periph.hfclkstat.read();
+        stat.src().is_xtal() && stat.state().is_running()
+    }
+
+    fn finish_calibration(&mut self) {
+        if self.lfclk.state == (CalibrationState::Calibrating { stop_hfxo: true }) {
+            self.periph.tasks_hfclkstop.write(|w| unsafe { w.bits(1) });
+        }
+        self.lfclk.state = CalibrationState::Waiting;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_intervals() {
+        assert_eq!(calibration_interval(Duration::from_millis(250)), Ok(1));
+        assert_eq!(calibration_interval(Duration::from_secs(4)), Ok(16));
+        assert_eq!(calibration_interval(Duration::from_millis(4_100)), Ok(16));
+        assert_eq!(calibration_interval(Duration::from_millis(31_750)), Ok(127));
+
+        assert_eq!(
+            calibration_interval(Duration::from_millis(249)),
+            Err(Error::CalibrationIntervalOutOfRange)
+        );
+        assert_eq!(
+            calibration_interval(Duration::from_secs(32)),
+            Err(Error::CalibrationIntervalOutOfRange)
+        );
+    }
+}
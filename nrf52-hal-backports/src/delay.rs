@@ -2,86 +2,342 @@
 
 #![allow(dead_code)]
 
+use core::cmp;
+use core::time::Duration;
+
 use cast::u32;
-use nrf52832_pac::SYST;
+use nrf52832_pac::{NVIC, SCB, SYST};
+use cortex_m::asm;
 use cortex_m::peripheral::syst::SystClkSource;
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::timer::CountDown;
 
 use crate::clocks::HFCLK_FREQ;
+use crate::monotonic::duration_to_ticks;
+use crate::rtc::{Rtc, RtcExt, RtcInterrupt, Started, MAX_PERIOD};
+
+/// The operations on the system timer (SysTick) used by `Delay`
+pub trait SysTimer {
+    fn set_clock_source(&mut self, clk_source: SystClkSource);
+    fn set_reload(&mut self, value: u32);
+    fn clear_current(&mut self);
+    fn enable_counter(&mut self);
+    fn disable_counter(&mut self);
+    fn has_wrapped(&mut self) -> bool;
+}
+
+impl SysTimer for SYST {
+    fn set_clock_source(&mut self, clk_source: SystClkSource) {
+        SYST::set_clock_source(self, clk_source)
+    }
+
+    fn set_reload(&mut self, value: u32) {
+        SYST::set_reload(self, value)
+    }
+
+    fn clear_current(&mut self) {
+        SYST::clear_current(self)
+    }
+
+    fn enable_counter(&mut self) {
+        SYST::enable_counter(self)
+    }
+
+    fn disable_counter(&mut self) {
+        SYST::disable_counter(self)
+    }
+
+    fn has_wrapped(&mut self) -> bool {
+        SYST::has_wrapped(self)
+    }
+}
+
+/// The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
+const MAX_RVR: u32 = 0x00FF_FFFF;
+
+/// Core clock cycles per microsecond
+const CYCLES_PER_US: u64 = (HFCLK_FREQ / 1_000_000) as u64;
 
 /// System timer (SysTick) as a delay provider
-pub struct Delay {
-    syst: SYST,
+///
+/// Delays last at least as long as requested. The longest delays of
+/// `DelayMs<u64>` and `DelayUs<u64>` saturate at about 9000 years
+pub struct Delay<S = SYST> {
+    syst: S,
 }
 
-impl Delay {
+impl<S> Delay<S> where S: SysTimer {
     /// Configures the system timer (SysTick) as a delay provider
-    pub fn new(mut syst: SYST) -> Self {
+    pub fn new(mut syst: S) -> Self {
         syst.set_clock_source(SystClkSource::Core);
 
         Delay { syst }
     }
 
     /// Releases the system timer (SysTick) resource
-    pub fn free(self) -> SYST {
+    pub fn free(self) -> S {
         self.syst
     }
+
+    /// Wait for a number of core clock cycles
+    fn delay_cycles(&mut self, mut cycles: u64) {
+        while cycles != 0 {
+            let current_rvr = cmp::min(cycles, u64::from(MAX_RVR)) as u32;
+
+            self.syst.set_reload(current_rvr);
+            self.syst.clear_current();
+            self.syst.enable_counter();
+
+            // Update the tracking variable while we are waiting...
+            cycles -= u64::from(current_rvr);
+
+            while !self.syst.has_wrapped() {}
+
+            self.syst.disable_counter();
+        }
+    }
 }
 
-impl DelayMs<u32> for Delay {
+impl<S> DelayMs<u64> for Delay<S> where S: SysTimer {
+    fn delay_ms(&mut self, ms: u64) {
+        self.delay_us(ms.saturating_mul(1_000));
+    }
+}
+
+impl<S> DelayMs<u32> for Delay<S> where S: SysTimer {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
+        self.delay_ms(u64::from(ms));
     }
 }
 
-impl DelayMs<u16> for Delay {
+impl<S> DelayMs<u16> for Delay<S> where S: SysTimer {
     fn delay_ms(&mut self, ms: u16) {
         self.delay_ms(u32(ms));
     }
 }
 
-impl DelayMs<u8> for Delay {
+impl<S> DelayMs<u8> for Delay<S> where S: SysTimer {
     fn delay_ms(&mut self, ms: u8) {
         self.delay_ms(u32(ms));
     }
 }
 
-impl DelayUs<u32> for Delay {
+impl<S> DelayUs<u64> for Delay<S> where S: SysTimer {
+    fn delay_us(&mut self, us: u64) {
+        self.delay_cycles(us.saturating_mul(CYCLES_PER_US));
+    }
+}
+
+impl<S> DelayUs<u32> for Delay<S> where S: SysTimer {
     fn delay_us(&mut self, us: u32) {
-        // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
-        const MAX_RVR: u32 =  0x00FF_FFFF;
+        self.delay_us(u64::from(us));
+    }
+}
+
+impl<S> DelayUs<u16> for Delay<S> where S: SysTimer {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32(us))
+    }
+}
 
-        let mut total_rvr = us * (HFCLK_FREQ / 1_000_000);
+impl<S> DelayUs<u8> for Delay<S> where S: SysTimer {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32(us))
+    }
+}
 
-        while total_rvr != 0 {
-            let current_rvr = if total_rvr <= MAX_RVR {
-                total_rvr
-            } else {
-                MAX_RVR
-            };
+const SCB_SCR_SEVONPEND: u32 = 0x1 << 4;
 
-            self.syst.set_reload(current_rvr);
-            self.syst.clear_current();
-            self.syst.enable_counter();
+/// Real Time Counter (RTC) as a low power delay provider
+///
+/// The CPU sleeps in `wfe` until the delay has passed, and the HFCLK may be
+/// stopped meanwhile. Compare register 0 of the RTC is used. The RTC interrupt
+/// should not be enabled in the NVIC, it only needs to become pending to
+/// wake the CPU
+pub struct RtcDelay<T> {
+    rtc: Rtc<T, Started>,
+    /// The number of 32.768 kHz periods per tick
+    divider: u32,
+}
 
-            // Update the tracking variable while we are waiting...
-            total_rvr -= current_rvr;
+impl<T> RtcDelay<T> where T: RtcExt {
+    /// Configures a running RTC as a delay provider
+    pub fn new(mut rtc: Rtc<T, Started>, scb: &mut SCB) -> Self {
+        // Wake from `wfe` when an interrupt becomes pending, even a disabled one
+        unsafe { scb.scr.modify(|scr| scr | SCB_SCR_SEVONPEND) };
+        rtc.enable_interrupt(RtcInterrupt::Compare0);
 
-            while !self.syst.has_wrapped() {}
+        let divider = rtc.get_prescaler() + 1;
+        RtcDelay { rtc, divider }
+    }
 
-            self.syst.disable_counter();
+    /// Releases the RTC
+    pub fn free(mut self) -> Rtc<T, Started> {
+        self.rtc.disable_interrupt(RtcInterrupt::Compare0);
+        self.rtc
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        let mut ticks = duration_to_ticks(duration, self.divider);
+
+        while ticks != 0 {
+            let period = cmp::min(ticks, u64::from(MAX_PERIOD));
+            ticks -= period;
+            self.rtc.start(period as u32);
+
+            // Only an interrupt that was not already pending sends an event
+            NVIC::unpend(T::INTERRUPT);
+            while self.rtc.wait().is_err() {
+                asm::wfe();
+                NVIC::unpend(T::INTERRUPT);
+            }
         }
     }
 }
 
-impl DelayUs<u16> for Delay {
+impl<T> DelayMs<u64> for RtcDelay<T> where T: RtcExt {
+    fn delay_ms(&mut self, ms: u64) {
+        self.sleep(Duration::from_millis(ms));
+    }
+}
+
+impl<T> DelayMs<u32> for RtcDelay<T> where T: RtcExt {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_ms(u64::from(ms));
+    }
+}
+
+impl<T> DelayMs<u16> for RtcDelay<T> where T: RtcExt {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl<T> DelayMs<u8> for RtcDelay<T> where T: RtcExt {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl<T> DelayUs<u64> for RtcDelay<T> where T: RtcExt {
+    fn delay_us(&mut self, us: u64) {
+        self.sleep(Duration::from_micros(us));
+    }
+}
+
+impl<T> DelayUs<u32> for RtcDelay<T> where T: RtcExt {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_us(u64::from(us));
+    }
+}
+
+impl<T> DelayUs<u16> for RtcDelay<T> where T: RtcExt {
     fn delay_us(&mut self, us: u16) {
         self.delay_us(u32(us))
     }
 }
 
-impl DelayUs<u8> for Delay {
+impl<T> DelayUs<u8> for RtcDelay<T> where T: RtcExt {
     fn delay_us(&mut self, us: u8) {
         self.delay_us(u32(us))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A SysTick that wraps after being polled `polls` times
+    #[derive(Default)]
+    struct MockSysTick {
+        polls: u32,
+        reload: u32,
+        enabled: bool,
+        remaining_polls: u32,
+        /// Core clock cycles waited, a reload value of N wraps after N + 1
+        cycles: u64,
+        delays: u64,
+    }
+
+    impl SysTimer for MockSysTick {
+        fn set_clock_source(&mut self, clk_source: SystClkSource) {
+            assert!(matches!(clk_source, SystClkSource::Core));
+        }
+
+        fn set_reload(&mut self, value: u32) {
+            assert!(!self.enabled);
+            assert!((1..=MAX_RVR).contains(&value), "reload {}", value);
+            self.reload = value;
+        }
+
+        fn clear_current(&mut self) {
+            self.remaining_polls = self.polls;
+        }
+
+        fn enable_counter(&mut self) {
+            self.enabled = true;
+        }
+
+        fn disable_counter(&mut self) {
+            self.enabled = false;
+        }
+
+        fn has_wrapped(&mut self) -> bool {
+            assert!(self.enabled);
+            if self.remaining_polls > 0 {
+                self.remaining_polls -= 1;
+                return false;
+            }
+
+            self.cycles += u64::from(self.reload) + 1;
+            self.delays += 1;
+            true
+        }
+    }
+
+    fn waited<F: FnOnce(&mut Delay<MockSysTick>)>(delay: F) -> MockSysTick {
+        let mut syst = Delay::new(MockSysTick {
+            polls: 3,
+            ..MockSysTick::default()
+        });
+        delay(&mut syst);
+
+        let syst = syst.free();
+        assert!(!syst.enabled);
+        syst
+    }
+
+    #[test]
+    fn short_delays() {
+        assert_eq!(waited(|d| d.delay_us(0u32)).cycles, 0);
+        assert_eq!(waited(|d| d.delay_us(1u8)).cycles, 65);
+        assert_eq!(waited(|d| d.delay_us(250u16)).cycles, 16_001);
+        assert_eq!(waited(|d| d.delay_ms(1u8)).cycles, 64_001);
+    }
+
+    #[test]
+    fn no_overflow() {
+        // Used to overflow, above 67 seconds
+        let syst = waited(|d| d.delay_ms(100_000u32));
+        let requested = 100_000 * 64_000;
+        assert!(syst.cycles >= requested);
+        assert!(syst.cycles <= requested + syst.delays);
+
+        let syst = waited(|d| d.delay_us(u32::MAX));
+        let requested = u64::from(u32::MAX) * 64;
+        assert!(syst.cycles >= requested);
+        assert!(syst.cycles <= requested + syst.delays);
+        assert_eq!(syst.delays, requested.div_ceil(u64::from(MAX_RVR)));
+    }
+
+    #[test]
+    fn long_delays() {
+        let syst = waited(|d| d.delay_ms(u64::from(u32::MAX) + 1));
+        let requested = (u64::from(u32::MAX) + 1) * 64_000;
+        assert!(syst.cycles >= requested);
+        assert!(syst.cycles <= requested + syst.delays);
+
+        let syst = waited(|d| d.delay_us(3_000_000_000u64));
+        assert_eq!(syst.cycles, 192_000_000_000 + syst.delays);
+    }
+}
@@ -0,0 +1,93 @@
+//! A simulated RTC, for testing code built on the RTC registers
+
+use core::cell::Cell;
+
+use crate::monotonic::{Counter, COUNTER_BITS};
+use crate::rtc::RtcCompareReg;
+use crate::timers::Compare;
+
+const WRAP: u64 = 1 << COUNTER_BITS;
+
+/// A fake RTC, where every register access takes `step` ticks
+pub struct FakeRtc {
+    pub prescaler: u32,
+    pub step: u64,
+    /// The true number of ticks
+    pub time: Cell<u64>,
+    /// The wrap period when the overflow event was last cleared
+    cleared: u64,
+    /// When the compare event will be set, if its interrupt is enabled
+    compare_at: Option<u64>,
+}
+
+impl FakeRtc {
+    pub fn new(time: u64, step: u64) -> Self {
+        FakeRtc {
+            prescaler: 0,
+            step,
+            time: Cell::new(time),
+            cleared: time >> COUNTER_BITS,
+            compare_at: None,
+        }
+    }
+
+    /// When the next interrupt will be requested
+    pub fn next_interrupt(&self) -> u64 {
+        let overflow = (self.cleared + 1) << COUNTER_BITS;
+        self.compare_at.map_or(overflow, |at| at.min(overflow))
+    }
+
+    fn access(&self) -> u64 {
+        let time = self.time.get();
+        self.time.set(time + self.step);
+        time
+    }
+}
+
+impl Counter for FakeRtc {
+    fn prescaler(&self) -> u32 {
+        self.prescaler
+    }
+
+    fn counter(&self) -> u32 {
+        (self.access() & (WRAP - 1)) as u32
+    }
+
+    fn overflowed(&self) -> bool {
+        self.access() >> COUNTER_BITS > self.cleared
+    }
+
+    fn clear_overflow(&mut self) {
+        self.cleared = self.access() >> COUNTER_BITS;
+    }
+}
+
+impl Compare for FakeRtc {
+    fn arm(&mut self, _reg: RtcCompareReg, value: u32) {
+        // Like the hardware, a compare value of the counter or the counter
+        // plus one may not set the event, so it never does here
+        let earliest = self.access() + 2;
+        let mut at = (earliest & !(WRAP - 1)) | u64::from(value);
+        if at < earliest {
+            at += WRAP;
+        }
+        self.compare_at = Some(at);
+    }
+
+    fn disarm(&mut self, _reg: RtcCompareReg) {
+        self.access();
+        self.compare_at = None;
+    }
+
+    fn take_compare(&mut self, _reg: RtcCompareReg) -> bool {
+        let time = self.access();
+        match self.compare_at {
+            Some(at) if at <= time => {
+                // Matches again once the counter wraps around
+                self.compare_at = Some(at + WRAP * ((time - at) / WRAP + 1));
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Events and tasks of GPIO pins, using the GPIO Tasks and Events (GPIOTE)
+//! peripheral
+//!
+//! Each of the eight channels either generates an IN event on an edge of an
+//! input pin, or drives an output pin from its tasks. Channels keep the high
+//! frequency clock running, so for low power input the PORT event may be used
+//! instead. It is generated when any pin with SENSE enabled is at its sensed
+//! level, using only the low frequency clock.
+
+#![allow(dead_code)]
+
+use nrf52832_pac::{gpiote, GPIOTE, P0};
+
+use crate::ppi::{Event, Pool, Task};
+
+/// The number of GPIOTE channels
+pub const CHANNELS: u8 = 8;
+
+/// The number of pins of the GPIO port
+const PINS: u8 = 32;
+
+/// The bit of the PORT event in INTENSET and INTENCLR
+const PORT_INTERRUPT: u32 = 1 << 31;
+
+/// Edges of an input pin generating the IN event
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Toggle,
+}
+
+/// What the OUT task does to an output pin
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutTask {
+    Set,
+    Clear,
+    Toggle,
+}
+
+/// Logic levels of a pin
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+    Low,
+    High,
+}
+
+/// The level of a pin generating the PORT event
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Sense {
+    Disabled,
+    High,
+    Low,
+}
+
+/// Error types associated with the GPIOTE peripheral interface
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    NoFreeChannels,
+    PinOutOfRange,
+}
+
+fn registers() -> &'static gpiote::RegisterBlock {
+    // Only the registers of an owned channel, or of the PORT event owned by
+    // `Gpiote`, are written
+    unsafe { &*GPIOTE::ptr() }
+}
+
+/// A channel generating events on edges of an input pin
+#[derive(Debug)]
+pub struct InputChannel {
+    index: u8,
+    pin: u8,
+}
+
+impl InputChannel {
+    /// The number of the channel
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The input pin
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// The IN event of the channel, such as for a PPI channel
+    pub fn event(&self) -> Event {
+        Event::from_register(&registers().events_in[usize::from(self.index)])
+    }
+
+    /// Has an edge been detected since the event was last cleared?
+    pub fn is_triggered(&self) -> bool {
+        registers().events_in[usize::from(self.index)].read().bits() == 1
+    }
+
+    /// Clear the IN event
+    pub fn clear(&mut self) {
+        registers().events_in[usize::from(self.index)].write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Request the GPIOTE interrupt on edges
+    pub fn enable_interrupt(&mut self) {
+        registers().intenset.write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Stop requesting the GPIOTE interrupt
+    pub fn disable_interrupt(&mut self) {
+        registers().intenclr.write(|w| unsafe { w.bits(1 << self.index) });
+    }
+}
+
+/// A channel driving an output pin from its tasks
+#[derive(Debug)]
+pub struct OutputChannel {
+    index: u8,
+    pin: u8,
+}
+
+impl OutputChannel {
+    /// The number of the channel
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The output pin
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// The OUT task, doing what the channel was configured with
+    pub fn out_task(&self) -> Task {
+        Task::from_register(&registers().tasks_out[usize::from(self.index)])
+    }
+
+    /// The SET task, driving the pin high
+    pub fn set_task(&self) -> Task {
+        Task::from_register(&registers().tasks_set[usize::from(self.index)])
+    }
+
+    /// The CLR task, driving the pin low
+    pub fn clear_task(&self) -> Task {
+        Task::from_register(&registers().tasks_clr[usize::from(self.index)])
+    }
+
+    /// Trigger the OUT task
+    pub fn out(&mut self) {
+        registers().tasks_out[usize::from(self.index)].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Drive the pin high
+    pub fn set_high(&mut self) {
+        registers().tasks_set[usize::from(self.index)].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Drive the pin low
+    pub fn set_low(&mut self) {
+        registers().tasks_clr[usize::from(self.index)].write(|w| unsafe { w.bits(1) });
+    }
+}
+
+/// An extension trait for constructing the high level interface
+pub trait GpioteExt {
+    fn constrain(self) -> Gpiote;
+}
+
+impl GpioteExt for GPIOTE {
+    fn constrain(self) -> Gpiote {
+        Gpiote {
+            periph: self,
+            channels: Pool::new(CHANNELS),
+        }
+    }
+}
+
+/// A high level interface to the GPIOTE peripheral, allocating its channels
+/// and handling the PORT event
+pub struct Gpiote {
+    periph: GPIOTE,
+    channels: Pool,
+}
+
+impl Gpiote {
+    /// Generate the IN event on edges of an input pin. The pin must be
+    /// configured as an input, and may not be used by another channel
+    pub fn input_channel(&mut self, pin: u8, edge: Edge) -> Result<InputChannel, Error> {
+        if pin >= PINS {
+            return Err(Error::PinOutOfRange);
+        }
+        let index = self.channels.take().ok_or(Error::NoFreeChannels)?;
+
+        self.periph.config[usize::from(index)].write(|w| {
+            let w = match edge {
+                Edge::Rising => w.polarity().lo_to_hi(),
+                Edge::Falling => w.polarity().hi_to_lo(),
+                Edge::Toggle => w.polarity().toggle(),
+            };
+            unsafe { w.psel().bits(pin) }.mode().event()
+        });
+
+        let mut channel = InputChannel { index, pin };
+        channel.clear();
+        Ok(channel)
+    }
+
+    /// Drive an output pin from the tasks of a channel, starting at `initial`.
+    /// The pin may not be used by another channel
+    pub fn output_channel(
+        &mut self,
+        pin: u8,
+        task: OutTask,
+        initial: Level,
+    ) -> Result<OutputChannel, Error> {
+        if pin >= PINS {
+            return Err(Error::PinOutOfRange);
+        }
+        let index = self.channels.take().ok_or(Error::NoFreeChannels)?;
+
+        self.periph.config[usize::from(index)].write(|w| {
+            let w = match task {
+                OutTask::Set => w.polarity().lo_to_hi(),
+                OutTask::Clear => w.polarity().hi_to_lo(),
+                OutTask::Toggle => w.polarity().toggle(),
+            };
+            let w = match initial {
+                Level::Low => w.outinit().low(),
+                Level::High => w.outinit().high(),
+            };
+            unsafe { w.psel().bits(pin) }.mode().task()
+        });
+
+        Ok(OutputChannel { index, pin })
+    }
+
+    /// Disable an input channel and return it to the free channels
+    pub fn free_input(&mut self, mut channel: InputChannel) {
+        channel.disable_interrupt();
+        self.periph.config[usize::from(channel.index)].reset();
+        channel.clear();
+        self.channels.give(channel.index);
+    }
+
+    /// Disable an output channel and return it to the free channels. The pin
+    /// is driven by the GPIO port again
+    pub fn free_output(&mut self, channel: OutputChannel) {
+        self.periph.config[usize::from(channel.index)].reset();
+        self.channels.give(channel.index);
+    }
+
+    /// Set the level of a pin of `port` that generates the PORT event. Only
+    /// the SENSE field of the pin configuration is changed, the pin must be
+    /// configured as an input with its buffer connected
+    ///
+    /// The event is generated when the first sensed pin reaches its level. To
+    /// see the pin change back, sense the opposite level after the event
+    pub fn set_sense(&mut self, port: &mut P0, pin: u8, sense: Sense) -> Result<(), Error> {
+        if pin >= PINS {
+            return Err(Error::PinOutOfRange);
+        }
+
+        port.pin_cnf[usize::from(pin)].modify(|_, w| match sense {
+            Sense::Disabled => w.sense().disabled(),
+            Sense::High => w.sense().high(),
+            Sense::Low => w.sense().low(),
+        });
+
+        Ok(())
+    }
+
+    /// The PORT event, such as for a PPI channel
+    pub fn port_event(&self) -> Event {
+        Event::from_register(&self.periph.events_port)
+    }
+
+    /// Has a sensed pin reached its level since the event was last cleared?
+    pub fn is_port_triggered(&self) -> bool {
+        self.periph.events_port.read().bits() == 1
+    }
+
+    /// Clear the PORT event
+    pub fn clear_port(&mut self) {
+        self.periph.events_port.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Request the GPIOTE interrupt on the PORT event
+    pub fn enable_port_interrupt(&mut self) {
+        self.periph.intenset.write(|w| unsafe { w.bits(PORT_INTERRUPT) });
+    }
+
+    /// Stop requesting the GPIOTE interrupt on the PORT event
+    pub fn disable_port_interrupt(&mut self) {
+        self.periph.intenclr.write(|w| unsafe { w.bits(PORT_INTERRUPT) });
+    }
+
+    /// Destructure the high level interface. Does not reset any configuration
+    /// made to the GPIOTE peripheral
+    pub fn release(self) -> GPIOTE {
+        self.periph
+    }
+}
@@ -1,4 +1,15 @@
 #![no_std]
+pub mod button;
 pub mod clocks;
 pub mod delay;
+pub mod gpiote;
+pub mod monotonic;
+pub mod ppi;
+pub mod pwm;
 pub mod rtc;
+pub mod temp;
+pub mod timers;
+pub mod wdt;
+
+#[cfg(test)]
+mod fake;
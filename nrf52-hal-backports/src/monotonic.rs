@@ -0,0 +1,336 @@
+//! A 64-bit monotonic timebase on top of the 24-bit RTC counter
+//!
+//! The RTC counter wraps around every 512 seconds when running at the full
+//! 32.768 kHz. `Monotonic` counts the overflows in the RTC interrupt, and
+//! combines the count with the counter to give an `Instant` that will not
+//! wrap around for millions of years.
+//!
+//! An overflow may happen after the interrupt handler last ran, such as while
+//! interrupts are masked. This is detected using the pending overflow event,
+//! so `now` never goes backwards, as long as the interrupt is handled within
+//! half a wrap period.
+
+use core::ops::{Add, Sub};
+use core::time::Duration;
+
+use crate::clocks::LFCLK_FREQ;
+use crate::rtc::{Rtc, RtcExt, RtcInterrupt, Started};
+
+/// The number of bits of the RTC counter
+pub const COUNTER_BITS: u32 = 24;
+
+/// Counter values below this were read after a pending overflow
+const HALF_RANGE: u32 = 1 << (COUNTER_BITS - 1);
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The number of ticks needed for at least `duration` to pass, at a tick rate
+/// of `32_768 / divider`, saturating at `u64::MAX`
+pub(crate) fn duration_to_ticks(duration: Duration, divider: u32) -> u64 {
+    let unit = NANOS_PER_SEC * u128::from(divider);
+    // Can't overflow, this is at most about 2^110
+    let scaled = duration.as_nanos() * u128::from(LFCLK_FREQ);
+    let ticks = scaled.div_ceil(unit);
+
+    if ticks > u128::from(u64::MAX) {
+        u64::MAX
+    } else {
+        ticks as u64
+    }
+}
+
+/// The registers of a running RTC used by `Monotonic`
+pub trait Counter {
+    /// The prescaler of the counter. fRTC = 32_768 / (`prescaler` + 1)
+    fn prescaler(&self) -> u32;
+
+    /// The current value of the 24-bit counter
+    fn counter(&self) -> u32;
+
+    /// Is the overflow event set?
+    fn overflowed(&self) -> bool;
+
+    /// Clear the overflow event
+    fn clear_overflow(&mut self);
+}
+
+impl<T> Counter for Rtc<T, Started> where T: RtcExt {
+    fn prescaler(&self) -> u32 {
+        self.get_prescaler()
+    }
+
+    fn counter(&self) -> u32 {
+        self.get_counter()
+    }
+
+    fn overflowed(&self) -> bool {
+        self.is_event_triggered(RtcInterrupt::Overflow)
+    }
+
+    fn clear_overflow(&mut self) {
+        self.get_event_triggered(RtcInterrupt::Overflow, true);
+    }
+}
+
+/// A point in time, in ticks of the RTC since the `Monotonic` was created
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, Hash, Default)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    /// The moment the `Monotonic` was created
+    pub const ZERO: Instant = Instant { ticks: 0 };
+
+    /// The instant a number of ticks after `ZERO`
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Instant { ticks }
+    }
+
+    /// The number of ticks since `ZERO`
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The number of ticks since `earlier`, or `None` if `earlier` is later
+    pub fn checked_ticks_since(&self, earlier: Instant) -> Option<u64> {
+        self.ticks.checked_sub(earlier.ticks)
+    }
+
+    /// The instant a number of ticks later, or `None` on overflow
+    pub fn checked_add(&self, ticks: u64) -> Option<Instant> {
+        self.ticks.checked_add(ticks).map(Instant::from_ticks)
+    }
+
+    /// The instant a number of ticks earlier, or `None` if before `ZERO`
+    pub fn checked_sub(&self, ticks: u64) -> Option<Instant> {
+        self.ticks.checked_sub(ticks).map(Instant::from_ticks)
+    }
+}
+
+impl Add<u64> for Instant {
+    type Output = Instant;
+
+    fn add(self, ticks: u64) -> Instant {
+        self.checked_add(ticks)
+            .expect("overflow when adding ticks to instant")
+    }
+}
+
+impl Sub<u64> for Instant {
+    type Output = Instant;
+
+    fn sub(self, ticks: u64) -> Instant {
+        self.checked_sub(ticks)
+            .expect("overflow when subtracting ticks from instant")
+    }
+}
+
+impl Sub for Instant {
+    type Output = u64;
+
+    fn sub(self, earlier: Instant) -> u64 {
+        self.checked_ticks_since(earlier)
+            .expect("earlier instant is later than self")
+    }
+}
+
+/// A monotonic clock extending a 24-bit RTC counter to 64 bits
+///
+/// The overflow interrupt of the RTC must be enabled, and `on_overflow`
+/// called from its handler. When shared with the handler, such as an RTFM
+/// resource, `now` must be called with the resource locked
+pub struct Monotonic<C> {
+    counter: C,
+    /// Overflows handled since the creation
+    overflows: u64,
+    /// The number of 32.768 kHz periods per tick
+    divider: u32,
+}
+
+impl<C> Monotonic<C> where C: Counter {
+    /// Extend a running counter. Any pending overflow event is cleared, the
+    /// current counter value is the first `Instant`
+    pub fn new(mut counter: C) -> Self {
+        counter.clear_overflow();
+        let divider = counter.prescaler() + 1;
+
+        Monotonic {
+            counter,
+            overflows: 0,
+            divider,
+        }
+    }
+
+    /// The current instant
+    pub fn now(&self) -> Instant {
+        // The counter must be read before the event. An overflow between the
+        // two reads then leaves a value near the end of the range, while one
+        // before the first read leaves a value near the start
+        let counter = self.counter.counter();
+        let mut overflows = self.overflows;
+        if self.counter.overflowed() && counter < HALF_RANGE {
+            overflows += 1;
+        }
+
+        Instant::from_ticks((overflows << COUNTER_BITS) | u64::from(counter))
+    }
+
+    /// Handle the overflow event, to be called from the RTC interrupt handler.
+    /// Returns whether the counter had overflowed
+    pub fn on_overflow(&mut self) -> bool {
+        if !self.counter.overflowed() {
+            return false;
+        }
+
+        self.counter.clear_overflow();
+        self.overflows += 1;
+        true
+    }
+
+    /// The number of ticks per second is `32_768 / divider`
+    pub fn divider(&self) -> u32 {
+        self.divider
+    }
+
+    /// The time taken by a number of ticks, rounded down to whole nanoseconds
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        // Can't overflow, this is at most 2^76
+        let periods = u128::from(ticks) * u128::from(self.divider);
+        let freq = u128::from(LFCLK_FREQ);
+        let nanos = (periods % freq) * NANOS_PER_SEC / freq;
+
+        // The seconds fit, they are at most 2^61
+        Duration::new((periods / freq) as u64, nanos as u32)
+    }
+
+    /// The number of ticks needed for at least `duration` to pass, saturating
+    /// at `u64::MAX`
+    pub fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        duration_to_ticks(duration, self.divider)
+    }
+
+    /// The time since the `Monotonic` was created, at `instant`
+    pub fn to_duration(&self, instant: Instant) -> Duration {
+        self.ticks_to_duration(instant.ticks())
+    }
+
+    /// The time passed since `earlier`, or zero if it is in the future
+    pub fn elapsed(&self, earlier: Instant) -> Duration {
+        let ticks = self.now().checked_ticks_since(earlier).unwrap_or(0);
+        self.ticks_to_duration(ticks)
+    }
+
+    /// Borrow the counter, such as to configure further interrupts
+    pub fn counter(&mut self) -> &mut C {
+        &mut self.counter
+    }
+
+    /// Release the counter
+    pub fn free(self) -> C {
+        self.counter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeRtc;
+
+    const WRAP: u64 = 1 << COUNTER_BITS;
+
+    #[test]
+    fn pending_overflow() {
+        let mut mono = Monotonic::new(FakeRtc::new(WRAP - 2, 0));
+        assert_eq!(mono.now().ticks(), WRAP - 2);
+
+        // Overflowed, but the interrupt has not been handled yet
+        mono.counter().time.set(WRAP + 5);
+        assert_eq!(mono.now().ticks(), WRAP + 5);
+        assert!(mono.on_overflow());
+        assert!(!mono.on_overflow());
+        assert_eq!(mono.now().ticks(), WRAP + 5);
+
+        // Handled too late, a whole wrap period is lost
+        mono.counter().time.set(3 * WRAP + 7);
+        assert_eq!(mono.now().ticks(), 2 * WRAP + 7);
+        assert!(mono.on_overflow());
+        assert_eq!(mono.now().ticks(), 2 * WRAP + 7);
+    }
+
+    #[test]
+    fn overflow_between_reads() {
+        // Every read takes a tick, so the counter is read before the wrap,
+        // and the event after it
+        for start in WRAP - 4..=WRAP {
+            let mono = Monotonic::new(FakeRtc::new(start - 1, 1));
+            let before = mono.counter.time.get();
+            let now = mono.now().ticks();
+            let after = mono.counter.time.get();
+            assert!(before <= now && now < after, "{} {} {}", before, now, after);
+        }
+    }
+
+    #[test]
+    fn never_goes_backwards() {
+        // Handle the interrupt late, at varying times after each overflow
+        for &step in &[1, 3, 1_000, 65_537] {
+            let mut mono = Monotonic::new(FakeRtc::new(WRAP / 3, step));
+            let mut previous = mono.now();
+            let mut handled = 0;
+
+            while mono.counter.time.get() < 5 * WRAP {
+                let before = mono.counter.time.get();
+                let now = mono.now();
+                let after = mono.counter.time.get();
+                assert!(previous <= now);
+                assert!(before <= now.ticks() && now.ticks() < after);
+                previous = now;
+
+                handled += 1;
+                if handled % 7 == 0 {
+                    mono.on_overflow();
+                }
+                let time = mono.counter.time.get();
+                mono.counter.time.set(time + WRAP / 64 + step);
+            }
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let mono = Monotonic::new(FakeRtc::new(0, 0));
+        assert_eq!(mono.ticks_to_duration(32_768), Duration::from_secs(1));
+        assert_eq!(mono.ticks_to_duration(3), Duration::from_nanos(91_552));
+        assert_eq!(mono.duration_to_ticks(Duration::from_nanos(91_552)), 3);
+        assert_eq!(mono.duration_to_ticks(Duration::from_nanos(91_553)), 4);
+        assert_eq!(mono.duration_to_ticks(Duration::from_millis(250)), 8_192);
+        assert_eq!(
+            mono.ticks_to_duration(u64::MAX),
+            Duration::new(u64::MAX >> 15, 999_969_482)
+        );
+        assert_eq!(mono.duration_to_ticks(Duration::new(u64::MAX, 0)), u64::MAX);
+
+        let mut rtc = FakeRtc::new(0, 0);
+        rtc.prescaler = 0xFFF;
+        let mono = Monotonic::new(rtc);
+        assert_eq!(mono.divider(), 4096);
+        assert_eq!(mono.ticks_to_duration(3), Duration::from_millis(375));
+        assert_eq!(mono.duration_to_ticks(Duration::from_millis(126)), 2);
+        assert_eq!(
+            mono.to_duration(Instant::from_ticks(1 << 40)),
+            Duration::from_secs(1 << 37)
+        );
+    }
+
+    #[test]
+    fn instants() {
+        let instant = Instant::from_ticks(10);
+        assert_eq!(instant + 5, Instant::from_ticks(15));
+        assert_eq!(instant - 10, Instant::ZERO);
+        assert_eq!(Instant::from_ticks(15) - instant, 5);
+        assert_eq!(Instant::ZERO.checked_ticks_since(instant), None);
+        assert_eq!(Instant::ZERO.checked_sub(1), None);
+        assert_eq!(Instant::from_ticks(u64::MAX).checked_add(1), None);
+    }
+}
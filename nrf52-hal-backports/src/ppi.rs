@@ -0,0 +1,367 @@
+//! Connecting peripheral events to tasks, using the Programmable Peripheral
+//! Interconnect (PPI)
+//!
+//! A PPI channel triggers a task of a peripheral whenever an event of a
+//! peripheral is generated, without any CPU involvement. Channels are
+//! allocated from `Ppi`, and may be enabled and disabled together as part of
+//! a channel group.
+
+#![allow(dead_code)]
+
+use core::ops::Deref;
+
+use nrf52832_pac::{ppi, timer0, GPIOTE, PPI};
+
+/// The number of programmable channels
+pub const CHANNELS: u8 = 20;
+/// The number of channel groups
+pub const GROUPS: u8 = 6;
+
+/// An event of a peripheral, that may trigger a task through a channel
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Event {
+    address: u32,
+}
+
+/// A task of a peripheral, that may be triggered by an event through a channel
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Task {
+    address: u32,
+}
+
+impl Event {
+    /// An event at the address of its EVENTS register
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the address of an EVENTS register of a peripheral
+    pub unsafe fn from_address(address: u32) -> Self {
+        Event { address }
+    }
+
+    /// An event at the address of a register of a peripheral
+    pub(crate) fn from_register<R>(register: &R) -> Self {
+        Event {
+            address: register as *const R as usize as u32,
+        }
+    }
+
+    /// The address of the EVENTS register
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// The COMPARE[n] event of a TIMER peripheral
+    pub fn timer_compare<T>(timer: &T, n: usize) -> Self
+    where
+        T: Deref<Target = timer0::RegisterBlock>,
+    {
+        Event::from_register(&timer.events_compare[n])
+    }
+
+    /// The IN[n] event of the GPIOTE peripheral
+    pub fn gpiote_in(gpiote: &GPIOTE, n: usize) -> Self {
+        Event::from_register(&gpiote.events_in[n])
+    }
+}
+
+impl Task {
+    /// A task at the address of its TASKS register
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the address of a TASKS register of a peripheral
+    pub unsafe fn from_address(address: u32) -> Self {
+        Task { address }
+    }
+
+    /// A task at the address of a register of a peripheral
+    pub(crate) fn from_register<R>(register: &R) -> Self {
+        Task {
+            address: register as *const R as usize as u32,
+        }
+    }
+
+    /// The address of the TASKS register
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// The START task of a TIMER peripheral
+    pub fn timer_start<T>(timer: &T) -> Self
+    where
+        T: Deref<Target = timer0::RegisterBlock>,
+    {
+        Task::from_register(&timer.tasks_start)
+    }
+
+    /// The STOP task of a TIMER peripheral
+    pub fn timer_stop<T>(timer: &T) -> Self
+    where
+        T: Deref<Target = timer0::RegisterBlock>,
+    {
+        Task::from_register(&timer.tasks_stop)
+    }
+
+    /// The CLEAR task of a TIMER peripheral
+    pub fn timer_clear<T>(timer: &T) -> Self
+    where
+        T: Deref<Target = timer0::RegisterBlock>,
+    {
+        Task::from_register(&timer.tasks_clear)
+    }
+
+    /// The CAPTURE[n] task of a TIMER peripheral
+    pub fn timer_capture<T>(timer: &T, n: usize) -> Self
+    where
+        T: Deref<Target = timer0::RegisterBlock>,
+    {
+        Task::from_register(&timer.tasks_capture[n])
+    }
+
+    /// The OUT[n] task of the GPIOTE peripheral, toggling or setting the pin
+    /// as configured
+    pub fn gpiote_out(gpiote: &GPIOTE, n: usize) -> Self {
+        Task::from_register(&gpiote.tasks_out[n])
+    }
+
+    /// The SET[n] task of the GPIOTE peripheral
+    pub fn gpiote_set(gpiote: &GPIOTE, n: usize) -> Self {
+        Task::from_register(&gpiote.tasks_set[n])
+    }
+
+    /// The CLR[n] task of the GPIOTE peripheral
+    pub fn gpiote_clr(gpiote: &GPIOTE, n: usize) -> Self {
+        Task::from_register(&gpiote.tasks_clr[n])
+    }
+}
+
+/// A set of free channels or groups
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Pool {
+    free: u32,
+}
+
+impl Pool {
+    pub(crate) fn new(size: u8) -> Self {
+        Pool {
+            free: (1 << size) - 1,
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Option<u8> {
+        if self.free == 0 {
+            return None;
+        }
+
+        let index = self.free.trailing_zeros();
+        self.free &= !(1 << index);
+        Some(index as u8)
+    }
+
+    pub(crate) fn give(&mut self, index: u8) {
+        self.free |= 1 << index;
+    }
+}
+
+fn registers() -> &'static ppi::RegisterBlock {
+    // Only the registers of an owned channel or group are written
+    unsafe { &*PPI::ptr() }
+}
+
+/// An allocated PPI channel
+#[derive(Debug)]
+pub struct Channel {
+    index: u8,
+}
+
+impl Channel {
+    /// The number of the channel
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Trigger `task` whenever `event` is generated, once the channel is
+    /// enabled
+    pub fn connect(&mut self, event: &Event, task: &Task) {
+        let ch = &registers().ch[usize::from(self.index)];
+        ch.eep.write(|w| unsafe { w.bits(event.address) });
+        ch.tep.write(|w| unsafe { w.bits(task.address) });
+    }
+
+    /// Trigger a second task whenever the event is generated
+    pub fn fork(&mut self, task: &Task) {
+        registers().fork[usize::from(self.index)]
+            .tep
+            .write(|w| unsafe { w.bits(task.address) });
+    }
+
+    /// Remove the second task
+    pub fn clear_fork(&mut self) {
+        registers().fork[usize::from(self.index)]
+            .tep
+            .write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Start triggering the task
+    pub fn enable(&mut self) {
+        registers()
+            .chenset
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Stop triggering the task
+    pub fn disable(&mut self) {
+        registers()
+            .chenclr
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Is the channel enabled, directly or by its group?
+    pub fn is_enabled(&self) -> bool {
+        registers().chen.read().bits() & (1 << self.index) != 0
+    }
+}
+
+/// An allocated channel group, for enabling and disabling many channels at
+/// once
+#[derive(Debug)]
+pub struct Group {
+    index: u8,
+}
+
+impl Group {
+    /// The number of the group
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Add a channel to the group
+    pub fn include(&mut self, channel: &Channel) {
+        registers().chg[usize::from(self.index)]
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << channel.index)) });
+    }
+
+    /// Remove a channel from the group
+    pub fn exclude(&mut self, channel: &Channel) {
+        registers().chg[usize::from(self.index)]
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << channel.index)) });
+    }
+
+    /// Enable all channels of the group
+    pub fn enable(&mut self) {
+        registers().tasks_chg[usize::from(self.index)]
+            .en
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Disable all channels of the group
+    pub fn disable(&mut self) {
+        registers().tasks_chg[usize::from(self.index)]
+            .dis
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    /// The task enabling all channels of the group, such as from another
+    /// channel
+    pub fn enable_task(&self) -> Task {
+        Task::from_register(&registers().tasks_chg[usize::from(self.index)].en)
+    }
+
+    /// The task disabling all channels of the group
+    pub fn disable_task(&self) -> Task {
+        Task::from_register(&registers().tasks_chg[usize::from(self.index)].dis)
+    }
+}
+
+/// An extension trait for constructing the high level interface
+pub trait PpiExt {
+    fn constrain(self) -> Ppi;
+}
+
+impl PpiExt for PPI {
+    fn constrain(self) -> Ppi {
+        Ppi {
+            periph: self,
+            channels: Pool::new(CHANNELS),
+            groups: Pool::new(GROUPS),
+        }
+    }
+}
+
+/// A high level interface to the PPI peripheral, allocating its channels and
+/// channel groups
+pub struct Ppi {
+    periph: PPI,
+    channels: Pool,
+    groups: Pool,
+}
+
+impl Ppi {
+    /// Allocate a channel, if any are free. The channel is disabled, and not
+    /// connected to anything
+    pub fn alloc_channel(&mut self) -> Option<Channel> {
+        let index = self.channels.take()?;
+        let mut channel = Channel { index };
+
+        channel.disable();
+        self.periph.ch[usize::from(index)].eep.write(|w| unsafe { w.bits(0) });
+        self.periph.ch[usize::from(index)].tep.write(|w| unsafe { w.bits(0) });
+        channel.clear_fork();
+
+        Some(channel)
+    }
+
+    /// Disable a channel and return it to the free channels. The channel is
+    /// removed from all groups, so they can't enable it once reallocated
+    pub fn free_channel(&mut self, mut channel: Channel) {
+        channel.disable();
+        for group in self.periph.chg.iter() {
+            group.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << channel.index)) });
+        }
+        self.channels.give(channel.index);
+    }
+
+    /// Allocate a channel group, if any are free. The group is empty
+    pub fn alloc_group(&mut self) -> Option<Group> {
+        let index = self.groups.take()?;
+        self.periph.chg[usize::from(index)].write(|w| unsafe { w.bits(0) });
+
+        Some(Group { index })
+    }
+
+    /// Return a channel group to the free groups. Its channels are not
+    /// disabled
+    pub fn free_group(&mut self, group: Group) {
+        self.groups.give(group.index);
+    }
+
+    /// Destructure the high level interface. Does not reset any configuration
+    /// made to the PPI peripheral
+    pub fn release(self) -> PPI {
+        self.periph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool() {
+        let mut pool = Pool::new(CHANNELS);
+        for index in 0..CHANNELS {
+            assert_eq!(pool.take(), Some(index));
+        }
+        assert_eq!(pool.take(), None);
+
+        pool.give(7);
+        pool.give(3);
+        assert_eq!(pool.take(), Some(3));
+        assert_eq!(pool.take(), Some(7));
+        assert_eq!(pool.take(), None);
+
+        let groups = Pool::new(GROUPS);
+        assert_eq!(groups.free, 0b11_1111);
+    }
+}
@@ -0,0 +1,674 @@
+//! A high level interface for PWM peripherals
+//!
+//! The PWM peripheral reads its duty cycles from RAM using EasyDMA, as
+//! sequences of values. `Pwm::set_duty` plays a short sequence setting the
+//! duty cycle of every channel, which is kept until it is changed.
+//! `Pwm::play` plays longer sequences, such as for fading LEDs, and `Melody`
+//! plays notes on a piezo buzzer, without the CPU.
+
+#![allow(dead_code)]
+
+use core::cmp;
+use core::ops::Deref;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use nrf52832_pac::{
+    pwm0,
+    PWM0,
+    PWM1,
+    PWM2,
+};
+
+/// The frequency of the PWM clock before the prescaler (in Hz)
+pub const PWM_CLOCK_FREQ: u32 = 16_000_000;
+
+/// The largest counter top, and so the largest duty cycle
+pub const MAX_COUNTER_TOP: u16 = 0x7FFF;
+const MIN_COUNTER_TOP: u16 = 3;
+
+/// The largest number of values in a sequence
+pub const MAX_SEQUENCE_LEN: usize = 0x7FFF;
+
+/// The largest number of extra PWM periods each value of a sequence is played
+pub const MAX_REFRESH: u32 = 0x00FF_FFFF;
+
+/// Set in a duty cycle value for the output to be high until the counter
+/// reaches the value, and low afterwards
+const FALLING_EDGE: u16 = 0x8000;
+
+/// Rests of a melody are played as silence at this frequency (in Hz)
+const REST_FREQ: u16 = 1_000;
+
+/// Divisions of the 16 MHz PWM clock
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Prescaler {
+    Div1,
+    Div2,
+    Div4,
+    Div8,
+    Div16,
+    Div32,
+    Div64,
+    Div128,
+}
+
+impl Prescaler {
+    const ALL: [Prescaler; 8] = [
+        Prescaler::Div1,
+        Prescaler::Div2,
+        Prescaler::Div4,
+        Prescaler::Div8,
+        Prescaler::Div16,
+        Prescaler::Div32,
+        Prescaler::Div64,
+        Prescaler::Div128,
+    ];
+
+    /// The frequency of the PWM counter (in Hz)
+    pub fn counter_freq(self) -> u32 {
+        PWM_CLOCK_FREQ >> (self as u32)
+    }
+}
+
+/// How the counter counts towards the counter top
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CounterMode {
+    /// Edge aligned PWM, a period is `max_duty` counts
+    Up,
+    /// Center aligned PWM, a period is twice `max_duty` counts
+    UpAndDown,
+}
+
+/// How the values of a sequence are loaded into the channels
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LoadMode {
+    /// Each value is used by all four channels
+    Common,
+    /// Each pair of values is used by channels 0 and 1, and 2 and 3
+    Grouped,
+    /// Each four values are used by channels 0 to 3
+    Individual,
+    /// Each four values are used by channels 0 to 2, and the counter top
+    WaveForm,
+}
+
+/// Output channels of the PWMn
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PwmChannel {
+    Channel0,
+    Channel1,
+    Channel2,
+    Channel3,
+}
+
+/// How often a sequence is played
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repeat {
+    /// Sequence 0, followed by sequence 1 if any
+    Once,
+    /// Sequence 0 followed by sequence 1, a number of times. Without a
+    /// sequence 1, sequence 0 is played twice each time
+    Times(u16),
+    /// Sequence 0 followed by sequence 1, until stopped
+    Forever,
+}
+
+/// Error types associated with the PWM peripheral interface
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    CounterTopOutOfRange,
+    FrequencyOutOfRange,
+    PinOutOfRange,
+    EmptySequence,
+    SequenceTooLong,
+}
+
+/// The prescaler and counter top of edge aligned PWM closest to a frequency,
+/// with the best duty cycle resolution
+fn frequency_config(freq_hz: u32, mode: CounterMode) -> Option<(Prescaler, u16)> {
+    let counts_per_period = match mode {
+        CounterMode::Up => 1,
+        CounterMode::UpAndDown => 2,
+    };
+    let freq = freq_hz.checked_mul(counts_per_period).filter(|&freq| freq != 0)?;
+
+    Prescaler::ALL.iter().find_map(|&prescaler| {
+        let counter_freq = prescaler.counter_freq();
+        let top = (counter_freq + freq / 2) / freq;
+
+        if top > u32::from(MAX_COUNTER_TOP) {
+            None
+        } else if top < u32::from(MIN_COUNTER_TOP) {
+            // Only gets worse with more division
+            Some(None)
+        } else {
+            Some(Some((prescaler, top as u16)))
+        }
+    })?
+}
+
+/// The number of PWM periods a note lasts, at least one
+fn note_periods(freq_hz: u16, duration_ms: u16) -> u32 {
+    let periods = (u32::from(duration_ms) * u32::from(freq_hz) + 500) / 1_000;
+    cmp::max(periods, 1)
+}
+
+/// An opaque high level interface to a PWM peripheral
+pub struct Pwm<T> {
+    periph: T,
+    /// The values played by `set_duty`, read by EasyDMA
+    duty: [u16; 4],
+    mode: CounterMode,
+    load: LoadMode,
+}
+
+/// An extension trait for constructing the high level interface
+pub trait PwmExt : Deref<Target=pwm0::RegisterBlock> + Sized {
+    fn constrain(self) -> Pwm<Self>;
+}
+
+macro_rules! impl_pwm_ext {
+    ($($pwm:ty,)*) => {
+        $(
+            impl PwmExt for $pwm {
+                fn constrain(self) -> Pwm<$pwm> {
+                    self.enable.write(|w| w.enable().enabled());
+                    self.mode.write(|w| w.updown().up());
+
+                    Pwm {
+                        periph: self,
+                        duty: [FALLING_EDGE; 4],
+                        mode: CounterMode::Up,
+                        load: LoadMode::Common,
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_pwm_ext!(
+    PWM0,
+    PWM1,
+    PWM2,
+);
+
+fn channel_index(channel: PwmChannel) -> usize {
+    match channel {
+        PwmChannel::Channel0 => 0,
+        PwmChannel::Channel1 => 1,
+        PwmChannel::Channel2 => 2,
+        PwmChannel::Channel3 => 3,
+    }
+}
+
+impl<T> Pwm<T> where T: PwmExt {
+    /// Drive a pin from a channel. The pin must be configured as an output
+    pub fn set_output_pin(&mut self, channel: PwmChannel, pin: u8) -> Result<(), Error> {
+        if pin >= 32 {
+            return Err(Error::PinOutOfRange);
+        }
+
+        self.periph.psel.out[channel_index(channel)].write(|w| {
+            unsafe { w.pin().bits(pin) }
+                .connect().connected()
+        });
+
+        Ok(())
+    }
+
+    /// Stop driving the pin of a channel
+    pub fn disconnect_output_pin(&mut self, channel: PwmChannel) {
+        self.periph.psel.out[channel_index(channel)].write(|w| w.connect().disconnected());
+    }
+
+    /// Set the prescaler of the 16 MHz PWM clock
+    pub fn set_prescaler(&mut self, prescaler: Prescaler) {
+        self.periph.prescaler.write(|w| {
+            let w = w.prescaler();
+            match prescaler {
+                Prescaler::Div1 => w.div_1(),
+                Prescaler::Div2 => w.div_2(),
+                Prescaler::Div4 => w.div_4(),
+                Prescaler::Div8 => w.div_8(),
+                Prescaler::Div16 => w.div_16(),
+                Prescaler::Div32 => w.div_32(),
+                Prescaler::Div64 => w.div_64(),
+                Prescaler::Div128 => w.div_128(),
+            }
+        });
+    }
+
+    /// Set the counter mode, edge or center aligned
+    pub fn set_counter_mode(&mut self, mode: CounterMode) {
+        self.periph.mode.write(|w| match mode {
+            CounterMode::Up => w.updown().up(),
+            CounterMode::UpAndDown => w.updown().up_and_down(),
+        });
+        self.mode = mode;
+    }
+
+    /// Set how the values of sequences are loaded into the channels
+    pub fn set_load_mode(&mut self, load: LoadMode) {
+        self.load = load;
+    }
+
+    /// Set the counter top, the duty cycle of an output that is always high.
+    /// 15 bits of range, at least 3
+    pub fn set_max_duty(&mut self, top: u16) -> Result<(), Error> {
+        if !(MIN_COUNTER_TOP..=MAX_COUNTER_TOP).contains(&top) {
+            return Err(Error::CounterTopOutOfRange);
+        }
+
+        unsafe { self.periph.countertop.write(|w| w.countertop().bits(top)) };
+
+        Ok(())
+    }
+
+    /// The counter top, the duty cycle of an output that is always high
+    pub fn max_duty(&self) -> u16 {
+        self.periph.countertop.read().countertop().bits()
+    }
+
+    /// Set the prescaler and counter top for a PWM frequency, with the best
+    /// duty cycle resolution available
+    pub fn set_frequency(&mut self, freq_hz: u32) -> Result<(), Error> {
+        let (prescaler, top) =
+            frequency_config(freq_hz, self.mode).ok_or(Error::FrequencyOutOfRange)?;
+
+        self.set_prescaler(prescaler);
+        self.set_max_duty(top)
+    }
+
+    /// The duty cycle of a channel, set with `set_duty`
+    pub fn duty(&self, channel: PwmChannel) -> u16 {
+        self.duty[channel_index(channel)] & !FALLING_EDGE
+    }
+
+    /// Set the duty cycle of a channel, the time the output is high in counts
+    /// of the PWM counter. Limited to `max_duty`. Blocks until the new duty
+    /// cycle is loaded, at the start of the next PWM period
+    pub fn set_duty(&mut self, channel: PwmChannel, duty: u16) {
+        let duty = cmp::min(duty, self.max_duty());
+        self.duty[channel_index(channel)] = duty | FALLING_EDGE;
+
+        self.periph.shorts.reset();
+        self.periph.decoder.write(|w| w.load().individual().mode().refresh_count());
+        self.periph.loop_.write(|w| w.cnt().disabled());
+
+        let seq = &self.periph.seq0;
+        unsafe {
+            seq.ptr.write(|w| w.ptr().bits(self.duty.as_ptr() as usize as u32));
+            seq.cnt.write(|w| w.cnt().bits(4));
+        }
+        seq.refresh.write(|w| w.cnt().continuous());
+        seq.enddelay.write(|w| unsafe { w.cnt().bits(0) });
+
+        // The values must be in RAM before EasyDMA reads them, and must not
+        // be changed until it has
+        compiler_fence(Ordering::SeqCst);
+        self.periph.events_seqend[0].write(|w| unsafe { w.bits(0) });
+        self.periph.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+
+        while self.periph.events_seqend[0].read().bits() != 1 {}
+        self.periph.events_seqend[0].write(|w| unsafe { w.bits(0) });
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Play sequences of duty cycles, loaded as set by `set_load_mode`
+    pub fn play(self, seq0: Sequence, seq1: Option<Sequence>, repeat: Repeat) -> Playback<T> {
+        let periph = &self.periph;
+        periph.decoder.write(|w| {
+            let w = match self.load {
+                LoadMode::Common => w.load().common(),
+                LoadMode::Grouped => w.load().grouped(),
+                LoadMode::Individual => w.load().individual(),
+                LoadMode::WaveForm => w.load().wave_form(),
+            };
+            w.mode().refresh_count()
+        });
+
+        seq0.configure(&periph.seq0);
+        seq1.as_ref().unwrap_or(&seq0).configure(&periph.seq1);
+
+        match (repeat, seq1.is_some()) {
+            (Repeat::Once, false) => {
+                periph.loop_.write(|w| w.cnt().disabled());
+                periph.shorts.write(|w| w.seqend0_stop().enabled());
+            }
+            (Repeat::Once, true) => {
+                periph.loop_.write(|w| unsafe { w.cnt().bits(1) });
+                periph.shorts.write(|w| w.loopsdone_stop().enabled());
+            }
+            (Repeat::Times(times), _) => {
+                periph.loop_.write(|w| unsafe { w.cnt().bits(cmp::max(times, 1)) });
+                periph.shorts.write(|w| w.loopsdone_stop().enabled());
+            }
+            (Repeat::Forever, _) => {
+                periph.loop_.write(|w| unsafe { w.cnt().bits(1) });
+                periph.shorts.write(|w| w.loopsdone_seqstart0().enabled());
+            }
+        }
+
+        compiler_fence(Ordering::SeqCst);
+        periph.events_stopped.write(|w| unsafe { w.bits(0) });
+        periph.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+
+        Playback {
+            pwm: self,
+            seq0,
+            seq1,
+        }
+    }
+
+    /// Destructure the high level interface, disabling the peripheral
+    pub fn release(self) -> T {
+        self.periph.enable.write(|w| w.enable().disabled());
+        self.periph
+    }
+
+    /// Stop generating PWM, blocking until stopped
+    fn stop(&mut self) {
+        self.periph.tasks_stop.write(|w| unsafe { w.bits(1) });
+        while self.periph.events_stopped.read().bits() != 1 {}
+        self.periph.events_stopped.write(|w| unsafe { w.bits(0) });
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Duty cycle values in RAM, played back by EasyDMA
+///
+/// Each value is the duty cycle in counts of the PWM counter. Set bit 15 for
+/// the output to be high until the counter reaches the value, and low
+/// afterwards, or clear it for the opposite
+pub struct Sequence {
+    values: &'static mut [u16],
+    refresh: u32,
+    end_delay: u32,
+}
+
+impl Sequence {
+    /// A sequence of up to `MAX_SEQUENCE_LEN` values, each played for one
+    /// PWM period
+    pub fn new(values: &'static mut [u16]) -> Result<Self, Error> {
+        if values.is_empty() {
+            return Err(Error::EmptySequence);
+        }
+        if values.len() > MAX_SEQUENCE_LEN {
+            return Err(Error::SequenceTooLong);
+        }
+
+        Ok(Sequence {
+            values,
+            refresh: 0,
+            end_delay: 0,
+        })
+    }
+
+    /// Play each value for this many additional PWM periods, limited to
+    /// `MAX_REFRESH`
+    pub fn set_refresh(&mut self, periods: u32) {
+        self.refresh = cmp::min(periods, MAX_REFRESH);
+    }
+
+    /// Keep the last value for this many additional PWM periods, limited to
+    /// `MAX_REFRESH`
+    pub fn set_end_delay(&mut self, periods: u32) {
+        self.end_delay = cmp::min(periods, MAX_REFRESH);
+    }
+
+    /// The values, to be changed while the sequence is not playing
+    pub fn values(&mut self) -> &mut [u16] {
+        self.values
+    }
+
+    /// Release the values
+    pub fn free(self) -> &'static mut [u16] {
+        self.values
+    }
+
+    fn configure(&self, seq: &pwm0::SEQ) {
+        unsafe {
+            seq.ptr.write(|w| w.ptr().bits(self.values.as_ptr() as usize as u32));
+            seq.cnt.write(|w| w.cnt().bits(self.values.len() as u16));
+            seq.refresh.write(|w| w.cnt().bits(self.refresh));
+            seq.enddelay.write(|w| w.cnt().bits(self.end_delay));
+        }
+    }
+}
+
+/// Sequences being played by a PWM peripheral
+pub struct Playback<T> {
+    pwm: Pwm<T>,
+    seq0: Sequence,
+    seq1: Option<Sequence>,
+}
+
+impl<T> Playback<T> where T: PwmExt {
+    /// Has the playback finished? Never for `Repeat::Forever`
+    pub fn is_done(&self) -> bool {
+        self.pwm.periph.events_stopped.read().bits() == 1
+    }
+
+    /// Block until the playback has finished, and release the sequences
+    pub fn wait(self) -> (Pwm<T>, Sequence, Option<Sequence>) {
+        while !self.is_done() {}
+        self.pwm.periph.events_stopped.write(|w| unsafe { w.bits(0) });
+        compiler_fence(Ordering::SeqCst);
+
+        (self.pwm, self.seq0, self.seq1)
+    }
+
+    /// Stop the playback, and release the sequences
+    pub fn stop(mut self) -> (Pwm<T>, Sequence, Option<Sequence>) {
+        self.pwm.stop();
+        (self.pwm, self.seq0, self.seq1)
+    }
+}
+
+/// Frequencies of notes (in Hz)
+pub mod pitch {
+    pub const C4: u16 = 262;
+    pub const D4: u16 = 294;
+    pub const E4: u16 = 330;
+    pub const F4: u16 = 349;
+    pub const G4: u16 = 392;
+    pub const A4: u16 = 440;
+    pub const B4: u16 = 494;
+    pub const C5: u16 = 523;
+    pub const D5: u16 = 587;
+    pub const E5: u16 = 659;
+    pub const F5: u16 = 698;
+    pub const G5: u16 = 784;
+    pub const A5: u16 = 880;
+    pub const B5: u16 = 988;
+    pub const C6: u16 = 1047;
+}
+
+/// A note of a melody
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Note {
+    /// The frequency (in Hz), or zero for a rest
+    pub frequency: u16,
+    pub duration_ms: u16,
+}
+
+impl Note {
+    /// A square wave of a frequency (in Hz), see `pitch`
+    pub const fn new(frequency: u16, duration_ms: u16) -> Self {
+        Note {
+            frequency,
+            duration_ms,
+        }
+    }
+
+    /// Silence
+    pub const fn rest(duration_ms: u16) -> Self {
+        Note {
+            frequency: 0,
+            duration_ms,
+        }
+    }
+}
+
+/// Plays melodies on a piezo buzzer, connected to any of the channels
+///
+/// Each note is played by the PWM peripheral on its own. The STOPPED
+/// interrupt is enabled, and the PWMn interrupt handler must call
+/// `on_interrupt` to start the next note
+pub struct Melody<T> {
+    pwm: Pwm<T>,
+    /// The duty cycle of the current note, read by EasyDMA
+    buffer: &'static mut u16,
+    notes: &'static [Note],
+    /// The index of the note after the current one
+    next: usize,
+    playing: bool,
+}
+
+impl<T> Melody<T> where T: PwmExt {
+    /// Play melodies, using a duty cycle value in RAM
+    pub fn new(mut pwm: Pwm<T>, buffer: &'static mut u16) -> Self {
+        pwm.set_counter_mode(CounterMode::Up);
+        pwm.periph.intenset.write(|w| w.stopped().set());
+
+        Melody {
+            pwm,
+            buffer,
+            notes: &[],
+            next: 0,
+            playing: false,
+        }
+    }
+
+    /// Start playing a melody, replacing any melody being played
+    pub fn play(&mut self, notes: &'static [Note]) {
+        self.stop();
+
+        self.notes = notes;
+        self.next = 0;
+        self.play_next();
+    }
+
+    /// Stop playing, blocking until the current note is stopped
+    pub fn stop(&mut self) {
+        if self.playing {
+            self.pwm.stop();
+            self.playing = false;
+        }
+    }
+
+    /// Is a melody being played?
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Handle the STOPPED event, to be called from the PWMn interrupt handler.
+    /// Returns whether the melody is still playing
+    pub fn on_interrupt(&mut self) -> bool {
+        if self.pwm.periph.events_stopped.read().bits() == 1 {
+            self.pwm.periph.events_stopped.write(|w| unsafe { w.bits(0) });
+            compiler_fence(Ordering::SeqCst);
+
+            if self.playing {
+                self.play_next();
+            }
+        }
+
+        self.playing
+    }
+
+    /// Stop playing, and release the PWM peripheral and the buffer
+    pub fn free(mut self) -> (Pwm<T>, &'static mut u16) {
+        self.stop();
+        self.pwm.periph.intenclr.write(|w| w.stopped().clear());
+        (self.pwm, self.buffer)
+    }
+
+    fn play_next(&mut self) {
+        let note = match self.notes.get(self.next) {
+            Some(note) => *note,
+            None => {
+                self.playing = false;
+                return;
+            }
+        };
+        self.next += 1;
+
+        // Frequencies that can't be played are rests
+        let (freq, config) = match frequency_config(u32::from(note.frequency), CounterMode::Up) {
+            Some(config) => (note.frequency, Some(config)),
+            None => (REST_FREQ, frequency_config(u32::from(REST_FREQ), CounterMode::Up)),
+        };
+        // Can't fail, the rest frequency is in range
+        let (prescaler, top) = config.unwrap();
+        let duty = if freq == note.frequency { top / 2 } else { 0 };
+
+        self.pwm.set_prescaler(prescaler);
+        // Can't fail, the configuration is in range
+        self.pwm.set_max_duty(top).unwrap();
+        *self.buffer = duty | FALLING_EDGE;
+
+        let periph = &self.pwm.periph;
+        periph.decoder.write(|w| w.load().common().mode().refresh_count());
+        periph.loop_.write(|w| w.cnt().disabled());
+        periph.shorts.write(|w| w.seqend0_stop().enabled());
+
+        let refresh = cmp::min(note_periods(freq, note.duration_ms) - 1, MAX_REFRESH);
+        unsafe {
+            periph.seq0.ptr.write(|w| w.ptr().bits(&*self.buffer as *const u16 as usize as u32));
+            periph.seq0.cnt.write(|w| w.cnt().bits(1));
+            periph.seq0.refresh.write(|w| w.cnt().bits(refresh));
+            periph.seq0.enddelay.write(|w| w.cnt().bits(0));
+        }
+
+        compiler_fence(Ordering::SeqCst);
+        periph.events_stopped.write(|w| unsafe { w.bits(0) });
+        periph.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+        self.playing = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequencies() {
+        // 16 MHz / 440 Hz does not fit in 15 bits
+        assert_eq!(
+            frequency_config(440, CounterMode::Up),
+            Some((Prescaler::Div2, 18_182))
+        );
+        assert_eq!(
+            frequency_config(440, CounterMode::UpAndDown),
+            Some((Prescaler::Div1, 18_182))
+        );
+        assert_eq!(
+            frequency_config(1_000, CounterMode::Up),
+            Some((Prescaler::Div1, 16_000))
+        );
+        assert_eq!(
+            frequency_config(4, CounterMode::Up),
+            Some((Prescaler::Div128, 31_250))
+        );
+
+        assert_eq!(frequency_config(3, CounterMode::Up), None);
+        assert_eq!(frequency_config(0, CounterMode::Up), None);
+        assert_eq!(
+            frequency_config(5_000_000, CounterMode::Up),
+            Some((Prescaler::Div1, 3))
+        );
+        assert_eq!(frequency_config(7_000_000, CounterMode::Up), None);
+        assert_eq!(frequency_config(u32::MAX, CounterMode::UpAndDown), None);
+    }
+
+    #[test]
+    fn notes() {
+        assert_eq!(note_periods(pitch::A4, 500), 220);
+        assert_eq!(note_periods(pitch::C4, 250), 66);
+        assert_eq!(note_periods(pitch::C4, 0), 1);
+        assert_eq!(note_periods(u16::MAX, u16::MAX), 4_294_836);
+        assert_eq!(Prescaler::Div128.counter_freq(), 125_000);
+    }
+}
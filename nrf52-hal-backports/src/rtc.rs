@@ -2,10 +2,18 @@
 
 #![allow(dead_code)]
 
+use core::cmp;
 use core::ops::Deref;
 
+use embedded_hal::timer::{CountDown, Periodic};
+use void::Void;
+
+use crate::clocks::LfClkRunning;
+use crate::ppi::{Event, Task};
+
 use nrf52832_pac::{
     rtc0,
+    Interrupt,
     RTC0,
     RTC1,
 };
@@ -26,21 +34,31 @@ pub struct Started;
 pub struct Rtc<T, M> {
     periph: T,
     _mode: M,
+    /// The period of the `CountDown`, in ticks
+    period: u32,
 }
 
 /// An extension trait for constructing the high level interface
 pub trait RtcExt : Deref<Target=rtc0::RegisterBlock> + Sized {
-    fn constrain(self) -> Rtc<Self, Stopped>;
+    /// The interrupt of the RTC peripheral
+    const INTERRUPT: Interrupt;
+
+    /// Construct the high level interface. The RTC is clocked by the Low
+    /// Frequency Clock, which must have been started
+    fn constrain(self, lfclk: LfClkRunning<'_>) -> Rtc<Self, Stopped>;
 }
 
 macro_rules! impl_rtc_ext {
-    ($($rtc:ty,)*) => {
+    ($($rtc:ident,)*) => {
         $(
             impl RtcExt for $rtc {
-                fn constrain(self) -> Rtc<$rtc, Stopped> {
+                const INTERRUPT: Interrupt = Interrupt::$rtc;
+
+                fn constrain(self, _lfclk: LfClkRunning<'_>) -> Rtc<$rtc, Stopped> {
                     Rtc {
                         periph: self,
                         _mode: Stopped,
+                        period: 0,
                     }
                 }
             }
@@ -68,7 +86,16 @@ pub enum RtcInterrupt {
     Compare3,
 }
 
+/// Tasks that can be triggered on the RTCn peripheral, such as through PPI
+pub enum RtcTask {
+    Start,
+    Stop,
+    Clear,
+    TriggerOverflow,
+}
+
 /// Compare registers available on the RTCn
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RtcCompareReg {
     Compare0,
     Compare1,
@@ -85,6 +112,7 @@ impl<T, M> Rtc<T, M> where T: RtcExt {
         Rtc {
             periph: self.periph,
             _mode: Started,
+            period: self.period,
         }
     }
 
@@ -96,6 +124,7 @@ impl<T, M> Rtc<T, M> where T: RtcExt {
         Rtc {
             periph: self.periph,
             _mode: Stopped,
+            period: self.period,
         }
     }
 
@@ -218,6 +247,48 @@ impl<T, M> Rtc<T, M> where T: RtcExt {
         self.periph.counter.read().bits()
     }
 
+    /// The PPI event endpoint of a given stimulus. The event is only
+    /// generated once enabled with `enable_event`
+    pub fn event(&self, evt: RtcInterrupt) -> Event {
+        match evt {
+            RtcInterrupt::Tick => Event::from_register(&self.periph.events_tick),
+            RtcInterrupt::Overflow => Event::from_register(&self.periph.events_ovrflw),
+            RtcInterrupt::Compare0 => Event::from_register(&self.periph.events_compare[0]),
+            RtcInterrupt::Compare1 => Event::from_register(&self.periph.events_compare[1]),
+            RtcInterrupt::Compare2 => Event::from_register(&self.periph.events_compare[2]),
+            RtcInterrupt::Compare3 => Event::from_register(&self.periph.events_compare[3]),
+        }
+    }
+
+    /// The PPI task endpoint of a given task
+    pub fn task(&self, task: RtcTask) -> Task {
+        match task {
+            RtcTask::Start => Task::from_register(&self.periph.tasks_start),
+            RtcTask::Stop => Task::from_register(&self.periph.tasks_stop),
+            RtcTask::Clear => Task::from_register(&self.periph.tasks_clear),
+            RtcTask::TriggerOverflow => Task::from_register(&self.periph.tasks_trigovrflw),
+        }
+    }
+
+    /// Obtain the current prescaler of the Real Time Counter
+    pub fn get_prescaler(&self) -> u32 {
+        self.periph.prescaler.read().bits()
+    }
+
+    /// Obtain the state of a given interrupt/event, without modifying it
+    pub fn is_event_triggered(&self, evt: RtcInterrupt) -> bool {
+        let bits = match evt {
+            RtcInterrupt::Tick => self.periph.events_tick.read().bits(),
+            RtcInterrupt::Overflow => self.periph.events_ovrflw.read().bits(),
+            RtcInterrupt::Compare0 => self.periph.events_compare[0].read().bits(),
+            RtcInterrupt::Compare1 => self.periph.events_compare[1].read().bits(),
+            RtcInterrupt::Compare2 => self.periph.events_compare[2].read().bits(),
+            RtcInterrupt::Compare3 => self.periph.events_compare[3].read().bits(),
+        };
+
+        bits == 1
+    }
+
     /// Destructure the high level interface. Does not reset any configuration made
     /// to the given RTC peripheral
     pub fn release(self) -> T {
@@ -245,3 +316,82 @@ impl<T> Rtc<T, Stopped> where T: RtcExt {
         Ok(())
     }
 }
+
+/// The longest `CountDown` period, in ticks
+pub const MAX_PERIOD: u32 = COUNTER_MASK;
+
+/// Writing a compare value of the counter, or the counter plus one, may not
+/// trigger the compare event. The counter may also increment once between
+/// reading it and writing the compare value
+const MIN_PERIOD: u32 = 3;
+
+const COUNTER_MASK: u32 = (1 << 24) - 1;
+
+/// The compare value of the period after the one ending at `previous`. If the
+/// counter already passed it, the period starts over from the counter instead
+fn next_compare(previous: u32, counter: u32, period: u32) -> u32 {
+    let elapsed = counter.wrapping_sub(previous) & COUNTER_MASK;
+
+    if elapsed + MIN_PERIOD > period {
+        counter.wrapping_add(period) & COUNTER_MASK
+    } else {
+        previous.wrapping_add(period) & COUNTER_MASK
+    }
+}
+
+/// Counts down using compare register 0. The count is in ticks of the RTC,
+/// fRTC = 32_768 / (`prescaler` + 1)
+impl<T> CountDown for Rtc<T, Started> where T: RtcExt {
+    type Time = u32;
+
+    /// Start counting down `count` ticks. Counts of less than three ticks
+    /// last three ticks. Panics if `count` is more than `MAX_PERIOD`
+    fn start<C>(&mut self, count: C) where C: Into<u32> {
+        let count = count.into();
+        assert!(count <= MAX_PERIOD, "count down period out of range");
+
+        self.period = cmp::max(count, MIN_PERIOD);
+        let compare = self.get_counter().wrapping_add(self.period) & COUNTER_MASK;
+
+        unsafe { self.periph.cc[0].write(|w| w.bits(compare)); }
+        self.get_event_triggered(RtcInterrupt::Compare0, true);
+        self.enable_event(RtcInterrupt::Compare0);
+    }
+
+    /// Check whether the period has passed. The next period starts at the end
+    /// of this one, so periods do not drift
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.period == 0 || !self.get_event_triggered(RtcInterrupt::Compare0, true) {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let previous = self.periph.cc[0].read().bits();
+        let compare = next_compare(previous, self.get_counter(), self.period);
+        unsafe { self.periph.cc[0].write(|w| w.bits(compare)); }
+
+        Ok(())
+    }
+}
+
+impl<T> Periodic for Rtc<T, Started> where T: RtcExt {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods() {
+        // On time, or a little late
+        assert_eq!(next_compare(1_000, 1_000, 100), 1_100);
+        assert_eq!(next_compare(1_000, 1_050, 100), 1_100);
+        assert_eq!(next_compare(COUNTER_MASK - 10, 5, 100), 89);
+
+        // Too late to catch the next compare value
+        assert_eq!(next_compare(1_000, 1_098, 100), 1_198);
+        assert_eq!(next_compare(1_000, 1_500, 100), 1_600);
+        assert_eq!(next_compare(COUNTER_MASK - 10, 200, 100), 300);
+
+        assert_eq!(next_compare(0, 1, MAX_PERIOD), MAX_PERIOD);
+        assert_eq!(next_compare(0, MAX_PERIOD - 1, MAX_PERIOD), MAX_PERIOD - 2);
+    }
+}
@@ -0,0 +1,181 @@
+//! A high level interface for the on-die temperature sensor (TEMP)
+//!
+//! A measurement takes about 36 µs, and has a resolution of 0.25 °C. It may
+//! be waited for with `measure`, or started with `start_measurement` and
+//! read with `read` once the DATARDY event is generated, such as from the
+//! TEMP interrupt handler.
+//!
+//! The anomalies of the nRF52832 TEMP peripheral are worked around:
+//!
+//! * 66: the factory linearization coefficients are copied from FICR when
+//!   constrained, as the default coefficients don't meet the specification
+//! * 28: negative values are sign extended from the 10 bits measured
+//! * 29: the value is read before the STOP task, which clears it
+//! * 30: the STOP task is triggered after every measurement, or the analog
+//!   front end stays powered
+
+#![allow(dead_code)]
+
+use nb;
+use nrf52832_pac::{ficr, FICR, TEMP};
+use void::Void;
+
+/// The number of bits of a measurement, the rest of the TEMP register may not
+/// be sign extended
+const VALUE_BITS: u32 = 10;
+
+/// The value of an erased FICR register, without factory coefficients
+const ERASED: u32 = 0xFFFF_FFFF;
+
+/// A temperature, in steps of 0.25 °C
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Temperature {
+    quarters: i32,
+}
+
+impl Temperature {
+    /// A temperature of a number of 0.25 °C steps
+    pub const fn from_quarter_degrees(quarters: i32) -> Self {
+        Temperature { quarters }
+    }
+
+    /// The temperature in 0.25 °C steps
+    pub fn quarter_degrees(&self) -> i32 {
+        self.quarters
+    }
+
+    /// The temperature in 0.01 °C steps
+    pub fn centidegrees(&self) -> i32 {
+        self.quarters * 25
+    }
+
+    /// The temperature in whole °C, rounded towards negative infinity
+    pub fn degrees(&self) -> i32 {
+        self.quarters >> 2
+    }
+}
+
+/// Sign extend the measured bits of the TEMP register
+fn sign_extend(raw: u32) -> i32 {
+    let shift = 32 - VALUE_BITS;
+    ((raw << shift) as i32) >> shift
+}
+
+/// An extension trait for constructing the high level interface
+pub trait TempExt {
+    fn constrain(self) -> Temp;
+}
+
+impl TempExt for TEMP {
+    fn constrain(self) -> Temp {
+        // FICR is read only
+        let ficr: &ficr::RegisterBlock = unsafe { &*FICR::ptr() };
+
+        macro_rules! calibrate {
+            ($($reg:ident,)*) => {
+                $(
+                    let value = ficr.temp.$reg.read().bits();
+                    if value != ERASED {
+                        self.$reg.write(|w| unsafe { w.bits(value) });
+                    }
+                )*
+            }
+        }
+
+        calibrate!(
+            a0, a1, a2, a3, a4, a5,
+            b0, b1, b2, b3, b4, b5,
+            t0, t1, t2, t3, t4,
+        );
+
+        Temp { periph: self }
+    }
+}
+
+/// An opaque high level interface to the temperature sensor
+pub struct Temp {
+    periph: TEMP,
+}
+
+impl Temp {
+    /// Measure the temperature, blocking until done
+    pub fn measure(&mut self) -> Temperature {
+        self.start_measurement();
+        loop {
+            match self.read() {
+                Ok(temperature) => return temperature,
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(void)) => match void {},
+            }
+        }
+    }
+
+    /// Start a measurement. The DATARDY event is generated once done
+    pub fn start_measurement(&mut self) {
+        self.periph.events_datardy.write(|w| unsafe { w.bits(0) });
+        self.periph.tasks_start.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// The result of the measurement, once done
+    pub fn read(&mut self) -> nb::Result<Temperature, Void> {
+        if self.periph.events_datardy.read().bits() == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.periph.events_datardy.write(|w| unsafe { w.bits(0) });
+
+        // The value must be read before the STOP task clears it
+        let raw = self.periph.temp.read().bits();
+        self.periph.tasks_stop.write(|w| unsafe { w.bits(1) });
+
+        Ok(Temperature::from_quarter_degrees(sign_extend(raw)))
+    }
+
+    /// Stop a measurement that has been started, without reading it
+    pub fn cancel_measurement(&mut self) {
+        self.periph.tasks_stop.write(|w| unsafe { w.bits(1) });
+        self.periph.events_datardy.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Request the TEMP interrupt on the DATARDY event
+    pub fn enable_interrupt(&mut self) {
+        self.periph.intenset.write(|w| w.datardy().set());
+    }
+
+    /// Stop requesting the TEMP interrupt
+    pub fn disable_interrupt(&mut self) {
+        self.periph.intenclr.write(|w| w.datardy().clear());
+    }
+
+    /// Destructure the high level interface. The calibration remains until
+    /// the next reset
+    pub fn release(self) -> TEMP {
+        self.periph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_extension() {
+        assert_eq!(sign_extend(0x0000_0064), 100);
+        assert_eq!(sign_extend(0x0000_01FF), 511);
+        // Only the measured bits are sign extended
+        assert_eq!(sign_extend(0x0000_03FF), -1);
+        assert_eq!(sign_extend(0xFFFF_FFFF), -1);
+        assert_eq!(sign_extend(0x0000_0200), -512);
+        assert_eq!(sign_extend(0x0000_03D8), -40);
+    }
+
+    #[test]
+    fn conversions() {
+        let temperature = Temperature::from_quarter_degrees(101);
+        assert_eq!(temperature.centidegrees(), 2_525);
+        assert_eq!(temperature.degrees(), 25);
+
+        let temperature = Temperature::from_quarter_degrees(-3);
+        assert_eq!(temperature.centidegrees(), -75);
+        assert_eq!(temperature.degrees(), -1);
+    }
+}
@@ -0,0 +1,394 @@
+//! Many software timers sharing one RTC compare register
+//!
+//! `Timers` keeps the deadlines of all running timers, and programs a single
+//! compare register with the earliest of them. Deadlines are `Instant`s of a
+//! `Monotonic`, so they may be much further away than the 24-bit range of the
+//! compare register. Those are armed by the overflow interrupt, once they are
+//! within range.
+//!
+//! The RTC interrupt handler collects the expired timers with `expired`,
+//! which also re-arms the compare register for the next deadline.
+
+use core::cmp;
+use core::time::Duration;
+
+use heapless::{ArrayLength, Vec};
+
+use crate::monotonic::{Counter, Instant, Monotonic, COUNTER_BITS};
+use crate::rtc::{Rtc, RtcCompareReg, RtcExt, RtcInterrupt, Started};
+
+/// The range of the compare register
+const WRAP: u64 = 1 << COUNTER_BITS;
+
+/// A compare value of the counter, or the counter plus one, may not set the
+/// compare event
+const MIN_DELTA: u64 = 2;
+
+/// The compare registers of a running RTC used by `Timers`
+pub trait Compare: Counter {
+    /// Set a compare register, clear its event and enable its interrupt
+    fn arm(&mut self, reg: RtcCompareReg, value: u32);
+
+    /// Disable the interrupt of a compare register
+    fn disarm(&mut self, reg: RtcCompareReg);
+
+    /// Obtain the state of the event of a compare register, and clear it
+    fn take_compare(&mut self, reg: RtcCompareReg) -> bool;
+}
+
+fn compare_interrupt(reg: RtcCompareReg) -> RtcInterrupt {
+    match reg {
+        RtcCompareReg::Compare0 => RtcInterrupt::Compare0,
+        RtcCompareReg::Compare1 => RtcInterrupt::Compare1,
+        RtcCompareReg::Compare2 => RtcInterrupt::Compare2,
+        RtcCompareReg::Compare3 => RtcInterrupt::Compare3,
+    }
+}
+
+impl<T> Compare for Rtc<T, Started> where T: RtcExt {
+    fn arm(&mut self, reg: RtcCompareReg, value: u32) {
+        // Can't fail, the value is masked to 24 bits
+        self.set_compare(reg, value & (WRAP as u32 - 1)).unwrap();
+        self.get_event_triggered(compare_interrupt(reg), true);
+        self.enable_interrupt(compare_interrupt(reg));
+    }
+
+    fn disarm(&mut self, reg: RtcCompareReg) {
+        self.disable_interrupt(compare_interrupt(reg));
+    }
+
+    fn take_compare(&mut self, reg: RtcCompareReg) -> bool {
+        self.get_event_triggered(compare_interrupt(reg), true)
+    }
+}
+
+/// Identifies a running timer
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash)]
+pub struct TimerId(u32);
+
+/// A running timer
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Timer {
+    id: TimerId,
+    deadline: Instant,
+}
+
+/// Error types associated with the software timers
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// All `N` timers are already running
+    Full,
+}
+
+/// Up to `N` one-shot timers, sharing a compare register
+///
+/// The RTC interrupt handler must drain `expired`, which also handles the
+/// overflow event. When shared with the handler, such as an RTFM resource,
+/// timers must be started and cancelled with the resource locked
+pub struct Timers<C, N>
+where
+    N: ArrayLength<Timer>,
+{
+    monotonic: Monotonic<C>,
+    reg: RtcCompareReg,
+    timers: Vec<Timer, N>,
+    next_id: u32,
+}
+
+impl<C, N> Timers<C, N>
+where
+    C: Compare,
+    N: ArrayLength<Timer>,
+{
+    /// Run timers on a compare register of the counter of `monotonic`. The
+    /// register must not be used by anything else
+    pub fn new(mut monotonic: Monotonic<C>, reg: RtcCompareReg) -> Self {
+        monotonic.counter().disarm(reg);
+
+        Timers {
+            monotonic,
+            reg,
+            timers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// The current instant
+    pub fn now(&self) -> Instant {
+        self.monotonic.now()
+    }
+
+    /// The monotonic clock the deadlines are measured with
+    pub fn monotonic(&self) -> &Monotonic<C> {
+        &self.monotonic
+    }
+
+    /// Start a timer that expires once `timeout` has passed
+    pub fn start(&mut self, timeout: Duration) -> Result<TimerId, Error> {
+        let ticks = self.monotonic.duration_to_ticks(timeout);
+        let deadline = self
+            .now()
+            .checked_add(ticks)
+            .unwrap_or_else(|| Instant::from_ticks(u64::MAX));
+
+        self.start_at(deadline)
+    }
+
+    /// Start a timer that expires at `deadline`. A deadline that has already
+    /// passed expires in the next interrupt
+    pub fn start_at(&mut self, deadline: Instant) -> Result<TimerId, Error> {
+        let id = self.allocate_id();
+        self.timers
+            .push(Timer { id, deadline })
+            .map_err(|_| Error::Full)?;
+
+        self.arm();
+        Ok(id)
+    }
+
+    /// Stop a running timer. Returns whether the timer was running
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        match self.timers.iter().position(|timer| timer.id == id) {
+            Some(index) => {
+                self.timers.swap_remove(index);
+                self.arm();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The deadline of a running timer
+    pub fn deadline(&self, id: TimerId) -> Option<Instant> {
+        self.timers
+            .iter()
+            .find(|timer| timer.id == id)
+            .map(|timer| timer.deadline)
+    }
+
+    /// The number of running timers
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Are no timers running?
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Handle the RTC events, and return the next expired timer. Once no
+    /// more timers have expired, the compare register is armed for the next
+    /// deadline and `None` is returned
+    pub fn poll(&mut self) -> Option<TimerId> {
+        self.monotonic.on_overflow();
+        self.monotonic.counter().take_compare(self.reg);
+
+        // Read after clearing the event, so any deadline that set it has passed
+        let now = self.now();
+        let expired = self
+            .timers
+            .iter()
+            .enumerate()
+            .filter(|(_, timer)| timer.deadline <= now)
+            .min_by_key(|(_, timer)| timer.deadline)
+            .map(|(index, _)| index);
+
+        match expired {
+            Some(index) => Some(self.timers.swap_remove(index).id),
+            None => {
+                self.arm();
+                None
+            }
+        }
+    }
+
+    /// The expired timers, earliest deadline first, to be drained from the
+    /// RTC interrupt handler
+    pub fn expired(&mut self) -> Expired<'_, C, N> {
+        Expired { timers: self }
+    }
+
+    /// Stop all timers, and release the monotonic clock
+    pub fn free(mut self) -> Monotonic<C> {
+        self.monotonic.counter().disarm(self.reg);
+        self.monotonic
+    }
+
+    fn allocate_id(&mut self) -> TimerId {
+        loop {
+            let id = TimerId(self.next_id);
+            self.next_id = self.next_id.wrapping_add(1);
+
+            if self.timers.iter().all(|timer| timer.id != id) {
+                return id;
+            }
+        }
+    }
+
+    /// Program the compare register with the earliest deadline, if it is
+    /// within range
+    fn arm(&mut self) {
+        let next = match self.timers.iter().map(|timer| timer.deadline).min() {
+            Some(next) => next.ticks(),
+            None => {
+                self.monotonic.counter().disarm(self.reg);
+                return;
+            }
+        };
+
+        let mut margin = MIN_DELTA;
+        loop {
+            let now = self.now().ticks();
+            let target = cmp::max(next, now + margin);
+
+            // Armed by the interrupt of a later overflow instead
+            if target - now >= WRAP {
+                self.monotonic.counter().disarm(self.reg);
+                return;
+            }
+
+            self.monotonic.counter().arm(self.reg, target as u32 & (WRAP as u32 - 1));
+
+            // The counter may have reached the compare value while it was
+            // written, in which case the event may never be set. Try again,
+            // further ahead in case the writes are slow
+            if self.now().ticks() + MIN_DELTA <= target {
+                return;
+            }
+            margin *= 2;
+        }
+    }
+}
+
+/// An iterator over the expired timers, returned by `Timers::expired`
+pub struct Expired<'a, C, N>
+where
+    N: ArrayLength<Timer>,
+{
+    timers: &'a mut Timers<C, N>,
+}
+
+impl<'a, C, N> Iterator for Expired<'a, C, N>
+where
+    C: Compare,
+    N: ArrayLength<Timer>,
+{
+    type Item = TimerId;
+
+    fn next(&mut self) -> Option<TimerId> {
+        self.timers.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeRtc;
+    use heapless::consts::U8;
+
+    fn fake_timers(time: u64, step: u64) -> Timers<FakeRtc, U8> {
+        Timers::new(Monotonic::new(FakeRtc::new(time, step)), RtcCompareReg::Compare1)
+    }
+
+    /// Run the interrupt handler `latency` ticks after each interrupt, until
+    /// `end`, recording when each timer expired
+    fn run(
+        timers: &mut Timers<FakeRtc, U8>,
+        latency: u64,
+        end: u64,
+        fired: &mut [(TimerId, u64)],
+    ) -> usize {
+        let mut count = 0;
+
+        loop {
+            let rtc = timers.monotonic.counter();
+            let at = rtc.next_interrupt() + latency;
+            if at > end {
+                return count;
+            }
+            rtc.time.set(cmp::max(at, rtc.time.get()));
+
+            while let Some(id) = timers.poll() {
+                fired[count] = (id, timers.now().ticks());
+                count += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn expire_in_order() {
+        let mut timers = fake_timers(1_000, 1);
+        let late = timers.start_at(Instant::from_ticks(900_000)).unwrap();
+        let early = timers.start(Duration::from_millis(1)).unwrap();
+        let far = timers.start_at(Instant::from_ticks(5 * WRAP + 123)).unwrap();
+        let cancelled = timers.start_at(Instant::from_ticks(500_000)).unwrap();
+        assert_eq!(timers.len(), 4);
+        let deadline = timers.deadline(early).unwrap().ticks();
+        assert!((1_033..1_050).contains(&deadline));
+
+        assert!(timers.cancel(cancelled));
+        assert!(!timers.cancel(cancelled));
+        assert_eq!(timers.deadline(cancelled), None);
+
+        let mut fired = [(TimerId(0), 0); 8];
+        let count = run(&mut timers, 3, 6 * WRAP, &mut fired);
+        assert_eq!(count, 3);
+        assert!(timers.is_empty());
+
+        let expected = [(early, deadline), (late, 900_000), (far, 5 * WRAP + 123)];
+        for (&(id, time), &(expected_id, deadline)) in fired.iter().zip(expected.iter()) {
+            assert_eq!(id, expected_id);
+            assert!(deadline <= time && time < deadline + 10, "{} {}", deadline, time);
+        }
+    }
+
+    #[test]
+    fn already_passed() {
+        let mut timers = fake_timers(10_000, 1);
+        let passed = timers.start_at(Instant::from_ticks(5)).unwrap();
+        let now = timers.start_at(timers.now()).unwrap();
+        let next = timers.start_at(timers.now() + 1).unwrap();
+
+        let mut fired = [(TimerId(0), 0); 8];
+        assert_eq!(run(&mut timers, 0, WRAP / 2, &mut fired), 3);
+        assert_eq!(fired[0].0, passed);
+        assert_eq!(fired[1].0, now);
+        assert_eq!(fired[2].0, next);
+        assert!(fired[2].1 < 10_100);
+    }
+
+    #[test]
+    fn compare_reached_while_written() {
+        // Register accesses are so slow the counter passes the first compare
+        // values before they are written
+        for step in 1..6 {
+            for offset in 0..12 {
+                let mut timers = fake_timers(WRAP - 8, step);
+                let deadline = timers.now() + offset;
+                let id = timers.start_at(deadline).unwrap();
+
+                let mut fired = [(TimerId(0), 0); 8];
+                assert_eq!(run(&mut timers, 0, 2 * WRAP, &mut fired), 1);
+                assert_eq!(fired[0].0, id);
+                assert!(fired[0].1 >= deadline.ticks());
+                assert!(fired[0].1 < deadline.ticks() + 40 * step, "{:?}", fired[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn full() {
+        let mut timers = fake_timers(0, 0);
+        for _ in 0..8 {
+            timers.start(Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(timers.start(Duration::from_secs(1)), Err(Error::Full));
+        assert_eq!(timers.len(), 8);
+
+        // Timers far in the future are not armed until in range
+        let mut timers = fake_timers(0, 0);
+        timers.start(Duration::from_secs(u64::MAX)).unwrap();
+        assert_eq!(timers.monotonic.counter().next_interrupt(), WRAP);
+        assert_eq!(timers.expired().count(), 0);
+    }
+}
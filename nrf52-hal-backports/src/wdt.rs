@@ -0,0 +1,259 @@
+//! A high level interface for the watchdog timer (WDT)
+//!
+//! Once started, the watchdog resets the chip unless every enabled reload
+//! request register is written within the timeout. It can't be stopped or
+//! reconfigured until the next reset, so `Wdt` is configured while
+//! `Inactive`, and started with `activate`.
+//!
+//! Each enabled reload request register is represented by a `WdtHandle`. The
+//! watchdog is only fed once all handles have been `pet`, so giving each task
+//! its own handle makes every task check in independently. A hung task
+//! resets the chip, even when the other tasks keep running.
+
+#![allow(dead_code)]
+
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use heapless::{consts::*, Vec};
+use nrf52832_pac::{wdt, WDT};
+
+use crate::clocks::LFCLK_FREQ;
+
+/// The number of reload request registers, and so of handles
+pub const HANDLES: u8 = 8;
+
+/// The shortest timeout, in 32.768 kHz ticks
+pub const MIN_TIMEOUT_TICKS: u32 = 0xF;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Configuring the watchdog, not started yet
+pub struct Inactive;
+
+/// Running the watchdog, its configuration is locked until the next reset
+pub struct Active;
+
+/// Error types associated with the WDT peripheral interface
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    TimeoutOutOfRange,
+    HandleCountOutOfRange,
+}
+
+/// The number of 32.768 kHz ticks closest to a timeout
+fn timeout_ticks(timeout: Duration) -> Result<u32, Error> {
+    let freq = u128::from(LFCLK_FREQ);
+    let ticks = (timeout.as_nanos() * freq + NANOS_PER_SEC / 2) / NANOS_PER_SEC;
+
+    if ticks < u128::from(MIN_TIMEOUT_TICKS) || ticks > u128::from(u32::MAX) {
+        return Err(Error::TimeoutOutOfRange);
+    }
+
+    Ok(ticks as u32)
+}
+
+/// The reload request registers enabled for a number of handles
+fn handle_mask(count: u8) -> Result<u8, Error> {
+    match count {
+        1..=HANDLES => Ok((0xFF_u16 >> (HANDLES - count)) as u8),
+        _ => Err(Error::HandleCountOutOfRange),
+    }
+}
+
+fn registers() -> &'static wdt::RegisterBlock {
+    // Only the reload request register of an owned handle is written
+    unsafe { &*WDT::ptr() }
+}
+
+/// The value feeding the watchdog, when written to a reload request register
+const RELOAD: u32 = 0x6E52_4635;
+
+/// A reload request register of a running watchdog
+#[derive(Debug)]
+pub struct WdtHandle {
+    index: u8,
+}
+
+impl WdtHandle {
+    /// The number of the reload request register
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Check in. The watchdog is fed once every handle has
+    pub fn pet(&mut self) {
+        registers().rr[usize::from(self.index)].write(|w| unsafe { w.bits(RELOAD) });
+    }
+
+    /// Has this handle checked in since the watchdog was last fed?
+    pub fn has_petted(&self) -> bool {
+        registers().reqstatus.read().bits() & (1 << self.index) == 0
+    }
+}
+
+/// Handles of the enabled reload request registers, in order
+pub type WdtHandles = Vec<WdtHandle, U8>;
+
+fn handles(mask: u8) -> WdtHandles {
+    let mut handles = Vec::new();
+    for index in (0..HANDLES).filter(|index| mask & (1 << index) != 0) {
+        // Can't fail, there are at most eight handles
+        handles.push(WdtHandle { index }).ok();
+    }
+    handles
+}
+
+/// An extension trait for constructing the high level interface
+pub trait WdtExt {
+    /// An inactive watchdog, or if it is already running, such as after a
+    /// soft reset, the running watchdog with handles of its enabled reload
+    /// request registers
+    fn constrain(self) -> Result<Wdt<Inactive>, (Wdt<Active>, WdtHandles)>;
+}
+
+impl WdtExt for WDT {
+    fn constrain(self) -> Result<Wdt<Inactive>, (Wdt<Active>, WdtHandles)> {
+        let wdt = Wdt {
+            periph: self,
+            _state: PhantomData,
+        };
+
+        if wdt.periph.runstatus.read().runstatus().bit_is_set() {
+            let mask = wdt.periph.rren.read().bits() as u8;
+            Err((wdt.into_state(), handles(mask)))
+        } else {
+            Ok(wdt)
+        }
+    }
+}
+
+/// An opaque high level interface to the watchdog
+pub struct Wdt<S> {
+    periph: WDT,
+    _state: PhantomData<S>,
+}
+
+impl<S> Wdt<S> {
+    fn into_state<N>(self) -> Wdt<N> {
+        Wdt {
+            periph: self.periph,
+            _state: PhantomData,
+        }
+    }
+
+    /// The timeout, in 32.768 kHz ticks
+    pub fn timeout_ticks(&self) -> u32 {
+        self.periph.crv.read().bits()
+    }
+
+    /// Has the TIMEOUT event been generated? The chip resets two 32.768 kHz
+    /// ticks after it
+    pub fn is_timed_out(&self) -> bool {
+        self.periph.events_timeout.read().bits() == 1
+    }
+}
+
+impl Wdt<Inactive> {
+    /// Set the time allowed between feeds, at least `MIN_TIMEOUT_TICKS` and
+    /// at most about 36 hours
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        let ticks = timeout_ticks(timeout)?;
+        self.set_timeout_ticks(ticks)
+    }
+
+    /// Set the time allowed between feeds, in 32.768 kHz ticks
+    pub fn set_timeout_ticks(&mut self, ticks: u32) -> Result<(), Error> {
+        if ticks < MIN_TIMEOUT_TICKS {
+            return Err(Error::TimeoutOutOfRange);
+        }
+
+        unsafe { self.periph.crv.write(|w| w.bits(ticks)) };
+        Ok(())
+    }
+
+    /// Keep counting while the CPU is sleeping, or pause. Runs by default
+    pub fn set_run_in_sleep(&mut self, run: bool) {
+        self.periph.config.modify(|_, w| {
+            if run {
+                w.sleep().run()
+            } else {
+                w.sleep().pause()
+            }
+        });
+    }
+
+    /// Keep counting while the CPU is halted by a debugger, or pause. Pauses
+    /// by default
+    pub fn set_run_in_halt(&mut self, run: bool) {
+        self.periph.config.modify(|_, w| {
+            if run {
+                w.halt().run()
+            } else {
+                w.halt().pause()
+            }
+        });
+    }
+
+    /// Request the WDT interrupt on the TIMEOUT event, such as to log the
+    /// reset. There are only two 32.768 kHz ticks before the reset
+    pub fn enable_interrupt(&mut self) {
+        self.periph.intenset.write(|w| w.timeout().set());
+    }
+
+    /// Start the watchdog with a number of handles, from one to eight. The
+    /// low frequency clock is started if it isn't running
+    pub fn activate(self, handle_count: u8) -> Result<(Wdt<Active>, WdtHandles), Error> {
+        let mask = handle_mask(handle_count)?;
+
+        self.periph.rren.write(|w| unsafe { w.bits(u32::from(mask)) });
+        self.periph.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        Ok((self.into_state(), handles(mask)))
+    }
+
+    /// Destructure the high level interface
+    pub fn release(self) -> WDT {
+        self.periph
+    }
+}
+
+impl Wdt<Active> {
+    /// The reload request registers not written since the watchdog was last
+    /// fed, one bit per handle
+    pub fn pending_handles(&self) -> u8 {
+        self.periph.reqstatus.read().bits() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(timeout_ticks(Duration::from_secs(2)), Ok(65_536));
+        assert_eq!(timeout_ticks(Duration::from_micros(458)), Ok(15));
+        assert_eq!(
+            timeout_ticks(Duration::from_micros(300)),
+            Err(Error::TimeoutOutOfRange)
+        );
+        assert_eq!(timeout_ticks(Duration::from_secs(131_071)), Ok(4_294_934_528));
+        assert_eq!(
+            timeout_ticks(Duration::from_secs(131_072)),
+            Err(Error::TimeoutOutOfRange)
+        );
+    }
+
+    #[test]
+    fn handle_masks() {
+        assert_eq!(handle_mask(1), Ok(0b1));
+        assert_eq!(handle_mask(3), Ok(0b111));
+        assert_eq!(handle_mask(8), Ok(0xFF));
+        assert_eq!(handle_mask(0), Err(Error::HandleCountOutOfRange));
+        assert_eq!(handle_mask(9), Err(Error::HandleCountOutOfRange));
+
+        let indices: Vec<u8, U8> = handles(0b1010_0001).iter().map(|h| h.index()).collect();
+        assert_eq!(&indices[..], &[0, 5, 7]);
+    }
+}
@@ -5,9 +5,9 @@
 use core::fmt::Write;
 
 // Crates.io dependencies
-use dw1000::{DW1000 as DW};
 use dwm1001::{
     self,
+    dw1000::{self, DW1000 as DW},
     nrf52832_hal::{
         // delay::Delay,
         prelude::*,
@@ -55,13 +55,12 @@ use nrf52_hal_backports::{
 use uhr::{
     Uhr,
     Wecker,
-    FixedOffsetFromUtc,
+    TimeZoneRule,
     UnixTimestamp,
     DayFlags,
+    Discipline,
 };
 
-use core::time::Duration;
-
 
 #[app(device = nrf52832_pac)]
 const APP: () = {
@@ -77,6 +76,7 @@ const APP: () = {
     static mut RANDOM:     Rng                      = ();
     static mut RTCT:        Rtc<RTC0_PERIPHERAL, Started>       = ();
     static mut ALARM_CLOCK: Wecker<U8> = ();
+    static mut DISCIPLINE:  Discipline = ();
 
     #[init]
     fn init() {
@@ -97,12 +97,13 @@ const APP: () = {
             pins.p0_20,
             pins.p0_18,
             pins.p0_17,
+            None,
         );
 
         let mut rst_pin = DW_RST::new(pins.p0_24.into_floating_input());
 
         // Start the clocks
-        let _clocks = device
+        let clocks = device
             .CLOCK
             .constrain()
             .enable_ext_hfosc()
@@ -116,19 +117,21 @@ const APP: () = {
 
         let dw1000 = dw1000.init().unwrap();
 
-        let mut rtc = RtcExt::constrain(device.RTC0);
-        rtc.set_prescaler(0xFFF).unwrap();
+        const PRESCALER: u16 = 0xFFF;
+        let mut rtc = RtcExt::constrain(device.RTC0, clocks.lfclk_running());
+        rtc.set_prescaler(PRESCALER.into()).unwrap();
         rtc.enable_interrupt(RtcInterrupt::Tick);
 
         let mut alarm = Wecker::new(UnixTimestamp(1554041486));
 
-        // CEST
-        alarm.time.set_local_time_zone(FixedOffsetFromUtc::from_hours_and_minutes(2, 0));
+        // Central European Time, with summer time
+        let cet = TimeZoneRule::parse_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        alarm.time.set_local_time_zone(cet);
 
         // alarm.alarms.push(Uhr::from(UnixTimestamp(1554041486 + 10))).unwrap();
 
         let mut next_alarm = Uhr::from(UnixTimestamp(1554041486 + 10));
-        next_alarm.set_local_time_zone(FixedOffsetFromUtc::from_hours_and_minutes(2, 0));
+        next_alarm.set_local_time_zone(cet);
 
         alarm.insert_alarm(next_alarm, DayFlags::SUNDAY).unwrap();
         // alarm.alarms.push(Uhr::from(UnixTimestamp(1554041486 + 25))).unwrap();
@@ -143,6 +146,7 @@ const APP: () = {
         TIMER = timer;
         LED_RED_1 = pins.p0_14.degrade().into_push_pull_output(Level::High);
         ALARM_CLOCK = alarm;
+        DISCIPLINE = Discipline::lfclk(PRESCALER);
     }
 
     #[idle(resources = [TIMER, RANDOM, DW1000])]
@@ -152,11 +156,10 @@ const APP: () = {
         }
     }
 
-    #[interrupt(resources = [ALARM_CLOCK, RTCT, LED_RED_1, LOGGER])]
+    #[interrupt(resources = [ALARM_CLOCK, DISCIPLINE, RTCT, LED_RED_1, LOGGER])]
     fn RTC0() {
         static mut TOGG: bool = false;
         static mut STEP: u32 = 0;
-        const TICK_TIME: &Duration = &Duration::from_millis(125);
 
         (*resources.RTCT).get_event_triggered(RtcInterrupt::Tick, true);
 
@@ -169,7 +172,7 @@ const APP: () = {
         let mut out: String<U1024> = String::new();
 
 
-        resources.ALARM_CLOCK.time.increment(TICK_TIME);
+        resources.DISCIPLINE.advance(&mut resources.ALARM_CLOCK.time, 1);
         if resources.ALARM_CLOCK.alarm_ready() {
             out.clear();
             write!(&mut out, "!!! ALARM !!!").unwrap();
@@ -180,15 +183,8 @@ const APP: () = {
         }
 
         if (*STEP & 0x7) == 0 {
-            let time = resources.ALARM_CLOCK.time.into_local_date_time();
             out.clear();
-            write!(
-                &mut out,
-                "TIME {:02}:{:02}:{:02}",
-                time.hour(),
-                time.minute(),
-                time.second(),
-            ).unwrap();
+            write!(&mut out, "TIME {}", resources.ALARM_CLOCK.time.rfc3339()).unwrap();
             (*resources.LOGGER).log(&out).unwrap();
         }
 
@@ -0,0 +1,359 @@
+//! Remote management of the alarms held by a node's `uhr::Wecker`
+//!
+//! A node receiving an `AlarmRequest` applies it to its alarm clock, and
+//! answers with an `AlarmResponse`. With the `uhr` feature enabled,
+//! `AlarmRequest::apply` does both.
+
+use heapless::{consts::U4, Vec};
+use serde::{Deserialize, Serialize};
+
+use crate::time::Timestamp;
+
+/// The maximum number of alarms sent in a single `AlarmResponse::Listing`
+pub const ALARMS_PER_PAGE: usize = 4;
+
+/// The schedule an alarm repeats on, as sent over the wire. Mirrors
+/// `uhr::Schedule`, and is encoded the same way, so new variants must only
+/// be added at the end
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum Repeat {
+    /// Never repeat
+    Once,
+    /// The bits of the `uhr::DayFlags` the alarm repeats on
+    Weekly(u8),
+    /// Every number of days
+    EveryDays(u16),
+    /// Every number of seconds
+    Interval(u32),
+    /// A day of every month
+    MonthlyOnDay(u8),
+    /// The `week`th `weekday` of every month, with the weekday given as its
+    /// ISO number
+    MonthlyOnWeekday { week: u8, weekday: u8 },
+    /// A date of every year, with the month given as its number
+    Yearly { month: u8, day: u8 },
+}
+
+/// An alarm, as sent over the wire
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct AlarmInfo {
+    pub id: u16,
+    /// The next time the alarm will fire
+    pub time: Timestamp,
+    pub repeat: Repeat,
+}
+
+/// A request to manage the alarms of a node
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum AlarmRequest {
+    /// Add a new alarm, first firing at `time`, and repeating on `repeat`
+    Add { time: Timestamp, repeat: Repeat },
+
+    /// List the alarms of the node in firing order, skipping the first
+    /// `start` alarms
+    List { start: u16 },
+
+    /// Remove an existing alarm
+    Remove { id: u16 },
+
+    /// Replace the schedule of an existing alarm
+    Update { id: u16, time: Timestamp, repeat: Repeat },
+}
+
+/// The reply to an `AlarmRequest`
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub enum AlarmResponse {
+    Added { id: u16 },
+
+    /// A page of at most `ALARMS_PER_PAGE` alarms, starting at the `start`th
+    /// alarm, out of a `total` number of alarms held by the node
+    Listing {
+        total: u16,
+        start: u16,
+        alarms: Vec<AlarmInfo, U4>,
+    },
+
+    Removed { id: u16 },
+    Updated { id: u16 },
+    Error(AlarmError),
+}
+
+/// Reasons an `AlarmRequest` may fail. These are sent over the wire by
+/// variant position, so new variants must only be added at the end
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum AlarmError {
+    /// The first time of a repeating alarm is not on one of its repeat days
+    NotOnRepeat,
+
+    /// The node has no space for more alarms
+    Full,
+
+    /// No alarm exists with the given id
+    UnknownAlarm,
+
+    /// The `Repeat` does not describe a valid schedule
+    InvalidRepeat,
+
+    /// The alarm has already been snoozed the maximum number of times
+    SnoozeLimit,
+
+    /// No space remains for more exception dates
+    ExceptionsFull,
+
+    /// No exception exists with the given dates
+    UnknownException,
+}
+
+#[cfg(feature = "uhr")]
+mod uhr_support {
+    use super::*;
+    use uhr::{wecker, Alarm, AlarmId, ArrayLength, DayFlags, Recurrence, Schedule, Uhr, Wecker};
+
+    impl From<wecker::Error> for AlarmError {
+        fn from(err: wecker::Error) -> AlarmError {
+            match err {
+                wecker::Error::AlarmNotOnRepeat => AlarmError::NotOnRepeat,
+                wecker::Error::AlarmFull => AlarmError::Full,
+                wecker::Error::UnknownAlarm => AlarmError::UnknownAlarm,
+                wecker::Error::SnoozeLimit => AlarmError::SnoozeLimit,
+                wecker::Error::ExceptionsFull => AlarmError::ExceptionsFull,
+                wecker::Error::UnknownException => AlarmError::UnknownException,
+            }
+        }
+    }
+
+    impl From<Recurrence> for Repeat {
+        fn from(recurrence: Recurrence) -> Repeat {
+            match recurrence.schedule() {
+                Schedule::Once => Repeat::Once,
+                Schedule::Weekly(days) => Repeat::Weekly(days.bits()),
+                Schedule::EveryDays(days) => Repeat::EveryDays(days),
+                Schedule::Interval(secs) => Repeat::Interval(secs),
+                Schedule::MonthlyOnDay(day) => Repeat::MonthlyOnDay(day),
+                Schedule::MonthlyOnWeekday { week, weekday } => {
+                    Repeat::MonthlyOnWeekday { week, weekday }
+                }
+                Schedule::Yearly { month, day } => Repeat::Yearly { month, day },
+            }
+        }
+    }
+
+    impl<'a> From<&'a Alarm> for AlarmInfo {
+        fn from(alarm: &'a Alarm) -> AlarmInfo {
+            AlarmInfo {
+                id: alarm.id().0,
+                time: alarm.next_time().into(),
+                repeat: alarm.recurrence().into(),
+            }
+        }
+    }
+
+    impl AlarmRequest {
+        /// Apply the request to an alarm clock, returning the reply to send.
+        /// Alarm times are interpreted in the time zone of the clock
+        pub fn apply<A>(&self, wecker: &mut Wecker<A>) -> AlarmResponse
+        where
+            A: ArrayLength<Alarm>,
+        {
+            match self.try_apply(wecker) {
+                Ok(resp) => resp,
+                Err(err) => AlarmResponse::Error(err),
+            }
+        }
+
+        fn try_apply<A>(&self, wecker: &mut Wecker<A>) -> Result<AlarmResponse, AlarmError>
+        where
+            A: ArrayLength<Alarm>,
+        {
+            match *self {
+                AlarmRequest::Add { time, repeat } => {
+                    let time = local_time(wecker, time);
+                    let id = wecker.insert_alarm(time, recurrence(repeat)?)?;
+                    Ok(AlarmResponse::Added { id: id.0 })
+                }
+                AlarmRequest::List { start } => {
+                    let mut alarms = Vec::new();
+                    for alarm in wecker.alarms().skip(start.into()).take(ALARMS_PER_PAGE) {
+                        // Can't fail, we only take as many as fit
+                        alarms.push(alarm.into()).ok();
+                    }
+
+                    Ok(AlarmResponse::Listing {
+                        total: wecker.alarms().len() as u16,
+                        start,
+                        alarms,
+                    })
+                }
+                AlarmRequest::Remove { id } => {
+                    wecker.remove_alarm(AlarmId(id))?;
+                    Ok(AlarmResponse::Removed { id })
+                }
+                AlarmRequest::Update { id, time, repeat } => {
+                    let time = local_time(wecker, time);
+                    wecker.update_alarm(AlarmId(id), time, recurrence(repeat)?)?;
+                    Ok(AlarmResponse::Updated { id })
+                }
+            }
+        }
+    }
+
+    fn local_time<A>(wecker: &Wecker<A>, time: Timestamp) -> Uhr
+    where
+        A: ArrayLength<Alarm>,
+    {
+        let mut time = Uhr::from(time);
+        time.set_local_time_zone(wecker.time.local_time_zone());
+        time
+    }
+
+    fn recurrence(repeat: Repeat) -> Result<Recurrence, AlarmError> {
+        let schedule = match repeat {
+            Repeat::Once => Schedule::Once,
+            Repeat::Weekly(bits) => {
+                Schedule::Weekly(DayFlags::from_bits(bits).ok_or(AlarmError::InvalidRepeat)?)
+            }
+            Repeat::EveryDays(days) => Schedule::EveryDays(days),
+            Repeat::Interval(secs) => Schedule::Interval(secs),
+            Repeat::MonthlyOnDay(day) => Schedule::MonthlyOnDay(day),
+            Repeat::MonthlyOnWeekday { week, weekday } => {
+                Schedule::MonthlyOnWeekday { week, weekday }
+            }
+            Repeat::Yearly { month, day } => Schedule::Yearly { month, day },
+        };
+
+        Recurrence::from_schedule(schedule).ok_or(AlarmError::InvalidRepeat)
+    }
+}
+
+#[cfg(all(test, feature = "uhr"))]
+mod tests {
+    use super::*;
+    use uhr::{DayFlags, UnixTimestamp, Wecker};
+
+    // Sunday, 2019-03-31 14:11:26 UTC
+    const NOW: i64 = 1554041486;
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp { seconds, nanos: 0 }
+    }
+
+    #[test]
+    fn add_list_remove() {
+        let mut wecker: Wecker<U4> = Wecker::new(UnixTimestamp(NOW));
+
+        let mut ids = [0u16; 4];
+        for (i, id) in ids.iter_mut().enumerate() {
+            let req = AlarmRequest::Add {
+                time: at(NOW + 60 * i as i64),
+                repeat: Repeat::Once,
+            };
+            *id = match req.apply(&mut wecker) {
+                AlarmResponse::Added { id } => id,
+                other => panic!("unexpected response: {:?}", other),
+            };
+        }
+
+        let req = AlarmRequest::Add { time: at(NOW), repeat: Repeat::Once };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Error(AlarmError::Full));
+
+        match (AlarmRequest::List { start: 1 }).apply(&mut wecker) {
+            AlarmResponse::Listing { total, start, alarms } => {
+                assert_eq!(total, 4);
+                assert_eq!(start, 1);
+                let listed: Vec<u16, U4> = alarms.iter().map(|alarm| alarm.id).collect();
+                assert_eq!(&listed[..], &ids[1..]);
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let req = AlarmRequest::Remove { id: ids[2] };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Removed { id: ids[2] });
+        assert_eq!(
+            req.apply(&mut wecker),
+            AlarmResponse::Error(AlarmError::UnknownAlarm)
+        );
+
+        match (AlarmRequest::List { start: 0 }).apply(&mut wecker) {
+            AlarmResponse::Listing { total, alarms, .. } => {
+                assert_eq!(total, 3);
+                assert!(alarms.iter().all(|alarm| alarm.id != ids[2]));
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_and_errors() {
+        let mut wecker: Wecker<U4> = Wecker::new(UnixTimestamp(NOW));
+        let sunday = Repeat::Weekly(DayFlags::SUNDAY.bits());
+
+        let req = AlarmRequest::Add { time: at(NOW), repeat: sunday };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Added { id: 0 });
+
+        let req = AlarmRequest::Add { time: at(NOW), repeat: Repeat::Weekly(DayFlags::MONDAY.bits()) };
+        assert_eq!(
+            req.apply(&mut wecker),
+            AlarmResponse::Error(AlarmError::NotOnRepeat)
+        );
+
+        let req = AlarmRequest::Add { time: at(NOW), repeat: Repeat::Weekly(0x80) };
+        assert_eq!(
+            req.apply(&mut wecker),
+            AlarmResponse::Error(AlarmError::InvalidRepeat)
+        );
+
+        let req = AlarmRequest::Add { time: at(NOW), repeat: Repeat::MonthlyOnDay(32) };
+        assert_eq!(
+            req.apply(&mut wecker),
+            AlarmResponse::Error(AlarmError::InvalidRepeat)
+        );
+
+        let req = AlarmRequest::Update { id: 0, time: at(NOW + 86400), repeat: Repeat::Once };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Updated { id: 0 });
+
+        let req = AlarmRequest::Update { id: 7, time: at(NOW), repeat: Repeat::Once };
+        assert_eq!(
+            req.apply(&mut wecker),
+            AlarmResponse::Error(AlarmError::UnknownAlarm)
+        );
+
+        match (AlarmRequest::List { start: 0 }).apply(&mut wecker) {
+            AlarmResponse::Listing { alarms, .. } => {
+                assert_eq!(
+                    &alarms[..],
+                    &[AlarmInfo { id: 0, time: at(NOW + 86400), repeat: Repeat::Once }]
+                );
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn listed_schedule_survives_update() {
+        let mut wecker: Wecker<U4> = Wecker::new(UnixTimestamp(NOW));
+
+        // The last Sunday of every month, starting today
+        let monthly = Repeat::MonthlyOnWeekday { week: 5, weekday: 7 };
+        let req = AlarmRequest::Add { time: at(NOW + 60), repeat: monthly };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Added { id: 0 });
+
+        let listed = match (AlarmRequest::List { start: 0 }).apply(&mut wecker) {
+            AlarmResponse::Listing { alarms, .. } => alarms[0],
+            other => panic!("unexpected response: {:?}", other),
+        };
+        assert_eq!(listed.repeat, monthly);
+
+        // Sending the listed alarm back keeps its schedule
+        let req = AlarmRequest::Update {
+            id: listed.id,
+            time: listed.time,
+            repeat: listed.repeat,
+        };
+        assert_eq!(req.apply(&mut wecker), AlarmResponse::Updated { id: 0 });
+
+        match (AlarmRequest::List { start: 0 }).apply(&mut wecker) {
+            AlarmResponse::Listing { alarms, .. } => assert_eq!(&alarms[..], &[listed]),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+}
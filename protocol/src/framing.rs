@@ -0,0 +1,390 @@
+//! COBS framing of messages for transmission over a byte stream, such
+//! as a UART
+//!
+//! Each frame consists of the payload, followed by a two byte CRC trailer
+//! (CRC-16/CCITT-FALSE, little endian). The payload and trailer are COBS
+//! encoded, and the frame is terminated with a single `0x00` byte. As
+//! `0x00` never appears inside of a COBS encoded frame, a receiver can
+//! always resynchronize at the next zero byte after corrupted or
+//! truncated input.
+
+use cobs::{decode_in_place, max_encoding_length, CobsEncoder};
+use generic_array::{ArrayLength, GenericArray};
+
+pub use generic_array::typenum::consts;
+
+use crate::{Envelope, Error};
+
+/// The size of the CRC trailer appended to each payload
+pub const CRC_LEN: usize = 2;
+
+/// The largest serialized envelope that `encode_frame` will handle
+pub const MAX_PAYLOAD_LEN: usize = 255;
+
+/// Errors that may occur when encoding or decoding a frame
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameError {
+    /// The provided output buffer is too small to hold the frame
+    BufferFull,
+
+    /// The envelope could not be serialized
+    Encode(Error),
+
+    /// The frame was not validly COBS encoded
+    Cobs,
+
+    /// The frame was too short to contain a CRC trailer
+    Truncated,
+
+    /// The CRC trailer did not match the payload
+    Crc,
+
+    /// The frame was larger than the decoder's buffer, and was discarded
+    Overflow,
+}
+
+/// The number of bytes needed to hold a frame with a payload of `payload_len`
+/// bytes, including the CRC trailer and zero terminator
+pub fn max_frame_len(payload_len: usize) -> usize {
+    max_encoding_length(payload_len + CRC_LEN) + 1
+}
+
+/// Calculate the CRC-16/CCITT-FALSE of the given data
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if (crc & 0x8000) != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Frame an already serialized payload. The used portion of the output
+/// buffer, including the zero terminator, is returned
+pub fn encode_raw<'b>(payload: &[u8], out: &'b mut [u8]) -> Result<&'b mut [u8], FrameError> {
+    if out.len() < max_frame_len(payload.len()) {
+        return Err(FrameError::BufferFull);
+    }
+
+    let crc = crc16(payload).to_le_bytes();
+
+    let mut enc = CobsEncoder::new(out);
+    enc.push(payload).map_err(|_| FrameError::BufferFull)?;
+    enc.push(&crc).map_err(|_| FrameError::BufferFull)?;
+    let len = enc.finalize().map_err(|_| FrameError::BufferFull)?;
+
+    out[len] = 0x00;
+    Ok(&mut out[..=len])
+}
+
+/// Serialize and frame an envelope. The used portion of the output
+/// buffer, including the zero terminator, is returned
+pub fn encode_frame<'b>(envelope: &Envelope, out: &'b mut [u8]) -> Result<&'b mut [u8], FrameError> {
+    let mut scratch = [0u8; MAX_PAYLOAD_LEN];
+    let payload = envelope.encode(&mut scratch).map_err(FrameError::Encode)?;
+    encode_raw(payload, out)
+}
+
+/// The result of feeding a chunk of data to a `FrameDecoder`
+#[derive(Debug, PartialEq, Eq)]
+pub enum FeedResult<'a, 'b> {
+    /// All data was consumed without completing a frame
+    Consumed,
+
+    /// A frame was completed. The payload (without CRC) is returned, along
+    /// with any data that was not yet consumed
+    Frame {
+        payload: &'a [u8],
+        remaining: &'b [u8],
+    },
+
+    /// A frame was completed, but was invalid. The frame has been discarded,
+    /// and decoding will resume with the remaining data
+    Error {
+        error: FrameError,
+        remaining: &'b [u8],
+    },
+}
+
+/// A streaming frame decoder, with a fixed size accumulator of `N` bytes.
+/// `N` must be large enough to hold the largest *encoded* frame expected,
+/// not including the zero terminator
+pub struct FrameDecoder<N>
+where
+    N: ArrayLength<u8>,
+{
+    buf: GenericArray<u8, N>,
+    idx: usize,
+    overflowed: bool,
+    complete: bool,
+}
+
+impl<N> FrameDecoder<N>
+where
+    N: ArrayLength<u8>,
+{
+    /// Create a new, empty decoder
+    pub fn new() -> Self {
+        FrameDecoder {
+            buf: GenericArray::default(),
+            idx: 0,
+            overflowed: false,
+            complete: false,
+        }
+    }
+
+    /// Discard any partially received frame
+    pub fn reset(&mut self) {
+        self.idx = 0;
+        self.overflowed = false;
+        self.complete = false;
+    }
+
+    /// Push a single byte into the decoder. Returns `None` until a frame
+    /// has been completed by a zero byte. Empty frames are ignored.
+    ///
+    /// The returned payload is valid until the next byte is pushed.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        self.step(byte)
+            .map(move |res| res.map(move |len| &self.buf[..len]))
+    }
+
+    /// Feed a chunk of data into the decoder, stopping at the end of the
+    /// first completed frame (if any)
+    pub fn feed<'a, 'b>(&'a mut self, data: &'b [u8]) -> FeedResult<'a, 'b> {
+        for (idx, byte) in data.iter().enumerate() {
+            let remaining = &data[idx + 1..];
+            match self.step(*byte) {
+                None => {}
+                Some(Ok(len)) => {
+                    return FeedResult::Frame {
+                        payload: &self.buf[..len],
+                        remaining,
+                    }
+                }
+                Some(Err(error)) => return FeedResult::Error { error, remaining },
+            }
+        }
+
+        FeedResult::Consumed
+    }
+
+    /// Process a single byte. If a frame was completed, the length of the
+    /// decoded payload at the start of the buffer is returned
+    fn step(&mut self, byte: u8) -> Option<Result<usize, FrameError>> {
+        if self.complete {
+            self.reset();
+        }
+
+        if byte != 0x00 {
+            if self.overflowed {
+                return None;
+            }
+
+            match self.buf.get_mut(self.idx) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.idx += 1;
+                }
+                None => self.overflowed = true,
+            }
+            return None;
+        }
+
+        if self.overflowed {
+            self.reset();
+            return Some(Err(FrameError::Overflow));
+        }
+
+        if self.idx == 0 {
+            return None;
+        }
+
+        // The buffer is cleared on the next call to `step`
+        self.complete = true;
+        Some(self.finish())
+    }
+
+    fn finish(&mut self) -> Result<usize, FrameError> {
+        let len = decode_in_place(&mut self.buf[..self.idx]).map_err(|_| FrameError::Cobs)?;
+
+        if len < CRC_LEN {
+            return Err(FrameError::Truncated);
+        }
+
+        let (payload, trailer) = self.buf[..len].split_at(len - CRC_LEN);
+        if crc16(payload).to_le_bytes() != trailer {
+            return Err(FrameError::Crc);
+        }
+
+        Ok(payload.len())
+    }
+}
+
+impl<N> Default for FrameDecoder<N>
+where
+    N: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, DemoMessage, Message, NodeId};
+    use super::consts::*;
+
+    // Payload `[0x11, 0x00, 0x22]`, CRC `0xBCEF`
+    const FIXTURE: &[u8] = &[0x02, 0x11, 0x04, 0x22, 0xEF, 0xBC, 0x00];
+
+    fn decode_all(dec: &mut FrameDecoder<U32>, mut data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut out = Vec::new();
+        loop {
+            match dec.feed(data) {
+                FeedResult::Consumed => return out,
+                FeedResult::Frame { payload, remaining } => {
+                    out.push(Ok(payload.to_vec()));
+                    data = remaining;
+                }
+                FeedResult::Error { error, remaining } => {
+                    out.push(Err(error));
+                    data = remaining;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn encode_fixture() {
+        let mut buf = [0u8; 16];
+        let used = encode_raw(&[0x11, 0x00, 0x22], &mut buf).unwrap();
+        assert_eq!(used, FIXTURE);
+
+        let mut small = [0u8; 6];
+        assert_eq!(
+            encode_raw(&[0x11, 0x00, 0x22], &mut small),
+            Err(FrameError::BufferFull)
+        );
+    }
+
+    #[test]
+    fn decode_fixture_bytewise() {
+        let mut dec: FrameDecoder<U32> = FrameDecoder::new();
+
+        for byte in &FIXTURE[..FIXTURE.len() - 1] {
+            assert_eq!(dec.push(*byte), None);
+        }
+        assert_eq!(dec.push(0x00), Some(Ok(&[0x11, 0x00, 0x22][..])));
+    }
+
+    #[test]
+    fn decode_stream() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&[0x00, 0x00]);
+        stream.extend_from_slice(FIXTURE);
+        stream.extend_from_slice(FIXTURE);
+
+        let mut dec = FrameDecoder::new();
+        assert_eq!(
+            decode_all(&mut dec, &stream),
+            vec![Ok(vec![0x11, 0x00, 0x22]), Ok(vec![0x11, 0x00, 0x22])]
+        );
+    }
+
+    #[test]
+    fn resync_after_corruption() {
+        let mut corrupt = FIXTURE.to_vec();
+        corrupt[3] ^= 0x01;
+
+        let mut stream = corrupt;
+        stream.extend_from_slice(FIXTURE);
+
+        let mut dec = FrameDecoder::new();
+        assert_eq!(
+            decode_all(&mut dec, &stream),
+            vec![Err(FrameError::Crc), Ok(vec![0x11, 0x00, 0x22])]
+        );
+    }
+
+    #[test]
+    fn resync_after_truncation() {
+        // A frame cut off partway through, followed by a complete frame
+        let mut stream = FIXTURE[..3].to_vec();
+        stream.extend_from_slice(FIXTURE);
+        stream.extend_from_slice(FIXTURE);
+
+        let mut dec = FrameDecoder::new();
+        let frames = decode_all(&mut dec, &stream);
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_err());
+        assert_eq!(frames[1], Ok(vec![0x11, 0x00, 0x22]));
+
+        // Too short to hold a CRC
+        let mut dec = FrameDecoder::new();
+        assert_eq!(
+            decode_all(&mut dec, &[0x02, 0x11, 0x00]),
+            vec![Err(FrameError::Truncated)]
+        );
+
+        // Invalid COBS (code byte points past the end of the frame)
+        let mut dec = FrameDecoder::new();
+        assert_eq!(
+            decode_all(&mut dec, &[0x09, 0x11, 0x22, 0x00]),
+            vec![Err(FrameError::Cobs)]
+        );
+    }
+
+    #[test]
+    fn resync_after_overflow() {
+        let mut stream = vec![0x55; 40];
+        stream.push(0x00);
+        stream.extend_from_slice(FIXTURE);
+
+        let mut dec = FrameDecoder::new();
+        assert_eq!(
+            decode_all(&mut dec, &stream),
+            vec![Err(FrameError::Overflow), Ok(vec![0x11, 0x00, 0x22])]
+        );
+    }
+
+    #[test]
+    fn envelope_round_trip() {
+        let env = Envelope::new(
+            7,
+            NodeId(0x0001),
+            NodeId(0x0002),
+            Message::Demo(DemoMessage {
+                small: 0,
+                medium: 0,
+                large: 0,
+                text_bytes: "framed",
+            }),
+        );
+
+        let mut buf = [0u8; 64];
+        let frame = encode_frame(&env, &mut buf).unwrap();
+        assert_eq!(frame.iter().filter(|b| **b == 0).count(), 1);
+
+        let mut dec: FrameDecoder<U64> = FrameDecoder::new();
+        match dec.feed(frame) {
+            FeedResult::Frame { payload, remaining } => {
+                assert!(remaining.is_empty());
+                assert_eq!(decode(payload), Ok(env));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,11 +1,392 @@
-#![no_std]
+//! Wire protocol shared by all nodes on the network
+//!
+//! Every message is sent inside of an envelope. The envelope consists of a
+//! small fixed `Header`, describing the protocol version, the kind of message,
+//! a sequence number, and the source/destination node, followed by the
+//! message body itself. Both are serialized with `postcard`.
 
+#![cfg_attr(not(test), no_std)]
+
+pub mod alarm;
+pub mod framing;
+pub mod sensor;
+pub mod time;
+
+use core::convert::TryFrom;
+
+use postcard::{take_from_bytes, to_slice};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::alarm::{AlarmRequest, AlarmResponse};
+use crate::sensor::SensorReading;
+use crate::time::{TimeSyncRequest, TimeSyncResponse};
+
+/// The version of the protocol implemented by this crate. Envelopes
+/// with any other version are rejected when decoding
+pub const PROTOCOL_VERSION: u8 = 1;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub struct DemoMessage<'a> {
     pub small:  u8,
     pub medium: u32,
     pub large: u64,
     pub text_bytes: &'a str,
 }
+
+/// The address of a node on the network
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct NodeId(pub u16);
+
+impl NodeId {
+    /// The address used to send a message to all nodes
+    pub const BROADCAST: NodeId = NodeId(0xFFFF);
+
+    /// Is this the broadcast address?
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+}
+
+/// The kind of message contained in an envelope. The numeric value
+/// of each kind is what is sent over the wire, and must never change
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum MessageKind {
+    Demo = 0,
+    TimeSyncRequest = 1,
+    TimeSyncResponse = 2,
+    AlarmRequest = 3,
+    AlarmResponse = 4,
+    SensorReading = 5,
+}
+
+impl TryFrom<u8> for MessageKind {
+    type Error = Error;
+
+    fn try_from(val: u8) -> Result<MessageKind, Error> {
+        match val {
+            0 => Ok(MessageKind::Demo),
+            1 => Ok(MessageKind::TimeSyncRequest),
+            2 => Ok(MessageKind::TimeSyncResponse),
+            3 => Ok(MessageKind::AlarmRequest),
+            4 => Ok(MessageKind::AlarmResponse),
+            5 => Ok(MessageKind::SensorReading),
+            other => Err(Error::UnknownKind(other)),
+        }
+    }
+}
+
+/// All message bodies that may be sent over the network
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Message<'a> {
+    Demo(DemoMessage<'a>),
+    TimeSyncRequest(TimeSyncRequest),
+    TimeSyncResponse(TimeSyncResponse),
+    AlarmRequest(AlarmRequest),
+    AlarmResponse(AlarmResponse),
+    SensorReading(SensorReading),
+}
+
+impl<'a> Message<'a> {
+    /// The kind of this message, as sent over the wire
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::Demo(_) => MessageKind::Demo,
+            Message::TimeSyncRequest(_) => MessageKind::TimeSyncRequest,
+            Message::TimeSyncResponse(_) => MessageKind::TimeSyncResponse,
+            Message::AlarmRequest(_) => MessageKind::AlarmRequest,
+            Message::AlarmResponse(_) => MessageKind::AlarmResponse,
+            Message::SensorReading(_) => MessageKind::SensorReading,
+        }
+    }
+}
+
+/// The fixed header sent at the start of every envelope
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Header {
+    pub version: u8,
+    pub kind: u8,
+    pub seq: u32,
+    pub src: NodeId,
+    pub dst: NodeId,
+}
+
+/// A message, along with its routing information
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Envelope<'a> {
+    pub seq: u32,
+    pub src: NodeId,
+    pub dst: NodeId,
+    pub message: Message<'a>,
+}
+
+/// Errors that may occur when encoding or decoding an envelope
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// The envelope was sent with an unsupported protocol version
+    UnsupportedVersion(u8),
+
+    /// The message kind is not known to this version of the protocol
+    UnknownKind(u8),
+
+    /// The header could not be decoded
+    MalformedHeader,
+
+    /// The message body could not be decoded as its declared kind
+    MalformedBody(MessageKind),
+
+    /// Extra data remained after the end of the message body
+    TrailingData,
+
+    /// The provided buffer is too small to hold the encoded envelope
+    BufferFull,
+}
+
+impl<'a> Envelope<'a> {
+    /// Create a new envelope around a message
+    pub fn new(seq: u32, src: NodeId, dst: NodeId, message: Message<'a>) -> Self {
+        Envelope {
+            seq,
+            src,
+            dst,
+            message,
+        }
+    }
+
+    /// The header that will be sent with this envelope
+    pub fn header(&self) -> Header {
+        Header {
+            version: PROTOCOL_VERSION,
+            kind: self.message.kind() as u8,
+            seq: self.seq,
+            src: self.src,
+            dst: self.dst,
+        }
+    }
+
+    /// Serialize the envelope into the given buffer. The used portion
+    /// of the buffer is returned
+    pub fn encode<'b>(&self, buf: &'b mut [u8]) -> Result<&'b mut [u8], Error> {
+        let hdr_len = to_slice(&self.header(), buf)
+            .map_err(|_| Error::BufferFull)?
+            .len();
+
+        let body_buf = &mut buf[hdr_len..];
+        let body_len = match &self.message {
+            Message::Demo(msg) => to_slice(msg, body_buf),
+            Message::TimeSyncRequest(msg) => to_slice(msg, body_buf),
+            Message::TimeSyncResponse(msg) => to_slice(msg, body_buf),
+            Message::AlarmRequest(msg) => to_slice(msg, body_buf),
+            Message::AlarmResponse(msg) => to_slice(msg, body_buf),
+            Message::SensorReading(msg) => to_slice(msg, body_buf),
+        }
+        .map_err(|_| Error::BufferFull)?
+        .len();
+
+        Ok(&mut buf[..hdr_len + body_len])
+    }
+}
+
+/// Decode only the header of an envelope, returning the header and the
+/// undecoded message body. This is useful for routing messages without
+/// needing to understand their contents
+pub fn decode_header(bytes: &[u8]) -> Result<(Header, &[u8]), Error> {
+    let (header, body) =
+        take_from_bytes::<Header>(bytes).map_err(|_| Error::MalformedHeader)?;
+
+    if header.version != PROTOCOL_VERSION {
+        return Err(Error::UnsupportedVersion(header.version));
+    }
+
+    Ok((header, body))
+}
+
+/// Decode a complete envelope, including the message body
+pub fn decode<'a>(bytes: &'a [u8]) -> Result<Envelope<'a>, Error> {
+    let (header, body) = decode_header(bytes)?;
+    let kind = MessageKind::try_from(header.kind)?;
+
+    let message = match kind {
+        MessageKind::Demo => Message::Demo(decode_body(kind, body)?),
+        MessageKind::TimeSyncRequest => Message::TimeSyncRequest(decode_body(kind, body)?),
+        MessageKind::TimeSyncResponse => Message::TimeSyncResponse(decode_body(kind, body)?),
+        MessageKind::AlarmRequest => Message::AlarmRequest(decode_body(kind, body)?),
+        MessageKind::AlarmResponse => Message::AlarmResponse(decode_body(kind, body)?),
+        MessageKind::SensorReading => Message::SensorReading(decode_body(kind, body)?),
+    };
+
+    Ok(Envelope {
+        seq: header.seq,
+        src: header.src,
+        dst: header.dst,
+        message,
+    })
+}
+
+fn decode_body<'a, T>(kind: MessageKind, bytes: &'a [u8]) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let (body, remainder) =
+        take_from_bytes::<T>(bytes).map_err(|_| Error::MalformedBody(kind))?;
+
+    if !remainder.is_empty() {
+        return Err(Error::TrailingData);
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demo_envelope() -> Envelope<'static> {
+        Envelope::new(
+            42,
+            NodeId(0x1234),
+            NodeId::BROADCAST,
+            Message::Demo(DemoMessage {
+                small: 0x01,
+                medium: 0x0203_0405,
+                large: 0x0607_0809_0A0B_0C0D,
+                text_bytes: "hello",
+            }),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let env = demo_envelope();
+        let mut buf = [0u8; 64];
+        let used = env.encode(&mut buf).unwrap();
+
+        assert_eq!(decode(used), Ok(env));
+    }
+
+    #[test]
+    fn header_only() {
+        let mut buf = [0u8; 64];
+        let used = demo_envelope().encode(&mut buf).unwrap();
+        let (header, body) = decode_header(used).unwrap();
+
+        assert_eq!(header.kind, MessageKind::Demo as u8);
+        assert_eq!(header.seq, 42);
+        assert_eq!(header.src, NodeId(0x1234));
+        assert!(header.dst.is_broadcast());
+        assert!(!body.is_empty());
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut buf = [0u8; 64];
+        let len = demo_envelope().encode(&mut buf).unwrap().len();
+
+        // The version is the first byte of the header
+        let mut bad_ver = buf;
+        bad_ver[0] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            decode(&bad_ver[..len]),
+            Err(Error::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        // The kind is the second byte of the header
+        let mut bad_kind = buf;
+        bad_kind[1] = 0xEE;
+        assert_eq!(decode(&bad_kind[..len]), Err(Error::UnknownKind(0xEE)));
+
+        assert_eq!(decode(&[]), Err(Error::MalformedHeader));
+        assert_eq!(
+            decode(&buf[..len - 1]),
+            Err(Error::MalformedBody(MessageKind::Demo))
+        );
+        assert_eq!(decode(&buf[..len + 1]), Err(Error::TrailingData));
+    }
+
+    #[test]
+    fn time_sync_round_trip() {
+        use crate::time::Timestamp;
+
+        let env = Envelope::new(
+            3,
+            NodeId(0x0000),
+            NodeId(0x1234),
+            Message::TimeSyncResponse(TimeSyncResponse {
+                originate: Timestamp { seconds: 1554041486, nanos: 1 },
+                receive: Timestamp { seconds: 1554041487, nanos: 2 },
+                transmit: Timestamp { seconds: -1, nanos: 999_999_999 },
+            }),
+        );
+
+        let mut buf = [0u8; 64];
+        let used = env.encode(&mut buf).unwrap();
+        assert_eq!(decode(used), Ok(env));
+    }
+
+    #[test]
+    fn alarm_round_trip() {
+        use crate::alarm::{AlarmError, AlarmInfo, Repeat};
+        use crate::time::Timestamp;
+
+        let repeats = [
+            Repeat::Weekly(0x7F),
+            Repeat::Interval(90 * 60),
+            Repeat::MonthlyOnWeekday { week: 5, weekday: 7 },
+            Repeat::Yearly { month: 2, day: 29 },
+        ];
+        let time = Timestamp { seconds: 1554041486, nanos: 0 };
+
+        let mut alarms = heapless::Vec::new();
+        for (id, repeat) in repeats.iter().enumerate() {
+            let info = AlarmInfo { id: id as u16, time, repeat: *repeat };
+            alarms.push(info).unwrap();
+        }
+
+        let messages = [
+            Message::AlarmRequest(AlarmRequest::Remove { id: 3 }),
+            Message::AlarmRequest(AlarmRequest::Update { id: 2, time, repeat: Repeat::MonthlyOnDay(31) }),
+            Message::AlarmResponse(AlarmResponse::Listing { total: 9, start: 4, alarms }),
+            Message::AlarmResponse(AlarmResponse::Error(AlarmError::UnknownAlarm)),
+        ];
+
+        for message in messages.iter() {
+            let env = Envelope::new(1, NodeId(0x1234), NodeId(0x0000), message.clone());
+            let mut buf = [0u8; 128];
+            let used = env.encode(&mut buf).unwrap();
+            assert_eq!(decode(used), Ok(env));
+        }
+    }
+
+    #[test]
+    fn sensor_round_trip() {
+        use crate::sensor::Quantity;
+        use crate::time::Timestamp;
+
+        let readings = [
+            SensorReading::die_temperature(-1_025, None),
+            SensorReading {
+                quantity: Quantity::DieTemperature,
+                value: 2_375,
+                time: Some(Timestamp { seconds: 1554041486, nanos: 500_000_000 }),
+            },
+        ];
+
+        for reading in readings.iter() {
+            let env = Envelope::new(
+                7,
+                NodeId(0x1234),
+                NodeId::BROADCAST,
+                Message::SensorReading(*reading),
+            );
+            let mut buf = [0u8; 64];
+            let used = env.encode(&mut buf).unwrap();
+            assert_eq!(decode(used), Ok(env));
+        }
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buf = [0u8; 8];
+        assert_eq!(demo_envelope().encode(&mut buf), Err(Error::BufferFull));
+    }
+}
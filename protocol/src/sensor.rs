@@ -0,0 +1,38 @@
+//! Measurements reported by sensor nodes
+//!
+//! A node broadcasts a `SensorReading` for every measurement it takes. Values
+//! are sent as integers, in the fixed unit of their `Quantity`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::time::Timestamp;
+
+/// What was measured. These are sent over the wire by variant position, so
+/// new variants must only be added at the end
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum Quantity {
+    /// The temperature of the radio's microcontroller, in hundredths of a
+    /// degree Celsius
+    DieTemperature,
+}
+
+/// A single measurement of a node
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct SensorReading {
+    pub quantity: Quantity,
+    /// The measured value, in the unit of the `quantity`
+    pub value: i32,
+    /// When the measurement was taken, if the node's clock is synchronized
+    pub time: Option<Timestamp>,
+}
+
+impl SensorReading {
+    /// A die temperature reading, in hundredths of a degree Celsius
+    pub fn die_temperature(centidegrees: i32, time: Option<Timestamp>) -> Self {
+        SensorReading {
+            quantity: Quantity::DieTemperature,
+            value: centidegrees,
+            time,
+        }
+    }
+}
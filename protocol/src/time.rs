@@ -0,0 +1,101 @@
+//! Time synchronization ("NTP-lite") messages
+//!
+//! A node sends a `TimeSyncRequest` containing its current time. The gateway
+//! replies with a `TimeSyncResponse`, echoing the node's time, and adding the
+//! time the request was received and the time the response was sent. See
+//! `uhr::sync` for how the node uses these to correct its clock.
+
+use serde::{Deserialize, Serialize};
+
+/// A point in time, as seconds and nanoseconds since the Unix epoch (UTC)
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Timestamp {
+    pub seconds: i64,
+    pub nanos: u32,
+}
+
+/// A request for the current time, sent from a node to the gateway
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct TimeSyncRequest {
+    /// Node time when the request was sent
+    pub originate: Timestamp,
+}
+
+/// The gateway's response to a `TimeSyncRequest`
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct TimeSyncResponse {
+    /// Node time when the request was sent, copied from the request
+    pub originate: Timestamp,
+    /// Gateway time when the request was received
+    pub receive: Timestamp,
+    /// Gateway time when the response was sent
+    pub transmit: Timestamp,
+}
+
+#[cfg(feature = "uhr")]
+mod uhr_support {
+    use super::*;
+    use uhr::{SyncSample, Uhr, UnixTimestamp};
+
+    impl From<Uhr> for Timestamp {
+        fn from(clock: Uhr) -> Timestamp {
+            Timestamp {
+                seconds: clock.timestamp().0,
+                nanos: clock.subsec_nanos(),
+            }
+        }
+    }
+
+    impl From<Timestamp> for Uhr {
+        fn from(ts: Timestamp) -> Uhr {
+            Uhr::from_timestamp_nanos(UnixTimestamp(ts.seconds), ts.nanos)
+        }
+    }
+
+    impl TimeSyncResponse {
+        /// Combine the response with the local time it was received at,
+        /// to be used for correcting the local clock
+        pub fn sample(&self, destination: Uhr) -> SyncSample {
+            SyncSample {
+                originate: self.originate.into(),
+                receive: self.receive.into(),
+                transmit: self.transmit.into(),
+                destination,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "uhr"))]
+mod tests {
+    use super::*;
+    use uhr::{Uhr, UnixTimestamp};
+
+    #[test]
+    fn uhr_conversion() {
+        let ts = Timestamp {
+            seconds: 1554041486,
+            nanos: 125_000_000,
+        };
+        let clock = Uhr::from(ts);
+
+        assert_eq!(clock.timestamp(), UnixTimestamp(1554041486));
+        assert_eq!(clock.subsec_nanos(), 125_000_000);
+        assert_eq!(Timestamp::from(clock), ts);
+    }
+
+    #[test]
+    fn sample_from_response() {
+        let resp = TimeSyncResponse {
+            originate: Timestamp { seconds: 100, nanos: 0 },
+            receive: Timestamp { seconds: 200, nanos: 0 },
+            transmit: Timestamp { seconds: 200, nanos: 0 },
+        };
+
+        let mut clock = Uhr::from(UnixTimestamp(100));
+        let result = clock.synchronize(&resp.sample(clock));
+
+        assert_eq!(result.offset_nanos, 100_000_000_000);
+        assert_eq!(clock.timestamp(), UnixTimestamp(200));
+    }
+}
@@ -32,19 +32,19 @@ use dwm1001::{
     block_timeout,
     embedded_hal::timer::CountDown,
 };
-use heapless::{String, Vec, consts::*};
+use heapless::{String, consts::*};
 use nb::{
     block,
     Error as NbError,
 };
 use rtfm::app;
-use postcard::{from_bytes, to_vec};
 
 // NOTE: Panic Provider
 use panic_ramdump as _;
 
 // Workspace dependencies
-use protocol::DemoMessage;
+use nrf52_hal_backports::temp::{Temp, TempExt};
+use protocol::{decode, sensor::SensorReading, Envelope, Message, NodeId};
 use uarte_logger::Logger;
 use utils::delay;
 use embedded_timeout_macros::TimeoutError;
@@ -66,6 +66,8 @@ const APP: () = {
                           > = ();
     static mut DW_RST_PIN: DW_RST                   = ();
     static mut RANDOM:     Rng                      = ();
+    static mut NODE_ID:    NodeId                   = ();
+    static mut TEMP_SENSOR: Temp                    = ();
 
     #[init]
     fn init() {
@@ -119,6 +121,8 @@ const APP: () = {
             }
         }
 
+        NODE_ID = NodeId(saddr.0);
+        TEMP_SENSOR = device.TEMP.constrain();
         RANDOM = rng;
         DW_RST_PIN = rst_pin;
         DW1000 = dw1000;
@@ -127,24 +131,37 @@ const APP: () = {
         LED_RED_1 = pins.p0_14.degrade().into_push_pull_output(Level::High);
     }
 
-    #[idle(resources = [TIMER, LED_RED_1, LOGGER, RANDOM, DW1000])]
+    #[idle(resources = [TIMER, LED_RED_1, LOGGER, RANDOM, DW1000, NODE_ID, TEMP_SENSOR])]
     fn idle() -> ! {
         let mut scratch = [0u8; 4096];
+        let mut tx_buf = [0u8; 1024];
+        let mut seq: u32 = 0;
         loop {
             let jitter = resources.RANDOM.random_u32() % MAX_WAIT_JITTER_US;
             resources.TIMER.start(NOMINAL_WAIT_US + jitter);
-            let message = rand_msg(&mut resources.RANDOM);
-            let serd: Vec<u8, U1024> = to_vec(&message).expect("ser fail");
+            // The clock isn't synchronized yet, so readings aren't timestamped
+            let temperature = resources.TEMP_SENSOR.measure();
+            let message = Envelope::new(
+                seq,
+                *resources.NODE_ID,
+                NodeId::BROADCAST,
+                Message::SensorReading(SensorReading::die_temperature(
+                    temperature.centidegrees(),
+                    None,
+                )),
+            );
+            seq = seq.wrapping_add(1);
+            let serd = message.encode(&mut tx_buf).expect("ser fail");
 
             let mut tx_fut = resources.DW1000.send(
-                &serd,
+                serd,
                 Address::broadcast(&AddressMode::Short),
                 None
             ).expect("tx fail");
 
             match block_timeout!(&mut *resources.TIMER, tx_fut.wait()) {
                 Ok(_) => {
-                    resources.LOGGER.log("Sent hello").expect("hello fail");
+                    resources.LOGGER.log("Sent reading").expect("reading fail");
                 },
                 _ => continue,
             };
@@ -155,18 +172,33 @@ const APP: () = {
 
             match block_timeout!(&mut *resources.TIMER, rx_fut.wait(&mut scratch)) {
                 Ok(msg) => {
-                    match from_bytes::<DemoMessage>(msg.frame.payload) {
-                        Ok(val) => {
+                    match decode(msg.frame.payload) {
+                        Ok(Envelope { src, seq: rx_seq, message: Message::Demo(val), .. }) => {
                             let mut out: String<U256> = String::new();
                             write!(&mut out, "got message! \r\n").unwrap();
+                            write!(&mut out, "from:  {:04X} (seq {})\r\n", src.0, rx_seq).unwrap();
                             write!(&mut out, "small: {:016X}\r\n", val.small).unwrap();
                             write!(&mut out, "med:   {:016X}\r\n", val.medium).unwrap();
                             write!(&mut out, "large  {:016X}\r\n", val.large).unwrap();
                             write!(&mut out, "text: {}\r\n", &val.text_bytes).unwrap();
                             resources.LOGGER.log(&out).unwrap();
                         }
-                        _ => {
-                            resources.LOGGER.error("failed to deser").unwrap();
+                        Ok(Envelope { src, seq: rx_seq, message: Message::SensorReading(reading), .. }) => {
+                            let mut out: String<U256> = String::new();
+                            write!(&mut out, "got reading! \r\n").unwrap();
+                            write!(&mut out, "from:  {:04X} (seq {})\r\n", src.0, rx_seq).unwrap();
+                            write!(&mut out, "{:?}: {}\r\n", reading.quantity, reading.value).unwrap();
+                            resources.LOGGER.log(&out).unwrap();
+                        }
+                        Ok(Envelope { message, .. }) => {
+                            let mut out: String<U256> = String::new();
+                            write!(&mut out, "ignored {:?} message", message.kind()).unwrap();
+                            resources.LOGGER.log(&out).unwrap();
+                        }
+                        Err(error) => {
+                            let mut out: String<U256> = String::new();
+                            write!(&mut out, "failed to deser: {:?}", error).unwrap();
+                            resources.LOGGER.error(&out).unwrap();
                         }
                     }
 
@@ -187,19 +219,3 @@ const APP: () = {
         }
     }
 };
-
-
-pub fn rand_msg(rng: &mut Rng) -> DemoMessage<'static> {
-    let start = (rng.random_u32() % ((MEME.len() - 64) as u32)) as usize;
-    let len = ((rng.random_u32() % 63) + 1) as usize;
-
-    DemoMessage {
-        small: rng.random_u8(),
-        medium: rng.random_u32(),
-        large: rng.random_u64(),
-        text_bytes: &MEME[start..(start+len)],
-    }
-}
-
-
-pub const MEME: &str = "Did you ever hear the tragedy of Darth Plagueis The Wise? I thought not. It's not a story the Jedi would tell you. It's a Sith legend. Darth Plagueis was a Dark Lord of the Sith, so powerful and so wise he could use the Force to influence the midichlorians to create life... He had such a knowledge of the dark side that he could even keep the ones he cared about from dying. The dark side of the Force is a pathway to many abilities some consider to be unnatural. He became so powerful... the only thing he was afraid of was losing his power, which eventually, of course, he did. Unfortunately, he taught his apprentice everything he knew, then his apprentice killed him in his sleep. Ironic. He could save others from death, but not himself.";
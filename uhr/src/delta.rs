@@ -0,0 +1,166 @@
+//! Signed differences between two points in time
+//!
+//! `core::time::Duration` can not be negative, so it can not describe how far
+//! a clock must be moved backwards, or how long ago an event happened.
+
+use core::convert::TryFrom;
+use core::ops::{Add, Neg, Sub};
+use core::time::Duration;
+
+use crate::uhr::NANOS_PER_SEC;
+
+/// A signed amount of time, with nanosecond resolution. Any difference
+/// between two `Uhr`s may be represented
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, Hash, Default)]
+pub struct TimeDelta {
+    nanos: i128,
+}
+
+impl TimeDelta {
+    /// No time at all
+    pub const ZERO: TimeDelta = TimeDelta { nanos: 0 };
+
+    /// A difference of a number of nanoseconds
+    pub const fn from_nanos(nanos: i128) -> Self {
+        TimeDelta { nanos }
+    }
+
+    /// A difference of a number of seconds
+    pub const fn from_secs(secs: i64) -> Self {
+        TimeDelta {
+            nanos: secs as i128 * NANOS_PER_SEC,
+        }
+    }
+
+    /// The total number of nanoseconds
+    pub fn as_nanos(&self) -> i128 {
+        self.nanos
+    }
+
+    /// The number of whole seconds, rounded towards zero
+    pub fn as_secs(&self) -> i128 {
+        self.nanos / NANOS_PER_SEC
+    }
+
+    /// Is this a negative difference?
+    pub fn is_negative(&self) -> bool {
+        self.nanos < 0
+    }
+
+    /// The difference as a `Duration`. `None` is returned if the difference
+    /// is negative, or too large for a `Duration`
+    pub fn to_duration(&self) -> Option<Duration> {
+        let secs = u64::try_from(self.nanos.div_euclid(NANOS_PER_SEC)).ok()?;
+        Some(Duration::new(
+            secs,
+            self.nanos.rem_euclid(NANOS_PER_SEC) as u32,
+        ))
+    }
+
+    /// The size of the difference as a `Duration`, ignoring its sign. `None`
+    /// is returned if the difference is too large for a `Duration`
+    pub fn abs_duration(&self) -> Option<Duration> {
+        if self.is_negative() {
+            self.checked_neg()?.to_duration()
+        } else {
+            self.to_duration()
+        }
+    }
+
+    /// Add two differences, returning `None` on overflow
+    pub fn checked_add(&self, other: TimeDelta) -> Option<TimeDelta> {
+        self.nanos
+            .checked_add(other.nanos)
+            .map(TimeDelta::from_nanos)
+    }
+
+    /// Subtract two differences, returning `None` on overflow
+    pub fn checked_sub(&self, other: TimeDelta) -> Option<TimeDelta> {
+        self.nanos
+            .checked_sub(other.nanos)
+            .map(TimeDelta::from_nanos)
+    }
+
+    /// Negate the difference, returning `None` on overflow
+    pub fn checked_neg(&self) -> Option<TimeDelta> {
+        self.nanos.checked_neg().map(TimeDelta::from_nanos)
+    }
+}
+
+impl From<Duration> for TimeDelta {
+    fn from(dur: Duration) -> Self {
+        // Can't overflow, the largest `Duration` is about 2^94 nanoseconds
+        TimeDelta {
+            nanos: dur.as_nanos() as i128,
+        }
+    }
+}
+
+impl Add for TimeDelta {
+    type Output = TimeDelta;
+
+    fn add(self, other: TimeDelta) -> TimeDelta {
+        self.checked_add(other)
+            .expect("overflow when adding time deltas")
+    }
+}
+
+impl Sub for TimeDelta {
+    type Output = TimeDelta;
+
+    fn sub(self, other: TimeDelta) -> TimeDelta {
+        self.checked_sub(other)
+            .expect("overflow when subtracting time deltas")
+    }
+}
+
+impl Neg for TimeDelta {
+    type Output = TimeDelta;
+
+    fn neg(self) -> TimeDelta {
+        self.checked_neg()
+            .expect("overflow when negating time delta")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let delta = TimeDelta::from(Duration::new(3, 250));
+        assert_eq!(delta.as_nanos(), 3_000_000_250);
+        assert_eq!(delta.as_secs(), 3);
+        assert_eq!(delta.to_duration(), Some(Duration::new(3, 250)));
+
+        let back = -delta;
+        assert!(back.is_negative());
+        assert_eq!(back.as_secs(), -3);
+        assert_eq!(back.to_duration(), None);
+        assert_eq!(back.abs_duration(), Some(Duration::new(3, 250)));
+
+        assert_eq!(
+            TimeDelta::from_secs(-2) + TimeDelta::from_nanos(1),
+            TimeDelta::from_nanos(-1_999_999_999)
+        );
+        assert_eq!(
+            TimeDelta::ZERO - TimeDelta::from_secs(1),
+            TimeDelta::from_secs(-1)
+        );
+        assert!(TimeDelta::from_secs(-1) < TimeDelta::ZERO);
+
+        let max = TimeDelta::from(Duration::new(u64::MAX, 999_999_999));
+        assert_eq!(
+            max.to_duration(),
+            Some(Duration::new(u64::MAX, 999_999_999))
+        );
+        assert_eq!((max + TimeDelta::from_nanos(1)).to_duration(), None);
+        assert_eq!(
+            TimeDelta::from_nanos(i128::MAX).checked_add(TimeDelta::from_nanos(1)),
+            None
+        );
+        assert_eq!(TimeDelta::from_nanos(i128::MIN).checked_neg(), None);
+        assert_eq!(TimeDelta::from_nanos(i128::MIN).abs_duration(), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Keeping a clock accurate using an imperfect tick source
+//!
+//! A `Discipline` converts raw ticks of a timer, such as the RTC peripheral,
+//! into elapsed time. Fractions of a nanosecond are carried between ticks,
+//! so no error accumulates from rounding, even for tick rates that do not
+//! divide a second evenly.
+//!
+//! Crystals are not perfectly accurate, so the discipline also applies a
+//! frequency correction, in parts per billion. The correction is estimated
+//! from the offsets measured by successive time synchronization exchanges,
+//! so the clock stays accurate long after the last exchange.
+
+use core::convert::TryFrom;
+use core::time::Duration;
+
+use crate::sync::{SyncResult, SyncSample};
+use crate::uhr::{Uhr, NANOS_PER_SEC};
+
+/// The frequency of the low frequency clock, used by the RTC peripheral
+pub const LFCLK_HZ: u32 = 32_768;
+
+/// The largest frequency correction that will be applied, in parts per
+/// billion. Crystals are usually accurate to 20 to 50 parts per million
+pub const MAX_CORRECTION_PPB: i32 = 500_000;
+
+/// The time between synchronization exchanges needed to estimate the
+/// frequency correction, unless changed with `set_min_interval`
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+const PPB: i128 = 1_000_000_000;
+
+/// Converts ticks of a timer to elapsed time, with a frequency correction
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Discipline {
+    /// The nominal tick rate is `hz / divider` ticks per second
+    hz: u32,
+    divider: u32,
+    correction_ppb: i32,
+
+    /// Nanoseconds not yet reported, in units of `1 / (hz * PPB)`
+    remainder: i128,
+
+    min_interval: Duration,
+
+    /// Has an exchange been seen, to measure the next estimate from?
+    synced: bool,
+    /// Corrected time reported since the last estimate
+    elapsed_nanos: u64,
+    /// Sum of the offsets measured since the last estimate
+    offset_nanos: i64,
+}
+
+impl Discipline {
+    /// A tick source running at `hz / divider` ticks per second, without any
+    /// correction. `None` is returned if either value is zero
+    pub fn new(hz: u32, divider: u32) -> Option<Self> {
+        if hz == 0 || divider == 0 {
+            return None;
+        }
+
+        Some(Discipline {
+            hz,
+            divider,
+            correction_ppb: 0,
+            remainder: 0,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            synced: false,
+            elapsed_nanos: 0,
+            offset_nanos: 0,
+        })
+    }
+
+    /// The tick source of an RTC peripheral with the given prescaler
+    pub fn lfclk(prescaler: u16) -> Self {
+        // Can't fail, neither value is zero
+        Discipline::new(LFCLK_HZ, u32::from(prescaler) + 1).unwrap()
+    }
+
+    /// The frequency correction currently applied, in parts per billion.
+    /// Positive if the tick source is slow
+    pub fn correction_ppb(&self) -> i32 {
+        self.correction_ppb
+    }
+
+    /// Replace the frequency correction, such as with one saved before a
+    /// reset. The correction is limited to `MAX_CORRECTION_PPB`
+    pub fn set_correction_ppb(&mut self, ppb: i32) {
+        self.correction_ppb = ppb.clamp(-MAX_CORRECTION_PPB, MAX_CORRECTION_PPB);
+    }
+
+    /// Change the time between synchronization exchanges needed to update
+    /// the frequency correction. Longer intervals give better estimates, as
+    /// the network delay matters less
+    pub fn set_min_interval(&mut self, interval: Duration) {
+        self.min_interval = interval;
+    }
+
+    /// The corrected time taken by a number of ticks, since the previous
+    /// call. Any fraction of a nanosecond is carried over to the next call
+    pub fn ticks(&mut self, ticks: u32) -> Duration {
+        // Can't overflow, this is at most about 2^125
+        let scaled = i128::from(ticks)
+            * i128::from(self.divider)
+            * NANOS_PER_SEC
+            * (PPB + i128::from(self.correction_ppb))
+            + self.remainder;
+        let unit = i128::from(self.hz) * PPB;
+
+        self.remainder = scaled % unit;
+
+        // Only saturates for absurdly slow tick sources
+        let nanos = u64::try_from(scaled / unit).unwrap_or(u64::MAX);
+        self.elapsed_nanos = self.elapsed_nanos.saturating_add(nanos);
+        Duration::from_nanos(nanos)
+    }
+
+    /// Move a clock forwards by the corrected time taken by a number of ticks
+    pub fn advance(&mut self, clock: &mut Uhr, ticks: u32) {
+        let elapsed = self.ticks(ticks);
+        clock.increment(&elapsed);
+    }
+
+    /// Correct a clock using the result of a time synchronization exchange,
+    /// where `destination` was measured by the clock. Once `min_interval`
+    /// has passed since the previous estimate, the frequency correction is
+    /// updated from the offsets measured since
+    pub fn synchronize(&mut self, clock: &mut Uhr, sample: &SyncSample) -> SyncResult {
+        let result = clock.synchronize(sample);
+
+        // The offset of the first exchange says nothing about the tick rate,
+        // the clock may have been set to any time
+        if !self.synced {
+            self.synced = true;
+            self.restart_estimate();
+            return result;
+        }
+
+        self.offset_nanos = self.offset_nanos.saturating_add(result.offset_nanos);
+        if Duration::from_nanos(self.elapsed_nanos) < self.min_interval {
+            return result;
+        }
+
+        // A clock that fell behind by `offset` over `elapsed` is running slow
+        // by `offset / elapsed`, which must be added to the correction
+        let drift = i128::from(self.offset_nanos) * PPB / i128::from(self.elapsed_nanos);
+        let correction = (i128::from(self.correction_ppb) + drift).clamp(
+            i128::from(-MAX_CORRECTION_PPB),
+            i128::from(MAX_CORRECTION_PPB),
+        );
+
+        // Can't fail, limited to `MAX_CORRECTION_PPB`
+        self.correction_ppb = i32::try_from(correction).unwrap();
+        self.restart_estimate();
+        result
+    }
+
+    fn restart_estimate(&mut self) {
+        self.elapsed_nanos = 0;
+        self.offset_nanos = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimeDelta;
+    use gregor::UnixTimestamp;
+
+    #[test]
+    fn exact_remainders() {
+        // 32768 Hz does not divide a second into whole nanoseconds
+        let mut discipline = Discipline::lfclk(0);
+        let total: Duration = (0..LFCLK_HZ).map(|_| discipline.ticks(1)).sum();
+        assert_eq!(total, Duration::from_secs(1));
+        assert_eq!(discipline.ticks(3), Duration::from_nanos(91_552));
+
+        // A prescaler of 2 gives 10922.666... Hz
+        let mut discipline = Discipline::lfclk(2);
+        let total: Duration = (0..10_000).map(|_| discipline.ticks(7)).sum();
+        assert_eq!(
+            total,
+            Duration::from_nanos(70_000 * 3 * 1_000_000_000 / 32_768)
+        );
+
+        let mut discipline = Discipline::lfclk(0xFFF);
+        assert_eq!(discipline.ticks(1), Duration::from_millis(125));
+        assert_eq!(
+            discipline.ticks(u32::MAX),
+            Duration::from_millis(125 * u64::from(u32::MAX))
+        );
+
+        assert_eq!(Discipline::new(0, 1), None);
+        assert_eq!(Discipline::new(1, 0), None);
+    }
+
+    #[test]
+    fn correction() {
+        let mut discipline = Discipline::lfclk(0);
+        discipline.set_correction_ppb(-20_000);
+        assert_eq!(
+            discipline.ticks(LFCLK_HZ * 1000),
+            Duration::from_micros(999_980_000)
+        );
+
+        discipline.set_correction_ppb(1_000_000);
+        assert_eq!(discipline.correction_ppb(), MAX_CORRECTION_PPB);
+        discipline.set_correction_ppb(i32::MIN);
+        assert_eq!(discipline.correction_ppb(), -MAX_CORRECTION_PPB);
+    }
+
+    /// A server with the true time, and no network delay
+    fn exchange(clock: &Uhr, true_nanos: i128) -> SyncSample {
+        let server = Uhr::from(UnixTimestamp(0)) + TimeDelta::from_nanos(true_nanos);
+
+        SyncSample {
+            originate: *clock,
+            receive: server,
+            transmit: server,
+            destination: *clock,
+        }
+    }
+
+    #[test]
+    fn drifting_crystal() {
+        // An 8 Hz tick from a crystal running 50 ppm fast
+        const ACTUAL_HZ: f64 = 8.0 * (1.0 + 50e-6);
+        let mut discipline = Discipline::lfclk(0xFFF);
+        discipline.set_min_interval(Duration::from_secs(3600));
+
+        let mut clock = Uhr::from(UnixTimestamp(1_000_000));
+        let mut ticks = 0u64;
+        let true_nanos = |ticks: u64| (ticks as f64 / ACTUAL_HZ * 1e9) as i128;
+
+        // Synchronize every ten minutes for half a day
+        for minute in 1..=720u64 {
+            for _ in 0..(60.0 * ACTUAL_HZ) as u64 {
+                discipline.advance(&mut clock, 1);
+                ticks += 1;
+            }
+
+            if minute % 10 == 0 {
+                let sample = exchange(&clock, true_nanos(ticks));
+                discipline.synchronize(&mut clock, &sample);
+            }
+        }
+
+        let ppb = discipline.correction_ppb();
+        assert!((ppb + 50_000).abs() < 100, "correction {}", ppb);
+
+        // Three days without network access
+        for _ in 0..3 * 24 * 3600 {
+            for _ in 0..8 {
+                discipline.advance(&mut clock, 1);
+                ticks += 1;
+            }
+        }
+
+        let error = (clock - Uhr::from(UnixTimestamp(0))).as_nanos() - true_nanos(ticks);
+        assert!(error.abs() < 50_000_000, "error {} ns", error);
+    }
+}
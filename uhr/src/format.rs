@@ -0,0 +1,389 @@
+//! Human readable formatting and parsing of wall clock times
+//!
+//! Times are written as RFC 3339 (the internet profile of ISO 8601), e.g.
+//! `2019-03-31T16:11:26+02:00`, or as a compact `16:11`, into any
+//! `core::fmt::Write`. RFC 3339 times can also be parsed back into a `Uhr`.
+
+use core::fmt::{self, Display, Formatter, Write};
+use core::time::Duration;
+
+use gregor::{Month, NaiveDateTime, UnambiguousTimeZone, UnixTimestamp, Utc, YearKind};
+
+use crate::tz::TimeZoneRule;
+use crate::uhr::Uhr;
+
+/// An error found while parsing an RFC 3339 time
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct ParseError {
+    /// The byte offset in the input where the error was found
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// The kinds of error that may be found while parsing an RFC 3339 time
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ParseErrorKind {
+    /// The input ended before a complete time was read
+    TooShort,
+
+    /// A digit was expected
+    ExpectedDigit,
+
+    /// A specific separator was expected, such as `-`, `T` or `:`
+    Expected(char),
+
+    /// A field was outside of its valid range, e.g. `2019-02-30`
+    OutOfRange(Field),
+
+    /// Characters remained after the end of the time
+    TrailingCharacters,
+}
+
+/// A field of an RFC 3339 time
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    OffsetHour,
+    OffsetMinute,
+}
+
+impl Uhr {
+    /// Write the local time as RFC 3339, including the offset from UTC. The
+    /// fraction of the second is written as milli-, micro- or nanoseconds,
+    /// and left out if zero. Years outside of 0000-9999 can not be written
+    pub fn write_rfc3339<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let local = self.into_local_date_time();
+        if local.year() < 0 || local.year() > 9999 {
+            return Err(fmt::Error);
+        }
+
+        write!(
+            out,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            local.year(),
+            local.month().to_number(),
+            local.day(),
+            local.hour(),
+            local.minute(),
+            local.second(),
+        )?;
+
+        match self.subsec_nanos() {
+            0 => {}
+            n if n % 1_000_000 == 0 => write!(out, ".{:03}", n / 1_000_000)?,
+            n if n % 1_000 == 0 => write!(out, ".{:06}", n / 1_000)?,
+            n => write!(out, ".{:09}", n)?,
+        }
+
+        let offset = self.local_time_zone().offset_seconds_at(self.timestamp());
+        if offset == 0 {
+            return out.write_char('Z');
+        }
+
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        write!(out, "{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60)
+    }
+
+    /// Write the local time as hours and minutes, e.g. `07:30`
+    pub fn write_hh_mm<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let local = self.into_local_date_time();
+        write!(out, "{:02}:{:02}", local.hour(), local.minute())
+    }
+
+    /// Display the local time as RFC 3339, see `write_rfc3339`
+    pub fn rfc3339(&self) -> Rfc3339 {
+        Rfc3339(*self)
+    }
+
+    /// Display the local time as hours and minutes, see `write_hh_mm`
+    pub fn hh_mm(&self) -> HhMm {
+        HhMm(*self)
+    }
+
+    /// Parse an RFC 3339 time, such as `2019-03-31T16:11:26.5+02:00`. The
+    /// clock uses the offset of the parsed time as its time zone. Leap
+    /// seconds (`:60`) are treated as the first second of the next minute
+    pub fn parse_rfc3339(input: &str) -> Result<Uhr, ParseError> {
+        let mut parser = Parser {
+            bytes: input.as_bytes(),
+            position: 0,
+        };
+
+        let year = parser.number(4, Field::Year, 0, 9999)? as i32;
+        parser.expect(b"-")?;
+        let month = parser.number(2, Field::Month, 1, 12)? as u8;
+        parser.expect(b"-")?;
+
+        // Validated above
+        let month = Month::from_number(month).unwrap();
+        let length = month.length(YearKind::from(year));
+        let day = parser.number(2, Field::Day, 1, u32::from(length))? as u8;
+
+        parser.expect(b"Tt")?;
+        let hour = parser.number(2, Field::Hour, 0, 23)? as u8;
+        parser.expect(b":")?;
+        let minute = parser.number(2, Field::Minute, 0, 59)? as u8;
+        parser.expect(b":")?;
+        let second = parser.number(2, Field::Second, 0, 60)? as u8;
+
+        let nanos = if parser.peek() == Some(b'.') {
+            parser.expect(b".")?;
+            parser.fraction()?
+        } else {
+            0
+        };
+
+        let offset = match parser.peek() {
+            Some(b'Z') | Some(b'z') => {
+                parser.expect(b"Zz")?;
+                0
+            }
+            Some(b'+') | Some(b'-') => {
+                let negative = parser.peek() == Some(b'-');
+                parser.expect(b"+-")?;
+                let hours = parser.number(2, Field::OffsetHour, 0, 23)? as i32;
+                parser.expect(b":")?;
+                let minutes = parser.number(2, Field::OffsetMinute, 0, 59)? as i32;
+
+                let offset = hours * 3600 + minutes * 60;
+                if negative {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+            _ => return Err(parser.error_expected('Z')),
+        };
+
+        if !parser.bytes.is_empty() {
+            return Err(parser.error(ParseErrorKind::TrailingCharacters));
+        }
+
+        // A leap second is the same as the first second of the next minute
+        let leap = if second == 60 { 1 } else { 0 };
+        let local = NaiveDateTime::new(year, month, day, hour, minute, second - leap);
+        let seconds = Utc.to_unambiguous_timestamp(&local).0 - i64::from(offset);
+
+        let mut clock = Uhr::from(UnixTimestamp(seconds));
+        clock.increment(&Duration::new(u64::from(leap), nanos));
+        clock.set_local_time_zone(TimeZoneRule::from_offset_seconds(offset));
+        Ok(clock)
+    }
+}
+
+/// A `Uhr` displayed as RFC 3339, created by `Uhr::rfc3339`
+#[derive(Debug, Copy, Clone)]
+pub struct Rfc3339(Uhr);
+
+impl Display for Rfc3339 {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.write_rfc3339(f)
+    }
+}
+
+/// A `Uhr` displayed as hours and minutes, created by `Uhr::hh_mm`
+#[derive(Debug, Copy, Clone)]
+pub struct HhMm(Uhr);
+
+impl Display for HhMm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.write_hh_mm(f)
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.first().cloned()
+    }
+
+    fn advance(&mut self) {
+        self.bytes = &self.bytes[1..];
+        self.position += 1;
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            position: self.position,
+            kind,
+        }
+    }
+
+    fn error_expected(&self, expected: char) -> ParseError {
+        match self.peek() {
+            Some(_) => self.error(ParseErrorKind::Expected(expected)),
+            None => self.error(ParseErrorKind::TooShort),
+        }
+    }
+
+    /// Consume one of the `allowed` separators
+    fn expect(&mut self, allowed: &[u8]) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(b) if allowed.contains(&b) => {
+                self.advance();
+                Ok(())
+            }
+            _ => Err(self.error_expected(char::from(allowed[0]))),
+        }
+    }
+
+    fn digit(&mut self) -> Result<u32, ParseError> {
+        match self.peek() {
+            Some(b) if b.is_ascii_digit() => {
+                self.advance();
+                Ok(u32::from(b - b'0'))
+            }
+            Some(_) => Err(self.error(ParseErrorKind::ExpectedDigit)),
+            None => Err(self.error(ParseErrorKind::TooShort)),
+        }
+    }
+
+    /// Parse exactly `digits` digits, between `min` and `max`
+    fn number(&mut self, digits: usize, field: Field, min: u32, max: u32) -> Result<u32, ParseError> {
+        let start = self.position;
+        let mut value = 0;
+        for _ in 0..digits {
+            value = value * 10 + self.digit()?;
+        }
+
+        if value < min || value > max {
+            return Err(ParseError {
+                position: start,
+                kind: ParseErrorKind::OutOfRange(field),
+            });
+        }
+
+        Ok(value)
+    }
+
+    /// Parse the digits of a fraction of a second as nanoseconds. Digits
+    /// beyond nanoseconds are ignored
+    fn fraction(&mut self) -> Result<u32, ParseError> {
+        let mut nanos = self.digit()? * 100_000_000;
+        let mut scale = 10_000_000;
+
+        while let Some(b) = self.peek().filter(u8::is_ascii_digit) {
+            self.advance();
+            nanos += u32::from(b - b'0') * scale;
+            scale /= 10;
+        }
+
+        Ok(nanos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(seconds: i64, nanos: u32, tz: &str) -> Uhr {
+        let mut clock = Uhr::from_timestamp_nanos(UnixTimestamp(seconds), nanos);
+        clock.set_local_time_zone(TimeZoneRule::parse_posix(tz).unwrap());
+        clock
+    }
+
+    fn err(position: usize, kind: ParseErrorKind) -> Result<Uhr, ParseError> {
+        Err(ParseError { position, kind })
+    }
+
+    #[test]
+    fn format() {
+        const CET: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+        let cases = [
+            (clock(1554041486, 0, "UTC0"), "2019-03-31T14:11:26Z"),
+            (clock(1554041486, 0, CET), "2019-03-31T16:11:26+02:00"),
+            (clock(1548979200, 0, CET), "2019-02-01T01:00:00+01:00"),
+            (clock(1554041486, 500_000_000, "UTC0"), "2019-03-31T14:11:26.500Z"),
+            (clock(1554041486, 1_000, "UTC0"), "2019-03-31T14:11:26.000001Z"),
+            (clock(1554041486, 123_456_789, "UTC0"), "2019-03-31T14:11:26.123456789Z"),
+            (clock(1554041486, 0, "<-0330>3:30"), "2019-03-31T10:41:26-03:30"),
+            (clock(0, 0, "EST5"), "1969-12-31T19:00:00-05:00"),
+        ];
+
+        for (clock, expected) in cases.iter() {
+            let mut out = String::new();
+            clock.write_rfc3339(&mut out).unwrap();
+            assert_eq!(out, *expected);
+            assert_eq!(format!("{}", clock.rfc3339()), *expected);
+        }
+
+        assert_eq!(format!("{}", clock(1554041486, 0, CET).hh_mm()), "16:11");
+        assert_eq!(format!("{}", clock(1554006000, 0, "UTC0").hh_mm()), "04:20");
+
+        let mut out = String::new();
+        assert!(clock(-62167219201, 0, "UTC0").write_rfc3339(&mut out).is_err());
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = Uhr::parse_rfc3339("2019-03-31T16:11:26+02:00").unwrap();
+        assert_eq!(parsed.timestamp(), UnixTimestamp(1554041486));
+        assert_eq!(parsed.local_time_zone(), TimeZoneRule::from_offset_seconds(7200));
+
+        let parsed = Uhr::parse_rfc3339("2019-03-31t14:11:26.25z").unwrap();
+        assert_eq!(parsed.timestamp(), UnixTimestamp(1554041486));
+        assert_eq!(parsed.subsec_nanos(), 250_000_000);
+
+        let parsed = Uhr::parse_rfc3339("1969-12-31T19:00:00.0000000019-05:00").unwrap();
+        assert_eq!(parsed.timestamp(), UnixTimestamp(0));
+        assert_eq!(parsed.subsec_nanos(), 1);
+
+        let parsed = Uhr::parse_rfc3339("2016-12-31T23:59:60Z").unwrap();
+        assert_eq!(parsed.timestamp(), UnixTimestamp(1483228800));
+
+        assert!(Uhr::parse_rfc3339("2020-02-29T00:00:00Z").is_ok());
+    }
+
+    #[test]
+    fn parse_errors() {
+        use self::Field::*;
+        use self::ParseErrorKind::*;
+
+        let parse = Uhr::parse_rfc3339;
+        assert_eq!(parse(""), err(0, TooShort));
+        assert_eq!(parse("19-03-31T14:11:26Z"), err(2, ExpectedDigit));
+        assert_eq!(parse("2019/03/31T14:11:26Z"), err(4, Expected('-')));
+        assert_eq!(parse("2019-13-31T14:11:26Z"), err(5, OutOfRange(Month)));
+        assert_eq!(parse("2019-02-29T14:11:26Z"), err(8, OutOfRange(Day)));
+        assert_eq!(parse("2019-03-00T14:11:26Z"), err(8, OutOfRange(Day)));
+        assert_eq!(parse("2019-03-31 14:11:26Z"), err(10, Expected('T')));
+        assert_eq!(parse("2019-03-31T24:11:26Z"), err(11, OutOfRange(Hour)));
+        assert_eq!(parse("2019-03-31T14:60:26Z"), err(14, OutOfRange(Minute)));
+        assert_eq!(parse("2019-03-31T14:11:61Z"), err(17, OutOfRange(Second)));
+        assert_eq!(parse("2019-03-31T14:11:26.Z"), err(20, ExpectedDigit));
+        assert_eq!(parse("2019-03-31T14:11:26"), err(19, TooShort));
+        assert_eq!(parse("2019-03-31T14:11:26+0200"), err(22, Expected(':')));
+        assert_eq!(parse("2019-03-31T14:11:26+24:00"), err(20, OutOfRange(OffsetHour)));
+        assert_eq!(parse("2019-03-31T14:11:26+02:60"), err(23, OutOfRange(OffsetMinute)));
+        assert_eq!(parse("2019-03-31T14:11:26 UTC"), err(19, Expected('Z')));
+        assert_eq!(parse("2019-03-31T14:11:26Z "), err(20, TrailingCharacters));
+    }
+
+    #[test]
+    fn round_trip() {
+        let times = [
+            clock(1554041486, 0, "CET-1CEST,M3.5.0,M10.5.0/3"),
+            clock(1572136200, 999_000_000, "CET-1CEST,M3.5.0,M10.5.0/3"),
+            clock(-1, 1, "<+0545>-5:45"),
+        ];
+
+        for time in times.iter() {
+            let text = format!("{}", time.rfc3339());
+            let parsed = Uhr::parse_rfc3339(&text).unwrap();
+            assert_eq!(parsed.timestamp(), time.timestamp());
+            assert_eq!(parsed.subsec_nanos(), time.subsec_nanos());
+            assert_eq!(format!("{}", parsed.rfc3339()), text);
+        }
+    }
+}
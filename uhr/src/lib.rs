@@ -1,9 +1,18 @@
 #![cfg_attr(not(test), no_std)]
 
+pub mod delta;
+pub mod discipline;
+pub mod format;
+pub mod sync;
+pub mod tz;
 pub mod uhr;
 pub mod wecker;
 
+pub use crate::delta::TimeDelta;
+pub use crate::discipline::Discipline;
+pub use crate::sync::{SyncResult, SyncSample};
+pub use crate::tz::TimeZoneRule;
 pub use crate::uhr::Uhr;
-pub use crate::wecker::{Alarm, DayFlags, Wecker};
+pub use crate::wecker::{Alarm, AlarmId, DateRange, DayFlags, FiredAlarm, Label, Recurrence, Schedule, Wecker};
 pub use generic_array::ArrayLength;
-pub use gregor::{DateTime, FixedOffsetFromUtc, UnixTimestamp};
+pub use gregor::{DateTime, DayOfTheWeek, FixedOffsetFromUtc, Month, NaiveDateTime, UnixTimestamp};
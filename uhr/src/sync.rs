@@ -0,0 +1,173 @@
+//! A simple NTP-style ("NTP-lite") time synchronization scheme
+//!
+//! A client records the time it sends a request (`originate`). The server
+//! records the time the request was received (`receive`), and the time the
+//! response was sent (`transmit`). The client then records the time the
+//! response arrived (`destination`). From these four timestamps, the offset
+//! between the two clocks and the round trip delay can be calculated,
+//! assuming the network delay is symmetric.
+
+use core::time::Duration;
+
+use crate::uhr::Uhr;
+
+/// The four timestamps of a single request/response exchange
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct SyncSample {
+    /// Client time when the request was sent
+    pub originate: Uhr,
+    /// Server time when the request was received
+    pub receive: Uhr,
+    /// Server time when the response was sent
+    pub transmit: Uhr,
+    /// Client time when the response was received
+    pub destination: Uhr,
+}
+
+/// The result of a time synchronization exchange
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct SyncResult {
+    /// The amount the client clock must be adjusted to match the
+    /// server clock, in nanoseconds
+    pub offset_nanos: i64,
+
+    /// The time spent in transit, excluding time spent in the server
+    pub round_trip_delay: Duration,
+}
+
+impl SyncSample {
+    /// Calculate the clock offset and round trip delay of this exchange
+    pub fn result(&self) -> SyncResult {
+        let t1 = self.originate.as_nanos();
+        let t2 = self.receive.as_nanos();
+        let t3 = self.transmit.as_nanos();
+        let t4 = self.destination.as_nanos();
+
+        let offset = ((t2 - t1) + (t3 - t4)) / 2;
+
+        // A misbehaving server could report that it took longer to respond
+        // than the entire exchange took. Never report a negative delay, or
+        // wrap around for garbage timestamps
+        let delay = (t4 - t1) - (t3 - t2);
+        let delay = delay.clamp(0, i128::from(u64::MAX)) as u64;
+
+        SyncResult {
+            offset_nanos: offset.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64,
+            round_trip_delay: Duration::from_nanos(delay),
+        }
+    }
+}
+
+impl Uhr {
+    /// Correct the clock using the result of a time synchronization exchange,
+    /// where `destination` was measured by this clock. The time zone of the
+    /// clock is not modified
+    pub fn synchronize(&mut self, sample: &SyncSample) -> SyncResult {
+        let result = sample.result();
+        self.adjust(result.offset_nanos);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gregor::UnixTimestamp;
+
+    fn at(secs: i64, millis: u32) -> Uhr {
+        Uhr::from_timestamp_nanos(UnixTimestamp(secs), millis * 1_000_000)
+    }
+
+    #[test]
+    fn client_behind() {
+        // Client is 5s behind, 100ms each way, 20ms server processing
+        let sample = SyncSample {
+            originate: at(1000, 0),
+            receive: at(1005, 100),
+            transmit: at(1005, 120),
+            destination: at(1000, 220),
+        };
+
+        assert_eq!(
+            sample.result(),
+            SyncResult {
+                offset_nanos: 5_000_000_000,
+                round_trip_delay: Duration::from_millis(200),
+            }
+        );
+    }
+
+    #[test]
+    fn client_ahead() {
+        // Client is 1.5s ahead, 10ms each way
+        let sample = SyncSample {
+            originate: at(2001, 500),
+            receive: at(2000, 10),
+            transmit: at(2000, 10),
+            destination: at(2001, 520),
+        };
+
+        let result = sample.result();
+        assert_eq!(result.offset_nanos, -1_500_000_000);
+        assert_eq!(result.round_trip_delay, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn negative_delay_clamped() {
+        let sample = SyncSample {
+            originate: at(1000, 0),
+            receive: at(1000, 0),
+            transmit: at(1001, 0),
+            destination: at(1000, 500),
+        };
+
+        assert_eq!(sample.result().round_trip_delay, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn extreme_timestamps_clamped() {
+        let earliest = Uhr::from(UnixTimestamp(i64::MIN));
+        let latest = Uhr::from_timestamp_nanos(UnixTimestamp(i64::MAX), 999_999_999);
+
+        let sample = SyncSample {
+            originate: earliest,
+            receive: latest,
+            transmit: latest,
+            destination: latest,
+        };
+        let result = sample.result();
+        assert_eq!(result.offset_nanos, i64::MAX);
+        assert_eq!(result.round_trip_delay, Duration::from_nanos(u64::MAX));
+
+        let sample = SyncSample {
+            originate: latest,
+            receive: earliest,
+            transmit: latest,
+            destination: earliest,
+        };
+        let result = sample.result();
+        assert_eq!(result.offset_nanos, 0);
+        assert_eq!(result.round_trip_delay, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn apply_to_clock() {
+        let sample = SyncSample {
+            originate: at(1000, 900),
+            receive: at(998, 0),
+            transmit: at(998, 0),
+            destination: at(1000, 900),
+        };
+
+        let mut clock = at(1000, 900);
+        clock.synchronize(&sample);
+        assert_eq!(clock, at(998, 0));
+
+        clock.adjust(1_250_000_000);
+        assert_eq!(clock, at(999, 250));
+
+        clock.adjust(-999_250_000_001);
+        assert_eq!(clock.timestamp(), UnixTimestamp(-1));
+        assert_eq!(clock.subsec_nanos(), 999_999_999);
+    }
+}
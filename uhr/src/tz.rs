@@ -0,0 +1,789 @@
+//! Time zone rules, including daylight saving time
+//!
+//! Rules follow the model of the POSIX `TZ` environment variable: a standard
+//! offset from UTC, and optionally a daylight saving offset along with the
+//! dates and times daylight saving time starts and ends each year. For
+//! example, Central European Time is described by `CET-1CEST,M3.5.0,M10.5.0/3`.
+
+use core::convert::From;
+
+use gregor::{
+    DayOfTheWeek, DaylightSaving, FixedOffsetFromUtc, Month, NaiveDateTime, TimeZone,
+    UnambiguousTimeZone, UnixTimestamp, Utc, YearKind,
+};
+
+pub(crate) const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Transitions happen at 02:00:00 local time, unless specified
+const DEFAULT_TRANSITION_TIME: i32 = 2 * 60 * 60;
+
+// The kinds of transition date. An enum is not used, see `TimeZoneRule`
+const KIND_MONTH_WEEK_DAY: u8 = 0;
+const KIND_JULIAN: u8 = 1;
+const KIND_DAY_OF_YEAR: u8 = 2;
+
+/// The date and local time of a daylight saving transition, repeating
+/// every year
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Transition {
+    kind: u8,
+    month: u8,
+    week: u8,
+    weekday: u8,
+    day: u16,
+    time: i32,
+}
+
+impl Transition {
+    /// The `week`th `weekday` of `month`, where a `week` of 5 means the last
+    /// `weekday` of the month (`Mm.w.d` in a POSIX `TZ` string). `time` is
+    /// the number of seconds after local midnight the transition happens,
+    /// which may be negative, or more than one day.
+    ///
+    /// `None` is returned if `week` is not between 1 and 5
+    pub fn month_week_day(month: Month, week: u8, weekday: DayOfTheWeek, time: i32) -> Option<Self> {
+        if !(1..=5).contains(&week) {
+            return None;
+        }
+
+        Some(Transition {
+            kind: KIND_MONTH_WEEK_DAY,
+            month: month.to_number(),
+            week,
+            weekday: weekday.to_iso_number(),
+            day: 0,
+            time,
+        })
+    }
+
+    /// The `day`th day of the year, from 1 to 365, never counting February
+    /// 29th (`Jn` in a POSIX `TZ` string). `None` is returned if `day` is
+    /// out of range
+    pub fn julian(day: u16, time: i32) -> Option<Self> {
+        if !(1..=365).contains(&day) {
+            return None;
+        }
+
+        Some(Transition {
+            kind: KIND_JULIAN,
+            month: 0,
+            week: 0,
+            weekday: 0,
+            day,
+            time,
+        })
+    }
+
+    /// The `day`th day of the year, from 0 to 365, counting February 29th
+    /// in leap years (`n` in a POSIX `TZ` string). `None` is returned if
+    /// `day` is out of range
+    pub fn day_of_year(day: u16, time: i32) -> Option<Self> {
+        if day > 365 {
+            return None;
+        }
+
+        Some(Transition {
+            kind: KIND_DAY_OF_YEAR,
+            month: 0,
+            week: 0,
+            weekday: 0,
+            day,
+            time,
+        })
+    }
+
+    /// The local time of the transition in `year`, as seconds since
+    /// 1970-01-01 00:00:00 local time
+    fn local_seconds(&self, year: i32) -> i64 {
+        let new_year = epoch_days(&NaiveDateTime::new(year, Month::January, 1, 0, 0, 0));
+        let days = match self.kind {
+            KIND_MONTH_WEEK_DAY => {
+                // Validated on construction
+                let month = Month::from_number(self.month).unwrap();
+                let day = nth_weekday(year, month, self.weekday, self.week);
+
+                epoch_days(&NaiveDateTime::new(year, month, day, 0, 0, 0))
+            }
+            KIND_JULIAN => {
+                let leap = YearKind::from(year) == YearKind::Leap;
+                let skip_leap_day = leap && self.day >= 60;
+                new_year + i64::from(self.day) - if skip_leap_day { 0 } else { 1 }
+            }
+            _ => new_year + i64::from(self.day),
+        };
+
+        days * SECONDS_PER_DAY + i64::from(self.time)
+    }
+}
+
+/// A time zone, with optional daylight saving time.
+///
+/// `Uhr` and `Alarm` are stored in `heapless` collections, which create their
+/// storage with `mem::uninitialized`. This is not allowed for types with
+/// invalid bit patterns, such as enums, `bool` or `Option`, so this type (and
+/// `Transition` and `Recurrence`) only contain plain integers, and `Alarm`
+/// keeps its flags and optional fields as integers too.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct TimeZoneRule {
+    /// Seconds ahead of UTC outside of daylight saving time
+    std_offset: i32,
+    /// Seconds ahead of UTC during daylight saving time. Equal to
+    /// `std_offset` if the time zone has no daylight saving time
+    dst_offset: i32,
+    dst_start: Transition,
+    dst_end: Transition,
+}
+
+/// Errors that may occur when parsing a POSIX `TZ` string
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ParseError {
+    /// A time zone name was missing, or not at least three characters
+    InvalidName,
+
+    /// A UTC offset or transition time was missing or out of range, or a
+    /// UTC offset was not a whole number of minutes
+    InvalidTime,
+
+    /// A daylight saving transition date was malformed or out of range
+    InvalidRule,
+
+    /// A daylight saving time zone was given without transition dates
+    MissingRule,
+
+    /// Unexpected characters followed a valid time zone
+    TrailingCharacters,
+}
+
+impl TimeZoneRule {
+    /// Coordinated Universal Time
+    pub const UTC: TimeZoneRule = TimeZoneRule::from_offset_seconds(0);
+
+    /// A time zone `seconds` ahead of UTC, with no daylight saving time.
+    /// Offsets are kept in whole minutes, as `FixedOffsetFromUtc` can't
+    /// represent seconds, so any remaining seconds are dropped
+    pub const fn from_offset_seconds(seconds: i32) -> Self {
+        // Never used, as the offsets are the same
+        const NEVER: Transition = Transition {
+            kind: KIND_DAY_OF_YEAR,
+            month: 0,
+            week: 0,
+            weekday: 0,
+            day: 0,
+            time: 0,
+        };
+
+        let seconds = seconds - seconds % 60;
+
+        TimeZoneRule {
+            std_offset: seconds,
+            dst_offset: seconds,
+            dst_start: NEVER,
+            dst_end: NEVER,
+        }
+    }
+
+    /// A time zone that switches from `std_offset` to `dst_offset` at
+    /// `start` (in standard time), and back at `end` (in daylight saving
+    /// time) each year
+    pub fn with_dst(
+        std_offset: FixedOffsetFromUtc,
+        dst_offset: FixedOffsetFromUtc,
+        start: Transition,
+        end: Transition,
+    ) -> Self {
+        TimeZoneRule {
+            std_offset: offset_seconds(std_offset),
+            dst_offset: offset_seconds(dst_offset),
+            dst_start: start,
+            dst_end: end,
+        }
+    }
+
+    /// Parse a POSIX `TZ` string, such as `CET-1CEST,M3.5.0,M10.5.0/3` or
+    /// `<+0530>-5:30`. Note that POSIX offsets are positive west of UTC.
+    /// Time zone names are not kept
+    pub fn parse_posix(tz: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { bytes: tz.as_bytes() };
+
+        parser.name()?;
+        let std_offset = -parser.offset()?;
+
+        if parser.is_empty() {
+            return Ok(TimeZoneRule::from_offset_seconds(std_offset));
+        }
+
+        parser.name()?;
+        let dst_offset = match parser.peek() {
+            Some(b',') | None => std_offset + 60 * 60,
+            Some(_) => -parser.offset()?,
+        };
+
+        if !parser.eat(b',') {
+            return Err(ParseError::MissingRule);
+        }
+        let dst_start = parser.transition()?;
+
+        if !parser.eat(b',') {
+            return Err(ParseError::MissingRule);
+        }
+        let dst_end = parser.transition()?;
+
+        if !parser.is_empty() {
+            return Err(ParseError::TrailingCharacters);
+        }
+
+        Ok(TimeZoneRule {
+            std_offset,
+            dst_offset,
+            dst_start,
+            dst_end,
+        })
+    }
+
+    /// Does this time zone observe daylight saving time?
+    pub fn has_dst(&self) -> bool {
+        self.std_offset != self.dst_offset
+    }
+
+    /// The offset from UTC in effect at the given time
+    pub fn offset_at(&self, time: UnixTimestamp) -> FixedOffsetFromUtc {
+        if self.is_in_dst(time) {
+            self.offset_during_dst()
+        } else {
+            self.offset_outside_dst()
+        }
+    }
+
+    /// The offset from UTC in effect at the given time, in seconds
+    pub fn offset_seconds_at(&self, time: UnixTimestamp) -> i32 {
+        if self.is_in_dst(time) {
+            self.dst_offset
+        } else {
+            self.std_offset
+        }
+    }
+
+    /// Convert a local time to a Unix timestamp. Unlike
+    /// `TimeZone::to_timestamp`, this never fails: a local time skipped when
+    /// clocks go forward is moved forward by the same amount, and a local
+    /// time that happens twice when clocks go back resolves to the first
+    /// instance
+    pub fn resolve(&self, local: &NaiveDateTime) -> UnixTimestamp {
+        let seconds = Utc.to_unambiguous_timestamp(local).0;
+        let assuming_during = UnixTimestamp(seconds - i64::from(self.dst_offset));
+        let assuming_outside = UnixTimestamp(seconds - i64::from(self.std_offset));
+
+        // When skipped, the time is only valid assuming standard time, which
+        // corresponds to the time after clocks went forward. When repeated,
+        // the daylight saving time is the first instance
+        if self.is_in_dst(assuming_during) {
+            assuming_during
+        } else {
+            assuming_outside
+        }
+    }
+}
+
+impl From<FixedOffsetFromUtc> for TimeZoneRule {
+    fn from(offset: FixedOffsetFromUtc) -> Self {
+        TimeZoneRule::from_offset_seconds(offset_seconds(offset))
+    }
+}
+
+impl Default for TimeZoneRule {
+    fn default() -> Self {
+        TimeZoneRule::UTC
+    }
+}
+
+impl DaylightSaving for TimeZoneRule {
+    fn offset_outside_dst(&self) -> FixedOffsetFromUtc {
+        FixedOffsetFromUtc::from_hours_and_minutes(0, self.std_offset / 60)
+    }
+
+    fn offset_during_dst(&self) -> FixedOffsetFromUtc {
+        FixedOffsetFromUtc::from_hours_and_minutes(0, self.dst_offset / 60)
+    }
+
+    fn is_in_dst(&self, time: UnixTimestamp) -> bool {
+        if !self.has_dst() {
+            return false;
+        }
+
+        // Transitions are relative to the year in local standard time
+        let year = Utc
+            .from_timestamp(UnixTimestamp(time.0 + i64::from(self.std_offset)))
+            .year;
+
+        let start = self.dst_start.local_seconds(year) - i64::from(self.std_offset);
+        let end = self.dst_end.local_seconds(year) - i64::from(self.dst_offset);
+
+        if start <= end {
+            // Northern hemisphere, daylight saving time in the middle of the year
+            start <= time.0 && time.0 < end
+        } else {
+            // Southern hemisphere, daylight saving time over the new year
+            !(end <= time.0 && time.0 < start)
+        }
+    }
+}
+
+/// `FixedOffsetFromUtc` does not expose its offset, so measure it
+fn offset_seconds(offset: FixedOffsetFromUtc) -> i32 {
+    let epoch = NaiveDateTime::new(1970, Month::January, 1, 0, 0, 0);
+    -(offset.to_unambiguous_timestamp(&epoch).0 as i32)
+}
+
+/// The day of `month` that is its `week`th `weekday`, given as an ISO weekday
+/// number. A `week` of 5 means the last `weekday` of the month. `week` must be
+/// between 1 and 5
+pub(crate) fn nth_weekday(year: i32, month: Month, weekday: u8, week: u8) -> u8 {
+    let length = month.length(YearKind::from(year));
+    let first = NaiveDateTime::new(year, month, 1, 0, 0, 0);
+
+    // Find the first matching weekday, then move to the right week
+    let first_dow = first.day_of_the_week().to_iso_number();
+    let mut day = 1 + (7 + weekday - first_dow) % 7 + (week - 1) * 7;
+    while day > length {
+        day -= 7;
+    }
+
+    day
+}
+
+/// The number of days between 1970-01-01 and the date of `date`, negative for
+/// earlier dates. The time of day is ignored
+pub(crate) fn epoch_days(date: &NaiveDateTime) -> i64 {
+    let midnight = NaiveDateTime {
+        hour: 0,
+        minute: 0,
+        second: 0,
+        ..*date
+    };
+
+    Utc.to_unambiguous_timestamp(&midnight)
+        .0
+        .div_euclid(SECONDS_PER_DAY)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.first().cloned()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.bytes = &self.bytes[1..];
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip a time zone name, either alphabetic, or quoted in `<>`
+    fn name(&mut self) -> Result<(), ParseError> {
+        let len = if self.eat(b'<') {
+            let len = self
+                .bytes
+                .iter()
+                .position(|&b| b == b'>')
+                .ok_or(ParseError::InvalidName)?;
+
+            let valid = self.bytes[..len]
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-');
+            if !valid {
+                return Err(ParseError::InvalidName);
+            }
+
+            self.bytes = &self.bytes[len + 1..];
+            len
+        } else {
+            let len = self
+                .bytes
+                .iter()
+                .take_while(|b| b.is_ascii_alphabetic())
+                .count();
+
+            self.bytes = &self.bytes[len..];
+            len
+        };
+
+        if len < 3 {
+            return Err(ParseError::InvalidName);
+        }
+
+        Ok(())
+    }
+
+    /// Parse a number of up to `max_digits` digits
+    fn number(&mut self, max_digits: usize) -> Option<u32> {
+        let len = self
+            .bytes
+            .iter()
+            .take(max_digits)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+
+        if len == 0 {
+            return None;
+        }
+
+        let value = self.bytes[..len]
+            .iter()
+            .fold(0, |acc, b| acc * 10 + u32::from(b - b'0'));
+
+        self.bytes = &self.bytes[len..];
+        Some(value)
+    }
+
+    /// Parse `[+-]hh[:mm[:ss]]` as seconds, with at most `max_hours` hours
+    fn time(&mut self, max_hours: u32) -> Result<i32, ParseError> {
+        let negative = if self.eat(b'-') {
+            true
+        } else {
+            self.eat(b'+');
+            false
+        };
+
+        let hours = self.number(3).ok_or(ParseError::InvalidTime)?;
+        let mut minutes = 0;
+        let mut seconds = 0;
+
+        if self.eat(b':') {
+            minutes = self.number(2).ok_or(ParseError::InvalidTime)?;
+            if self.eat(b':') {
+                seconds = self.number(2).ok_or(ParseError::InvalidTime)?;
+            }
+        }
+
+        if hours > max_hours || minutes > 59 || seconds > 59 {
+            return Err(ParseError::InvalidTime);
+        }
+
+        let total = (hours * 60 * 60 + minutes * 60 + seconds) as i32;
+        Ok(if negative { -total } else { total })
+    }
+
+    /// Parse a UTC offset as seconds. Offsets are kept in whole minutes, see
+    /// `TimeZoneRule::from_offset_seconds`
+    fn offset(&mut self) -> Result<i32, ParseError> {
+        let offset = self.time(24)?;
+        if offset % 60 != 0 {
+            return Err(ParseError::InvalidTime);
+        }
+
+        Ok(offset)
+    }
+
+    /// Parse `Mm.w.d`, `Jn` or `n`, followed by an optional `/time`
+    fn transition(&mut self) -> Result<Transition, ParseError> {
+        let transition = if self.eat(b'M') {
+            let month = self.number(2).and_then(|m| Month::from_number(m as u8));
+            let week = if self.eat(b'.') { self.number(1) } else { None };
+            let weekday = if self.eat(b'.') { self.number(1) } else { None };
+
+            match (month, week, weekday) {
+                (Some(month), Some(week), Some(weekday)) if weekday <= 6 => {
+                    // POSIX counts weekdays from Sunday = 0, ISO 8601 from Monday = 1
+                    let iso = if weekday == 0 { 7 } else { weekday as u8 };
+                    DayOfTheWeek::from_iso_number(iso).and_then(|weekday| {
+                        Transition::month_week_day(month, week as u8, weekday, DEFAULT_TRANSITION_TIME)
+                    })
+                }
+                _ => None,
+            }
+        } else if self.eat(b'J') {
+            self.number(3)
+                .and_then(|day| Transition::julian(day as u16, DEFAULT_TRANSITION_TIME))
+        } else {
+            self.number(3)
+                .and_then(|day| Transition::day_of_year(day as u16, DEFAULT_TRANSITION_TIME))
+        };
+
+        let mut transition = transition.ok_or(ParseError::InvalidRule)?;
+        if self.eat(b'/') {
+            transition.time = self.time(167)?;
+        }
+
+        Ok(transition)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::*;
+    use serde::de::{Deserializer, Error};
+    use serde::ser::Serializer;
+    use serde::{Deserialize, Serialize};
+
+    /// The encoded form of a `Transition`. Weekdays are ISO 8601 numbers,
+    /// from Monday = 1 to Sunday = 7. The order of the variants and fields
+    /// is part of the encoding, and must not change
+    #[derive(Serialize, Deserialize)]
+    enum TransitionRepr {
+        MonthWeekDay { month: u8, week: u8, weekday: u8, time: i32 },
+        Julian { day: u16, time: i32 },
+        DayOfYear { day: u16, time: i32 },
+    }
+
+    /// The encoded form of a `TimeZoneRule`. Time zones without daylight
+    /// saving time only need their offset
+    #[derive(Serialize, Deserialize)]
+    enum TimeZoneRuleRepr {
+        Fixed(i32),
+        Dst {
+            std_offset: i32,
+            dst_offset: i32,
+            start: Transition,
+            end: Transition,
+        },
+    }
+
+    impl Serialize for Transition {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let repr = match self.kind {
+                KIND_MONTH_WEEK_DAY => TransitionRepr::MonthWeekDay {
+                    month: self.month,
+                    week: self.week,
+                    weekday: self.weekday,
+                    time: self.time,
+                },
+                KIND_JULIAN => TransitionRepr::Julian {
+                    day: self.day,
+                    time: self.time,
+                },
+                _ => TransitionRepr::DayOfYear {
+                    day: self.day,
+                    time: self.time,
+                },
+            };
+
+            repr.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Transition {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Transition, D::Error> {
+            let transition = match TransitionRepr::deserialize(deserializer)? {
+                TransitionRepr::MonthWeekDay { month, week, weekday, time } => {
+                    let month = Month::from_number(month);
+                    let weekday = DayOfTheWeek::from_iso_number(weekday);
+                    match (month, weekday) {
+                        (Some(month), Some(weekday)) => {
+                            Transition::month_week_day(month, week, weekday, time)
+                        }
+                        _ => None,
+                    }
+                }
+                TransitionRepr::Julian { day, time } => Transition::julian(day, time),
+                TransitionRepr::DayOfYear { day, time } => Transition::day_of_year(day, time),
+            };
+
+            transition.ok_or_else(|| D::Error::custom("invalid transition date"))
+        }
+    }
+
+    impl Serialize for TimeZoneRule {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let repr = if self.has_dst() {
+                TimeZoneRuleRepr::Dst {
+                    std_offset: self.std_offset,
+                    dst_offset: self.dst_offset,
+                    start: self.dst_start,
+                    end: self.dst_end,
+                }
+            } else {
+                TimeZoneRuleRepr::Fixed(self.std_offset)
+            };
+
+            repr.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for TimeZoneRule {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TimeZoneRule, D::Error> {
+            let repr = TimeZoneRuleRepr::deserialize(deserializer)?;
+            let (std_offset, dst_offset) = match repr {
+                TimeZoneRuleRepr::Fixed(offset) => (offset, offset),
+                TimeZoneRuleRepr::Dst { std_offset, dst_offset, .. } => (std_offset, dst_offset),
+            };
+
+            // Kept in whole minutes, see `TimeZoneRule::from_offset_seconds`
+            if std_offset % 60 != 0 || dst_offset % 60 != 0 {
+                return Err(D::Error::custom("offset not a whole number of minutes"));
+            }
+
+            Ok(match repr {
+                TimeZoneRuleRepr::Fixed(offset) => TimeZoneRule::from_offset_seconds(offset),
+                TimeZoneRuleRepr::Dst { start, end, .. } => TimeZoneRule {
+                    std_offset,
+                    dst_offset,
+                    dst_start: start,
+                    dst_end: end,
+                },
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CET: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+    fn offset(tz: &TimeZoneRule, time: i64) -> i32 {
+        offset_seconds(tz.offset_at(UnixTimestamp(time)))
+    }
+
+    fn local(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> NaiveDateTime {
+        NaiveDateTime::new(year, Month::from_number(month).unwrap(), day, hour, minute, 0)
+    }
+
+    #[test]
+    fn parse() {
+        let cet = TimeZoneRule::parse_posix(CET).unwrap();
+        assert_eq!(
+            cet,
+            TimeZoneRule::with_dst(
+                FixedOffsetFromUtc::from_hours_and_minutes(1, 0),
+                FixedOffsetFromUtc::from_hours_and_minutes(2, 0),
+                Transition::month_week_day(Month::March, 5, DayOfTheWeek::Sunday, 2 * 3600).unwrap(),
+                Transition::month_week_day(Month::October, 5, DayOfTheWeek::Sunday, 3 * 3600).unwrap(),
+            )
+        );
+
+        let india = TimeZoneRule::parse_posix("<+0530>-5:30").unwrap();
+        assert_eq!(india, TimeZoneRule::from_offset_seconds(5 * 3600 + 30 * 60));
+        assert!(!india.has_dst());
+
+        // Offsets are kept in whole minutes, like `FixedOffsetFromUtc`
+        let lmt = TimeZoneRule::from_offset_seconds(3208);
+        assert_eq!(lmt, TimeZoneRule::from_offset_seconds(53 * 60));
+        assert_eq!(lmt.offset_seconds_at(UnixTimestamp(0)), offset(&lmt, 0));
+
+        let custom = TimeZoneRule::parse_posix("ABC+3DEF+1:30,J60/-1,300/26:00:30").unwrap();
+        assert_eq!(custom.std_offset, -3 * 3600);
+        assert_eq!(custom.dst_offset, -5400);
+        assert_eq!(custom.dst_start, Transition::julian(60, -3600).unwrap());
+        assert_eq!(custom.dst_end, Transition::day_of_year(300, 26 * 3600 + 30).unwrap());
+
+        assert_eq!(TimeZoneRule::parse_posix("UTC0"), Ok(TimeZoneRule::UTC));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("", ParseError::InvalidName),
+            ("XY1", ParseError::InvalidName),
+            ("<+05", ParseError::InvalidName),
+            ("CET", ParseError::InvalidTime),
+            ("CET-25", ParseError::InvalidTime),
+            ("CET-1:60", ParseError::InvalidTime),
+            ("LMT-0:53:28", ParseError::InvalidTime),
+            ("CET-1CEST-2:00:30,M3.5.0,M10.5.0", ParseError::InvalidTime),
+            ("CET-1CEST", ParseError::MissingRule),
+            ("CET-1CEST,M3.5.0", ParseError::MissingRule),
+            ("CET-1CEST,M13.5.0,M10.5.0", ParseError::InvalidRule),
+            ("CET-1CEST,M3.6.0,M10.5.0", ParseError::InvalidRule),
+            ("CET-1CEST,M3.5.7,M10.5.0", ParseError::InvalidRule),
+            ("CET-1CEST,M3.5,M10.5.0", ParseError::InvalidRule),
+            ("CET-1CEST,J0,J365", ParseError::InvalidRule),
+            ("CET-1CEST,0,366", ParseError::InvalidRule),
+            ("CET-1CEST,M3.5.0,M10.5.0/168", ParseError::InvalidTime),
+            ("CET-1CEST,M3.5.0,M10.5.0x", ParseError::TrailingCharacters),
+        ];
+
+        for (tz, err) in cases.iter() {
+            assert_eq!(TimeZoneRule::parse_posix(tz), Err(*err), "{}", tz);
+        }
+    }
+
+    #[test]
+    fn transitions() {
+        // Europe: 2019-03-31 01:00 UTC and 2019-10-27 01:00 UTC
+        let cet = TimeZoneRule::parse_posix(CET).unwrap();
+        assert_eq!(offset(&cet, 1553994000 - 1), 3600);
+        assert_eq!(offset(&cet, 1553994000), 7200);
+        assert_eq!(offset(&cet, 1572138000 - 1), 7200);
+        assert_eq!(offset(&cet, 1572138000), 3600);
+
+        // USA: 2019-03-10 07:00 UTC and 2019-11-03 06:00 UTC
+        let est = TimeZoneRule::parse_posix("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(offset(&est, 1552201200 - 1), -5 * 3600);
+        assert_eq!(offset(&est, 1552201200), -4 * 3600);
+        assert_eq!(offset(&est, 1572760800 - 1), -4 * 3600);
+        assert_eq!(offset(&est, 1572760800), -5 * 3600);
+
+        // Australia: 2019-04-06 16:00 UTC and 2019-10-05 16:00 UTC
+        let aest = TimeZoneRule::parse_posix("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(offset(&aest, 1554566400 - 1), 11 * 3600);
+        assert_eq!(offset(&aest, 1554566400), 10 * 3600);
+        assert_eq!(offset(&aest, 1570291200 - 1), 10 * 3600);
+        assert_eq!(offset(&aest, 1570291200), 11 * 3600);
+
+        // Julian days never count February 29th, zero based days do
+        let julian = TimeZoneRule::parse_posix("AAA0BBB,J60/0,J61/0").unwrap();
+        let zero_based = TimeZoneRule::parse_posix("AAA0BBB,59/0,60/0").unwrap();
+        let feb_29 = 1582934400;
+        let mar_1 = feb_29 + SECONDS_PER_DAY;
+        assert!(!julian.is_in_dst(UnixTimestamp(feb_29)));
+        assert!(julian.is_in_dst(UnixTimestamp(mar_1)));
+        assert!(zero_based.is_in_dst(UnixTimestamp(feb_29)));
+        assert!(!zero_based.is_in_dst(UnixTimestamp(mar_1)));
+    }
+
+    #[test]
+    fn resolve_gap_and_overlap() {
+        let cet = TimeZoneRule::parse_posix(CET).unwrap();
+
+        // Skipped: 02:30 does not exist, and becomes 03:30 CEST
+        assert_eq!(cet.resolve(&local(2019, 3, 31, 2, 30)), UnixTimestamp(1553995800));
+        assert!(cet.to_timestamp(&local(2019, 3, 31, 2, 30)).is_err());
+
+        // Repeated: 02:30 happens twice, the first time is in CEST
+        assert_eq!(cet.resolve(&local(2019, 10, 27, 2, 30)), UnixTimestamp(1572136200));
+
+        // Unambiguous times on either side
+        assert_eq!(cet.resolve(&local(2019, 3, 31, 1, 59)), UnixTimestamp(1553993940));
+        assert_eq!(cet.resolve(&local(2019, 3, 31, 3, 0)), UnixTimestamp(1553994000));
+        assert_eq!(cet.resolve(&local(2019, 10, 27, 3, 0)), UnixTimestamp(1572141600));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_sub_minute_offsets() {
+        use postcard::{from_bytes, to_slice};
+
+        // Both encodings, with an offset of an hour and 30 seconds
+        let cet = TimeZoneRule::parse_posix(CET).unwrap();
+        let fixed = TimeZoneRule {
+            std_offset: 3630,
+            dst_offset: 3630,
+            ..cet
+        };
+        let dst = TimeZoneRule {
+            std_offset: 3630,
+            ..cet
+        };
+
+        let mut buf = [0u8; 32];
+        for tz in [cet, TimeZoneRule::from_offset_seconds(-9000)].iter() {
+            let used = to_slice(tz, &mut buf).unwrap();
+            assert_eq!(from_bytes::<TimeZoneRule>(used).unwrap(), *tz);
+        }
+        for tz in [fixed, dst].iter() {
+            let used = to_slice(tz, &mut buf).unwrap();
+            assert!(from_bytes::<TimeZoneRule>(used).is_err());
+        }
+    }
+}
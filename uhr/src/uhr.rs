@@ -1,15 +1,26 @@
 use core::cmp::{Ord, Ordering};
-use core::convert::From;
+use core::convert::{From, TryFrom};
+use core::ops::{Add, AddAssign, Sub, SubAssign};
 use core::time::Duration;
 
-use gregor::{DateTime, FixedOffsetFromUtc, UnixTimestamp};
+use gregor::{DateTime, FixedOffsetFromUtc, NaiveDateTime, UnixTimestamp};
+
+use crate::delta::TimeDelta;
+use crate::tz::TimeZoneRule;
+
+pub(crate) const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// The earliest and latest representable times, in nanoseconds since the
+/// Unix epoch
+const MIN_NANOS: i128 = i64::MIN as i128 * NANOS_PER_SEC;
+const MAX_NANOS: i128 = i64::MAX as i128 * NANOS_PER_SEC + (NANOS_PER_SEC - 1);
 
 /// A clock representing wall-clock-time. Not guaranteed to be
 /// monotonic. Time is stored referenced to epoch/UTC time, and a
-/// time zone offset may be provided to determine local time
+/// time zone may be provided to determine local time
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub struct Uhr {
-    tz_offset: FixedOffsetFromUtc,
+    tz: TimeZoneRule,
     seconds: UnixTimestamp,
     nanos: u32,
 }
@@ -19,7 +30,7 @@ impl From<UnixTimestamp> for Uhr {
         Uhr {
             seconds: uts,
             nanos: 0,
-            tz_offset: FixedOffsetFromUtc::from_hours_and_minutes(0, 0),
+            tz: TimeZoneRule::UTC,
         }
     }
 }
@@ -42,13 +53,68 @@ impl PartialOrd for Uhr {
 }
 
 impl Uhr {
-    /// Increment the current clock by a duration
+    /// Create a clock from a number of seconds and nanoseconds since the Unix epoch.
+    /// Any whole seconds contained in `nanos` are carried into `seconds`
+    pub fn from_timestamp_nanos(seconds: UnixTimestamp, nanos: u32) -> Uhr {
+        let mut clock = Uhr::from(seconds);
+        clock.increment(&Duration::from_nanos(u64::from(nanos)));
+        clock
+    }
+
+    /// Create a clock from a local date and time in the given time zone.
+    /// Local times that are skipped or repeated by a daylight saving time
+    /// change are resolved as described in `TimeZoneRule::resolve`
+    pub fn from_local_date_time(local: &NaiveDateTime, tz: TimeZoneRule) -> Uhr {
+        let mut clock = Uhr::from(tz.resolve(local));
+        clock.tz = tz;
+        clock
+    }
+
+    /// The whole number of seconds since the Unix epoch
+    pub fn timestamp(&self) -> UnixTimestamp {
+        self.seconds
+    }
+
+    /// The fractional part of the current second, in nanoseconds
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Step the clock forwards (positive) or backwards (negative) by a
+    /// number of nanoseconds, saturating at the earliest or latest
+    /// representable time
+    pub fn adjust(&mut self, offset_nanos: i64) {
+        *self = self.with_nanos_saturating(self.as_nanos() + i128::from(offset_nanos));
+    }
+
+    /// The number of nanoseconds since the Unix epoch
+    pub(crate) fn as_nanos(&self) -> i128 {
+        i128::from(self.seconds.0) * NANOS_PER_SEC + i128::from(self.nanos)
+    }
+
+    /// Increment the current clock by a duration, saturating at the latest
+    /// representable time
     pub fn increment(&mut self, dur: &Duration) {
-        self.seconds.0 += dur.as_secs() as i64; // TODO
-        self.nanos += dur.subsec_nanos();
-        if self.nanos >= 1_000_000_000 {
-            self.nanos -= 1_000_000_000;
-            self.seconds.0 += 1;
+        // Called on every RTC tick, so 128 bit arithmetic, which is done in
+        // software on the nRF52, is only used when saturating
+        let mut nanos = self.nanos + dur.subsec_nanos();
+        let mut carry = 0;
+        if nanos >= NANOS_PER_SEC as u32 {
+            nanos -= NANOS_PER_SEC as u32;
+            carry = 1;
+        }
+
+        let seconds = i64::try_from(dur.as_secs())
+            .ok()
+            .and_then(|secs| self.seconds.0.checked_add(secs))
+            .and_then(|secs| secs.checked_add(carry));
+
+        match seconds {
+            Some(seconds) => {
+                self.seconds = UnixTimestamp(seconds);
+                self.nanos = nanos;
+            }
+            None => *self = self.saturating_add(*dur),
         }
     }
 
@@ -59,37 +125,338 @@ impl Uhr {
         new_time
     }
 
-    /// Obtain the duration since a given time. An error is returned if
-    /// the before time is after now
-    pub fn try_duration_since(&self, before: &Uhr) -> Result<Duration, ()> {
-        if before > self {
-            return Err(());
+    /// A time `dur` after this time, in the same time zone. `None` is
+    /// returned if the result can not be represented
+    pub fn checked_add(&self, dur: Duration) -> Option<Uhr> {
+        self.checked_add_delta(TimeDelta::from(dur))
+    }
+
+    /// A time `dur` before this time, in the same time zone. `None` is
+    /// returned if the result can not be represented
+    pub fn checked_sub(&self, dur: Duration) -> Option<Uhr> {
+        self.checked_add_delta(-TimeDelta::from(dur))
+    }
+
+    /// A time `delta` after this time, which is before this time if `delta`
+    /// is negative. `None` is returned if the result can not be represented
+    pub fn checked_add_delta(&self, delta: TimeDelta) -> Option<Uhr> {
+        let total = self.as_nanos().checked_add(delta.as_nanos())?;
+        if !(MIN_NANOS..=MAX_NANOS).contains(&total) {
+            return None;
         }
 
-        let mut delta_sec = self.seconds.0 - before.seconds.0;
-        let delta_nanos = if before.nanos > self.nanos {
-            delta_sec -= 1;
-            (self.nanos + 1_000_000_000) - before.nanos
-        } else {
-            self.nanos - before.nanos
-        };
+        Some(self.with_nanos_saturating(total))
+    }
+
+    /// A time `dur` after this time, saturating at the latest representable
+    /// time
+    pub fn saturating_add(&self, dur: Duration) -> Uhr {
+        self.with_nanos_saturating(self.as_nanos().saturating_add(TimeDelta::from(dur).as_nanos()))
+    }
+
+    /// A time `dur` before this time, saturating at the earliest
+    /// representable time
+    pub fn saturating_sub(&self, dur: Duration) -> Uhr {
+        self.with_nanos_saturating(self.as_nanos().saturating_sub(TimeDelta::from(dur).as_nanos()))
+    }
+
+    /// The signed amount of time from `earlier` to this time, which is
+    /// negative if `earlier` is after this time
+    pub fn signed_duration_since(&self, earlier: &Uhr) -> TimeDelta {
+        // Can't overflow, both are at most about 2^93 nanoseconds from zero
+        TimeDelta::from_nanos(self.as_nanos() - earlier.as_nanos())
+    }
 
-        Ok(Duration::new(delta_sec as u64, delta_nanos))
+    /// Obtain the duration since a given time. `None` is returned if the
+    /// before time is after now
+    pub fn try_duration_since(&self, before: &Uhr) -> Option<Duration> {
+        // Can't fail unless negative, the largest difference of two times
+        // fits in a `Duration`
+        self.signed_duration_since(before).to_duration()
     }
 
-    /// Obtain the duration since a given time. This function panics if `before`
-    /// is after the current time. Consider using `try_duration_since()`
+    /// Obtain the duration since a given time. If `before` is after the
+    /// current time, which may happen as the clock is not monotonic, zero
+    /// is returned. Use `try_duration_since()` or `signed_duration_since()`
+    /// to detect this
     pub fn duration_since(&self, before: &Uhr) -> Duration {
-        self.try_duration_since(before).unwrap()
+        self.try_duration_since(before).unwrap_or_default()
     }
 
-    /// Convert the current wall clock into a local `DateTime` object
+    /// Convert the current wall clock into a local `DateTime` object, using
+    /// the offset from UTC in effect at the current time
     pub fn into_local_date_time(&self) -> DateTime<FixedOffsetFromUtc> {
-        DateTime::from_timestamp(self.seconds, self.tz_offset)
+        DateTime::from_timestamp(self.seconds, self.tz.offset_at(self.seconds))
+    }
+
+    /// The local timezone of the clock
+    pub fn local_time_zone(&self) -> TimeZoneRule {
+        self.tz
+    }
+
+    /// Change the local timezone of the clock. Either a `TimeZoneRule`, or
+    /// a `FixedOffsetFromUtc` may be used
+    pub fn set_local_time_zone<TZ: Into<TimeZoneRule>>(&mut self, tz: TZ) {
+        self.tz = tz.into();
+    }
+
+    /// A time in the same time zone, `total` nanoseconds since the Unix
+    /// epoch, saturating at the earliest or latest representable time
+    fn with_nanos_saturating(&self, total: i128) -> Uhr {
+        let total = total.clamp(MIN_NANOS, MAX_NANOS);
+
+        Uhr {
+            tz: self.tz,
+            seconds: UnixTimestamp(total.div_euclid(NANOS_PER_SEC) as i64),
+            nanos: total.rem_euclid(NANOS_PER_SEC) as u32,
+        }
+    }
+}
+
+/// Operators panic if the result can not be represented, use the `checked_`
+/// or `saturating_` methods to avoid this
+impl Add<Duration> for Uhr {
+    type Output = Uhr;
+
+    fn add(self, dur: Duration) -> Uhr {
+        self.checked_add(dur)
+            .expect("overflow when adding duration to clock")
     }
+}
+
+impl AddAssign<Duration> for Uhr {
+    fn add_assign(&mut self, dur: Duration) {
+        *self = *self + dur;
+    }
+}
+
+impl Sub<Duration> for Uhr {
+    type Output = Uhr;
+
+    fn sub(self, dur: Duration) -> Uhr {
+        self.checked_sub(dur)
+            .expect("overflow when subtracting duration from clock")
+    }
+}
 
-    /// Change the local timezone of the clock
-    pub fn set_local_time_zone(&mut self, offset: FixedOffsetFromUtc) {
-        self.tz_offset = offset;
+impl SubAssign<Duration> for Uhr {
+    fn sub_assign(&mut self, dur: Duration) {
+        *self = *self - dur;
     }
 }
+
+impl Add<TimeDelta> for Uhr {
+    type Output = Uhr;
+
+    fn add(self, delta: TimeDelta) -> Uhr {
+        self.checked_add_delta(delta)
+            .expect("overflow when adding time delta to clock")
+    }
+}
+
+impl AddAssign<TimeDelta> for Uhr {
+    fn add_assign(&mut self, delta: TimeDelta) {
+        *self = *self + delta;
+    }
+}
+
+impl Sub<TimeDelta> for Uhr {
+    type Output = Uhr;
+
+    fn sub(self, delta: TimeDelta) -> Uhr {
+        delta
+            .checked_neg()
+            .and_then(|delta| self.checked_add_delta(delta))
+            .expect("overflow when subtracting time delta from clock")
+    }
+}
+
+impl SubAssign<TimeDelta> for Uhr {
+    fn sub_assign(&mut self, delta: TimeDelta) {
+        *self = *self - delta;
+    }
+}
+
+/// The signed difference between two times
+impl Sub<Uhr> for Uhr {
+    type Output = TimeDelta;
+
+    fn sub(self, earlier: Uhr) -> TimeDelta {
+        self.signed_duration_since(&earlier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn at(seconds: i64, nanos: u32) -> Uhr {
+        Uhr::from_timestamp_nanos(UnixTimestamp(seconds), nanos)
+    }
+
+    fn uhr() -> impl Strategy<Value = Uhr> {
+        (any::<i64>(), 0..1_000_000_000u32).prop_map(|(seconds, nanos)| at(seconds, nanos))
+    }
+
+    /// Times within a few hundred years of the epoch, which can be moved
+    /// by any `small_duration` without overflow
+    fn near_uhr() -> impl Strategy<Value = Uhr> {
+        (-(1i64 << 33)..(1i64 << 33), 0..1_000_000_000u32).prop_map(|(seconds, nanos)| at(seconds, nanos))
+    }
+
+    fn small_duration() -> impl Strategy<Value = Duration> {
+        (0..(1u64 << 32), 0..1_000_000_000u32).prop_map(|(secs, nanos)| Duration::new(secs, nanos))
+    }
+
+    proptest! {
+        #[test]
+        fn add_sub_round_trip(time in near_uhr(), dur in small_duration()) {
+            let later = time + dur;
+            prop_assert!(later.subsec_nanos() < 1_000_000_000);
+            prop_assert!(later >= time);
+            prop_assert_eq!(later > time, dur > Duration::from_secs(0));
+            prop_assert_eq!(later - dur, time);
+            prop_assert_eq!(later - time, TimeDelta::from(dur));
+            prop_assert_eq!(later.duration_since(&time), dur);
+            prop_assert_eq!(later.checked_add(dur), Some(time + dur + dur));
+
+            let earlier = time - dur;
+            prop_assert!(earlier.subsec_nanos() < 1_000_000_000);
+            prop_assert!(earlier <= time);
+            prop_assert_eq!(earlier + dur, time);
+            prop_assert_eq!(earlier - time, -TimeDelta::from(dur));
+            prop_assert_eq!(time.incremented(&dur), later);
+
+            let mut assigned = time;
+            assigned += dur;
+            prop_assert_eq!(assigned, later);
+            assigned -= TimeDelta::from(dur);
+            prop_assert_eq!(assigned, time);
+        }
+
+        #[test]
+        fn ordering_matches_difference(a in uhr(), b in uhr()) {
+            let delta = a - b;
+            prop_assert_eq!(a.cmp(&b), delta.as_nanos().cmp(&0));
+            prop_assert_eq!(b - a, -delta);
+            prop_assert_eq!(b + delta, a);
+            prop_assert_eq!(a.try_duration_since(&b).is_some(), a >= b);
+            prop_assert_eq!(a.try_duration_since(&b), delta.to_duration());
+            prop_assert_eq!(a.duration_since(&b), delta.to_duration().unwrap_or_default());
+            prop_assert_eq!(delta.abs_duration(), Some(a.duration_since(&b).max(b.duration_since(&a))));
+        }
+
+        #[test]
+        fn nanosecond_carry(seconds in -(1i64 << 40)..(1i64 << 40), nanos in 0..1_000_000_000u32, step in 0..2_000_000_000u64) {
+            let time = at(seconds, nanos);
+            let step = Duration::from_nanos(step);
+            let total = u64::from(nanos) + step.as_nanos() as u64;
+
+            let later = time + step;
+            prop_assert_eq!(time.incremented(&step), later);
+            prop_assert_eq!(later.timestamp().0, seconds + (total / 1_000_000_000) as i64);
+            prop_assert_eq!(u64::from(later.subsec_nanos()), total % 1_000_000_000);
+
+            let mut adjusted = time;
+            adjusted.adjust(-(step.as_nanos() as i64));
+            prop_assert_eq!(adjusted, time - step);
+        }
+
+        #[test]
+        fn saturating_and_checked(time in uhr(), secs in any::<u64>(), nanos in 0..1_000_000_000u32) {
+            let dur = Duration::new(secs, nanos);
+            let max = at(i64::MAX, 999_999_999);
+            let min = at(i64::MIN, 0);
+
+            match time.checked_add(dur) {
+                Some(later) => prop_assert_eq!(time.saturating_add(dur), later),
+                None => prop_assert_eq!(time.saturating_add(dur), max),
+            }
+            match time.checked_sub(dur) {
+                Some(earlier) => prop_assert_eq!(time.saturating_sub(dur), earlier),
+                None => prop_assert_eq!(time.saturating_sub(dur), min),
+            }
+            prop_assert_eq!(time.incremented(&dur), time.saturating_add(dur));
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let max = at(i64::MAX, 999_999_999);
+        let min = at(i64::MIN, 0);
+        let nano = Duration::from_nanos(1);
+
+        assert_eq!(max.checked_add(nano), None);
+        assert_eq!(min.checked_sub(nano), None);
+        assert_eq!(max.checked_sub(nano), Some(at(i64::MAX, 999_999_998)));
+        assert_eq!(max.saturating_add(Duration::new(u64::MAX, 0)), max);
+        assert_eq!(min.saturating_sub(Duration::new(u64::MAX, 0)), min);
+        assert_eq!(max - min, TimeDelta::from(Duration::new(u64::MAX, 999_999_999)));
+        assert_eq!(max.duration_since(&min), Duration::new(u64::MAX, 999_999_999));
+        assert_eq!(min.duration_since(&max), Duration::from_secs(0));
+        assert_eq!(min.try_duration_since(&max), None);
+
+        // The time zone is kept
+        let mut local = at(0, 0);
+        local.set_local_time_zone(TimeZoneRule::from_offset_seconds(3600));
+        assert_eq!((local + nano).local_time_zone(), local.local_time_zone());
+        assert_eq!((local - TimeDelta::from_secs(1)).timestamp(), UnixTimestamp(-1));
+
+        let mut clock = max;
+        clock.increment(&nano);
+        assert_eq!(clock, max);
+        clock.adjust(i64::MAX);
+        assert_eq!(clock, max);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow when adding duration to clock")]
+    fn add_overflow_panics() {
+        let _ = at(i64::MAX, 999_999_999) + Duration::from_nanos(1);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::*;
+    use serde::de::{Deserializer, Error};
+    use serde::ser::Serializer;
+    use serde::{Deserialize, Serialize};
+
+    /// The encoded form of a `Uhr`. The order of the fields is part of
+    /// the encoding, and must not change
+    #[derive(Serialize, Deserialize)]
+    struct UhrRepr {
+        seconds: i64,
+        nanos: u32,
+        tz: TimeZoneRule,
+    }
+
+    impl Serialize for Uhr {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            UhrRepr {
+                seconds: self.seconds.0,
+                nanos: self.nanos,
+                tz: self.tz,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Uhr {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Uhr, D::Error> {
+            let repr = UhrRepr::deserialize(deserializer)?;
+            if repr.nanos >= 1_000_000_000 {
+                return Err(D::Error::custom("nanoseconds out of range"));
+            }
+
+            Ok(Uhr {
+                seconds: UnixTimestamp(repr.seconds),
+                nanos: repr.nanos,
+                tz: repr.tz,
+            })
+        }
+    }
+}
+
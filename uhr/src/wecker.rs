@@ -6,9 +6,18 @@ use bitflags::bitflags;
 use generic_array::ArrayLength;
 use gregor::{DayOfTheWeek, UnixTimestamp};
 use heapless::binary_heap::{BinaryHeap, Min};
+use heapless::consts::{U16, U4};
+use heapless::{String, Vec};
 
+use crate::sync::{SyncResult, SyncSample};
 use crate::uhr::Uhr;
 
+mod exceptions;
+mod recurrence;
+
+pub use self::exceptions::DateRange;
+pub use self::recurrence::{Recurrence, Schedule};
+
 bitflags! {
     /// A bit packed structure representing days of the week
     pub struct DayFlags: u8 {
@@ -72,16 +81,188 @@ impl DayFlags {
     }
 }
 
+/// The number of times an alarm may be snoozed in a row, unless changed
+/// with `Wecker::set_max_snoozes`
+pub const DEFAULT_MAX_SNOOZES: u8 = 3;
+
+/// A short, human readable description of an alarm
+pub type Label = String<U16>;
+
+/// The exception dates of a single alarm
+pub type AlarmExceptions = Vec<DateRange, U4>;
+
+/// The exception dates shared by all alarms of a `Wecker`
+pub type Exceptions = Vec<DateRange, U16>;
+
+/// A handle to an alarm stored in a `Wecker`
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash)]
+pub struct AlarmId(pub u16);
+
 /// An opaque structure representing an alarm that may or may not repeat periodically
 #[derive(Debug, Eq, PartialEq)]
 pub struct Alarm {
+    id: AlarmId,
+    label: Label,
     next_time: Uhr,
-    repeat: DayFlags,
+    recurrence: Recurrence,
+    snoozes: u8,
+
+    /// The local time of day the alarm was set for, in seconds after
+    /// midnight. Kept separately, as `next_time` may have been moved
+    /// by a daylight saving time change
+    wall_time: u32,
+
+    exceptions: AlarmExceptions,
+
+    // Not an `Option`, see `TimeZoneRule`. Only valid when `State::SNOOZED`
+    // is set
+    snoozed_until: Uhr,
+    state: State,
+}
+
+bitflags! {
+    struct State: u8 {
+        /// The alarm only fires once, and has already fired
+        const EXPIRED = 0b0000_0001;
+        /// A snooze is pending
+        const SNOOZED = 0b0000_0010;
+    }
+}
+
+impl Alarm {
+    /// The handle of this alarm
+    pub fn id(&self) -> AlarmId {
+        self.id
+    }
+
+    /// The label of this alarm. Empty unless set with `Wecker::set_label`
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The next scheduled time of this alarm, ignoring any snooze. For an
+    /// alarm that only fires once, and has already fired, this is the
+    /// time it fired at
+    pub fn next_time(&self) -> Uhr {
+        self.next_time
+    }
+
+    /// The days of the week this alarm repeats on. Empty if the alarm only
+    /// fires once, or repeats on a schedule other than `Recurrence::weekly`
+    pub fn repeat(&self) -> DayFlags {
+        self.recurrence.days()
+    }
+
+    /// The schedule this alarm repeats on
+    pub fn recurrence(&self) -> Recurrence {
+        self.recurrence
+    }
+
+    /// The dates this alarm does not fire on, in addition to the
+    /// exceptions shared by all alarms
+    pub fn exceptions(&self) -> &[DateRange] {
+        &self.exceptions
+    }
+
+    /// How many times this alarm has been snoozed since it last fired
+    /// on schedule
+    pub fn snoozes(&self) -> u8 {
+        self.snoozes
+    }
+
+    /// The next time this alarm will fire, including snoozes. `None` if
+    /// the alarm has already fired, and will not fire again
+    pub fn due(&self) -> Option<Uhr> {
+        let scheduled = if self.is_expired() {
+            None
+        } else {
+            Some(self.next_time)
+        };
+
+        match (scheduled, self.snoozed_until()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.state.contains(State::EXPIRED)
+    }
+
+    fn snoozed_until(&self) -> Option<Uhr> {
+        if self.state.contains(State::SNOOZED) {
+            Some(self.snoozed_until)
+        } else {
+            None
+        }
+    }
+
+    fn set_snooze(&mut self, until: Option<Uhr>) {
+        match until {
+            Some(until) => {
+                self.snoozed_until = until;
+                self.state.insert(State::SNOOZED);
+            }
+            None => self.state.remove(State::SNOOZED),
+        }
+    }
+
+    /// Move a repeating alarm to its next occurrence strictly after `after`
+    /// that is not on an exception date. An alarm without another
+    /// occurrence expires
+    fn advance(&mut self, after: &Uhr, shared: &[DateRange]) {
+        let mut after = *after;
+
+        // Each skipped occurrence moves past the end of one exception, so
+        // this is always enough to find an occurrence outside all of them
+        for _ in 0..=(self.exceptions.len() + shared.len()) {
+            let next = match self.recurrence.next_occurrence(&self.next_time, self.wall_time, &after) {
+                Some(next) => next,
+                None => {
+                    self.state.insert(State::EXPIRED);
+                    return;
+                }
+            };
+
+            self.next_time = next;
+            if self.recurrence.is_once() {
+                return;
+            }
+
+            let date = next.into_local_date_time().naive;
+            match self
+                .exceptions
+                .iter()
+                .chain(shared)
+                .find(|range| range.contains(&date))
+            {
+                Some(range) => after = range.end(&next),
+                None => return,
+            }
+        }
+    }
+
+    /// Move a repeating alarm off any exception dates. Alarms that are not
+    /// on an exception date are not changed
+    fn skip_exceptions(&mut self, shared: &[DateRange]) {
+        if self.is_expired() || self.recurrence.is_once() {
+            return;
+        }
+
+        let mut before = self.next_time;
+        before.adjust(-1);
+        self.advance(&before, shared);
+    }
 }
 
 impl Ord for Alarm {
     fn cmp(&self, other: &Alarm) -> Ordering {
-        self.next_time.cmp(&other.next_time)
+        // Alarms that will not fire again sort after all others
+        let key = |alarm: &Alarm| (alarm.due().is_none(), alarm.due());
+
+        key(self)
+            .cmp(&key(other))
+            .then(self.id.cmp(&other.id))
     }
 }
 
@@ -98,6 +279,33 @@ pub enum Error {
 
     /// No space remains to push alarm
     AlarmFull,
+
+    /// No alarm exists with the given id
+    UnknownAlarm,
+
+    /// The alarm has already been snoozed the maximum number of times
+    SnoozeLimit,
+
+    /// No space remains to add an exception
+    ExceptionsFull,
+
+    /// The exception to remove does not exist
+    UnknownException,
+}
+
+/// A single occurrence of an alarm, reported by `Wecker::next_fired`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct FiredAlarm {
+    pub id: AlarmId,
+    pub label: Label,
+
+    /// The time the occurrence was scheduled for. This may be before the
+    /// current time, if alarms were not processed promptly
+    pub scheduled: Uhr,
+
+    /// How many times the alarm had been snoozed when this occurrence
+    /// fired. Zero if the alarm fired on schedule
+    pub snoozes: u8,
 }
 
 /// A structure for storing a wall clock with associated alarms. Alarms
@@ -109,6 +317,9 @@ where
 {
     pub time: Uhr,
     alarms: BinaryHeap<Alarm, ALARMS, Min>,
+    exceptions: Exceptions,
+    next_id: u16,
+    max_snoozes: u8,
 }
 
 impl<ALARMS> From<Uhr> for Wecker<ALARMS>
@@ -119,6 +330,9 @@ where
         Wecker {
             time: clock,
             alarms: BinaryHeap::new(),
+            exceptions: Exceptions::new(),
+            next_id: 0,
+            max_snoozes: DEFAULT_MAX_SNOOZES,
         }
     }
 }
@@ -132,69 +346,681 @@ where
         Wecker {
             time: Uhr::from(time),
             alarms: BinaryHeap::new(),
+            exceptions: Exceptions::new(),
+            next_id: 0,
+            max_snoozes: DEFAULT_MAX_SNOOZES,
         }
     }
 
-    pub fn insert_alarm(&mut self, first_time: Uhr, repeat: DayFlags) -> Result<(), Error> {
-        // If repeats, verify that first instance is on a repeat day
-        if !repeat.is_empty() {
-            let ftdt = first_time.into_local_date_time();
-            let good = DayFlags::from(ftdt.day_of_the_week()).intersects(repeat);
-            if !good {
-                return Err(Error::AlarmNotOnRepeat);
+    /// Add an alarm, first firing at `first_time`, and then repeating on
+    /// each day in `repeat`, or any other `Recurrence`. A repeating alarm
+    /// first fires on the first occurrence not on an exception date. A
+    /// handle to the new alarm is returned
+    pub fn insert_alarm<R: Into<Recurrence>>(&mut self, first_time: Uhr, repeat: R) -> Result<AlarmId, Error> {
+        let recurrence = repeat.into();
+        check_repeat(&first_time, &recurrence)?;
+
+        if self.alarms.len() >= self.alarms.capacity() {
+            return Err(Error::AlarmFull);
+        }
+
+        let id = self.allocate_id();
+        let mut alarm = Alarm {
+            id,
+            label: Label::new(),
+            next_time: first_time,
+            recurrence,
+            snoozes: 0,
+            wall_time: wall_time(&first_time),
+            exceptions: AlarmExceptions::new(),
+            snoozed_until: first_time,
+            state: State::empty(),
+        };
+
+        alarm.skip_exceptions(&self.exceptions);
+        self.alarms.push(alarm).map_err(|_| Error::AlarmFull)?;
+
+        Ok(id)
+    }
+
+    /// Remove an alarm, returning it
+    pub fn remove_alarm(&mut self, id: AlarmId) -> Result<Alarm, Error> {
+        // The heap has no way to remove an arbitrary item, so rebuild it
+        // without the removed alarm
+        let mut remaining = BinaryHeap::new();
+        let mut removed = None;
+
+        while let Some(alarm) = self.alarms.pop() {
+            if alarm.id == id {
+                removed = Some(alarm);
+            } else {
+                // We know there is space left, the heaps have the same capacity
+                remaining.push(alarm).unwrap();
             }
         }
 
-        self.alarms
-            .push(Alarm {
-                next_time: first_time,
-                repeat,
-            })
-            .map_err(|_| Error::AlarmFull)
+        self.alarms = remaining;
+        removed.ok_or(Error::UnknownAlarm)
+    }
+
+    /// Replace the schedule of an existing alarm, keeping its handle, label
+    /// and exceptions. Any pending snooze is cancelled
+    pub fn update_alarm<R: Into<Recurrence>>(&mut self, id: AlarmId, first_time: Uhr, repeat: R) -> Result<(), Error> {
+        let recurrence = repeat.into();
+        check_repeat(&first_time, &recurrence)?;
+        let shared = self.exceptions.clone();
+
+        self.modify_alarm(id, |alarm| {
+            alarm.next_time = first_time;
+            alarm.wall_time = wall_time(&first_time);
+            alarm.recurrence = recurrence;
+            alarm.set_snooze(None);
+            alarm.snoozes = 0;
+            alarm.state.remove(State::EXPIRED);
+            alarm.skip_exceptions(&shared);
+            Ok(())
+        })
+    }
+
+    /// Change the label of an existing alarm
+    pub fn set_label(&mut self, id: AlarmId, label: Label) -> Result<(), Error> {
+        self.modify_alarm(id, |alarm| {
+            alarm.label = label;
+            Ok(())
+        })
+    }
+
+    /// The number of times an alarm may be snoozed in a row
+    pub fn max_snoozes(&self) -> u8 {
+        self.max_snoozes
+    }
+
+    /// Change the number of times an alarm may be snoozed in a row
+    pub fn set_max_snoozes(&mut self, max: u8) {
+        self.max_snoozes = max;
+    }
+
+    /// Fire an alarm again, `duration` from now, as a one-off occurrence.
+    /// The regular schedule of the alarm is not changed. Only one snooze may
+    /// be pending at a time, snoozing again replaces the pending snooze
+    pub fn snooze(&mut self, id: AlarmId, duration: Duration) -> Result<(), Error> {
+        let until = self.time.incremented(&duration);
+        let max = self.max_snoozes;
+
+        self.modify_alarm(id, |alarm| {
+            if alarm.snoozes >= max {
+                return Err(Error::SnoozeLimit);
+            }
+
+            alarm.set_snooze(Some(until));
+            alarm.snoozes += 1;
+            Ok(())
+        })
+    }
+
+    /// Acknowledge an alarm, cancelling any pending snooze. An alarm that
+    /// only fires once, and has already fired, is removed
+    pub fn dismiss(&mut self, id: AlarmId) -> Result<(), Error> {
+        let mut alarm = self.remove_alarm(id)?;
+
+        if !alarm.is_expired() {
+            alarm.set_snooze(None);
+            alarm.snoozes = 0;
+
+            // We know there is space left, because we just removed one
+            self.alarms.push(alarm).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Skip the next scheduled occurrence of an alarm, without removing it.
+    /// Any pending snooze is cancelled. An alarm that only fires once will
+    /// not fire at all, and is kept until dismissed
+    pub fn skip_next(&mut self, id: AlarmId) -> Result<(), Error> {
+        let shared = self.exceptions.clone();
+
+        self.modify_alarm(id, |alarm| {
+            alarm.set_snooze(None);
+            alarm.snoozes = 0;
+
+            if !alarm.is_expired() {
+                let after = alarm.next_time;
+                alarm.advance(&after, &shared);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// The exception dates shared by all alarms
+    pub fn exceptions(&self) -> &[DateRange] {
+        &self.exceptions
+    }
+
+    /// Add an exception shared by all alarms. Repeating alarms currently
+    /// scheduled on one of its dates are moved to their next occurrence
+    pub fn add_exception(&mut self, range: DateRange) -> Result<(), Error> {
+        if !self.exceptions.contains(&range) {
+            self.exceptions.push(range).map_err(|_| Error::ExceptionsFull)?;
+        }
+
+        // The heap has no way to modify items in place, so rebuild it
+        let mut alarms = BinaryHeap::new();
+        while let Some(mut alarm) = self.alarms.pop() {
+            alarm.skip_exceptions(&self.exceptions);
+
+            // We know there is space left, the heaps have the same capacity
+            alarms.push(alarm).unwrap();
+        }
+
+        self.alarms = alarms;
+        Ok(())
+    }
+
+    /// Remove an exception shared by all alarms. Occurrences that have
+    /// already been skipped are not restored
+    pub fn remove_exception(&mut self, range: &DateRange) -> Result<(), Error> {
+        remove_range(&mut self.exceptions, range)
+    }
+
+    /// Add an exception to a single alarm. If the alarm is currently
+    /// scheduled on one of its dates, it is moved to its next occurrence
+    pub fn add_alarm_exception(&mut self, id: AlarmId, range: DateRange) -> Result<(), Error> {
+        let shared = self.exceptions.clone();
+
+        self.modify_alarm(id, |alarm| {
+            if !alarm.exceptions.contains(&range) {
+                alarm.exceptions.push(range).map_err(|_| Error::ExceptionsFull)?;
+            }
+
+            alarm.skip_exceptions(&shared);
+            Ok(())
+        })
+    }
+
+    /// Remove an exception from a single alarm. Occurrences that have
+    /// already been skipped are not restored
+    pub fn remove_alarm_exception(&mut self, id: AlarmId, range: &DateRange) -> Result<(), Error> {
+        self.modify_alarm(id, |alarm| remove_range(&mut alarm.exceptions, range))
+    }
+
+    /// Apply a change to an alarm, keeping the alarms in firing order.
+    /// The alarm is kept, even if the change fails
+    fn modify_alarm<F>(&mut self, id: AlarmId, change: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Alarm) -> Result<(), Error>,
+    {
+        let mut alarm = self.remove_alarm(id)?;
+        let result = change(&mut alarm);
+
+        // We know there is space left, because we just removed one
+        self.alarms.push(alarm).unwrap();
+        result
+    }
+
+    /// Look up an alarm by its handle
+    pub fn get_alarm(&self, id: AlarmId) -> Option<&Alarm> {
+        self.alarms.iter().find(|alarm| alarm.id == id)
+    }
+
+    /// The alarm that will fire soonest, if any
+    pub fn next_alarm(&self) -> Option<&Alarm> {
+        self.alarms.peek()
+    }
+
+    /// All alarms, in the order they will fire. Alarms that will not fire
+    /// again come last
+    pub fn alarms(&self) -> Alarms<'_, ALARMS> {
+        Alarms {
+            heap: &self.alarms,
+            last: None,
+            remaining: self.alarms.len(),
+        }
+    }
+
+    /// Obtain an unused alarm handle
+    fn allocate_id(&mut self) -> AlarmId {
+        // There are fewer alarms than handles, so this will always terminate
+        loop {
+            let id = AlarmId(self.next_id);
+            self.next_id = self.next_id.wrapping_add(1);
+
+            if !self.alarms.iter().any(|alarm| alarm.id == id) {
+                return id;
+            }
+        }
+    }
+
+    /// Correct the wall clock using the result of a time synchronization
+    /// exchange. Any alarms that are now in the past will be reported by the
+    /// next call to `alarm_ready()`
+    pub fn synchronize(&mut self, sample: &SyncSample) -> SyncResult {
+        self.time.synchronize(sample)
     }
 
     /// Process all pending alarms, including rescheduling. If
-    /// one or more alarms were ready, this function returns `true`
+    /// one or more alarms were ready, this function returns `true`.
+    /// Fired alarms are dismissed, use `next_fired` to snooze them instead
     pub fn alarm_ready(&mut self) -> bool {
         let mut flag = false;
 
-        while {
-            self.alarms
-                .peek()
-                .map(|alarm| alarm.next_time <= self.time)
-                .unwrap_or(false)
-        } {
-            const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
-            const ONE_WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
-
-            // We know there is an alarm ready
-            let mut alarm = self.alarms.pop().unwrap();
+        while let Some(fired) = self.next_fired() {
+            // Can't fail, the alarm has just fired
+            self.dismiss(fired.id).ok();
             flag = true;
+        }
 
-            // Alarm doesn't repeat? All done!
-            if alarm.repeat.is_empty() {
-                continue;
+        flag
+    }
+
+    /// Process the next alarm that is ready, if any, returning the fired
+    /// occurrence. Repeating alarms are rescheduled to their next occurrence
+    /// after the current time. Alarms that only fire once are kept until dismissed, so they
+    /// may still be snoozed
+    pub fn next_fired(&mut self) -> Option<FiredAlarm> {
+        let ready = self
+            .alarms
+            .peek()
+            .and_then(Alarm::due)
+            .map(|due| due <= self.time)
+            .unwrap_or(false);
+
+        if !ready {
+            return None;
+        }
+
+        // We know there is an alarm ready
+        let mut alarm = self.alarms.pop().unwrap();
+
+        let fired = match alarm.snoozed_until() {
+            Some(until) if alarm.is_expired() || until <= alarm.next_time => {
+                alarm.set_snooze(None);
+
+                FiredAlarm {
+                    id: alarm.id,
+                    label: alarm.label.clone(),
+                    scheduled: until,
+                    snoozes: alarm.snoozes,
+                }
             }
+            _ => {
+                let fired = FiredAlarm {
+                    id: alarm.id,
+                    label: alarm.label.clone(),
+                    scheduled: alarm.next_time,
+                    snoozes: 0,
+                };
 
-            // How many days until the next alarm instance?
-            let days_til = alarm
-                .repeat
-                .days_after(self.time.into_local_date_time().day_of_the_week());
+                // A regular occurrence replaces any pending snooze
+                alarm.set_snooze(None);
+                alarm.snoozes = 0;
 
-            // Increment the alarm to the next period
-            alarm.next_time.increment(&(days_til * ONE_DAY));
+                self.reschedule(&mut alarm);
 
-            // Just in case we lost a lot of time, bump the week until we are
-            // actually in the future from now
-            while alarm.next_time < self.time {
-                alarm.next_time.increment(&ONE_WEEK);
+                fired
             }
+        };
 
-            // We know there is space left, because we just popped one
-            self.alarms.push(alarm).unwrap();
+        // We know there is space left, because we just popped one
+        self.alarms.push(alarm).unwrap();
+        Some(fired)
+    }
+
+    /// Move an alarm to its next occurrence after the current time, at the
+    /// same local wall time, even if daylight saving time has changed.
+    /// Occurrences on exception dates are skipped. An alarm without another
+    /// occurrence expires
+    fn reschedule(&self, alarm: &mut Alarm) {
+        alarm.advance(&self.time, &self.exceptions);
+    }
+}
+
+/// An iterator over the alarms of a `Wecker`, soonest first.
+/// Alarms firing at the same time are ordered by handle
+pub struct Alarms<'a, ALARMS>
+where
+    ALARMS: ArrayLength<Alarm>,
+{
+    heap: &'a BinaryHeap<Alarm, ALARMS, Min>,
+    last: Option<&'a Alarm>,
+    remaining: usize,
+}
+
+impl<'a, ALARMS> Iterator for Alarms<'a, ALARMS>
+where
+    ALARMS: ArrayLength<Alarm>,
+{
+    type Item = &'a Alarm;
+
+    fn next(&mut self) -> Option<&'a Alarm> {
+        // The heap is only partially sorted, so find the smallest alarm
+        // after the previously returned one. Handles are unique, so the
+        // ordering is strict
+        let last = self.last;
+        let next = self
+            .heap
+            .iter()
+            .filter(|alarm| last.map(|last| *alarm > last).unwrap_or(true))
+            .min()?;
+
+        self.last = Some(next);
+        self.remaining -= 1;
+        Some(next)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, ALARMS> ExactSizeIterator for Alarms<'a, ALARMS> where ALARMS: ArrayLength<Alarm> {}
+
+/// The local time of day, in seconds after midnight
+fn wall_time(time: &Uhr) -> u32 {
+    let local = time.into_local_date_time();
+    u32::from(local.hour()) * 3600 + u32::from(local.minute()) * 60 + u32::from(local.second())
+}
+
+/// Remove a range from a list of exceptions, keeping the order of the others
+fn remove_range<N>(ranges: &mut Vec<DateRange, N>, range: &DateRange) -> Result<(), Error>
+where
+    N: ArrayLength<DateRange>,
+{
+    let index = ranges
+        .iter()
+        .position(|other| other == range)
+        .ok_or(Error::UnknownException)?;
+
+    // `Vec::remove` is not available, so move the range to the end first
+    ranges[index..].rotate_left(1);
+    ranges.pop();
+
+    Ok(())
+}
+
+/// If an alarm repeats, verify that the first instance is on a repeat date
+fn check_repeat(first_time: &Uhr, recurrence: &Recurrence) -> Result<(), Error> {
+    if !recurrence.matches(first_time) {
+        return Err(Error::AlarmNotOnRepeat);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::*;
+    use core::fmt;
+    use core::marker::PhantomData;
+    use serde::de::{Deserializer, Error, SeqAccess, Visitor};
+    use serde::ser::{SerializeStruct, Serializer};
+    use serde::{Deserialize, Serialize};
+
+    impl Serialize for DayFlags {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u8(self.bits())
         }
+    }
 
-        flag
+    impl<'de> Deserialize<'de> for DayFlags {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DayFlags, D::Error> {
+            let bits = u8::deserialize(deserializer)?;
+            DayFlags::from_bits(bits).ok_or_else(|| D::Error::custom("invalid day flags"))
+        }
+    }
+
+    impl Serialize for AlarmId {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u16(self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AlarmId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AlarmId, D::Error> {
+            u16::deserialize(deserializer).map(AlarmId)
+        }
+    }
+
+    /// A label, encoded as a string
+    struct LabelRepr(Label);
+
+    impl Serialize for LabelRepr {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for LabelRepr {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LabelRepr, D::Error> {
+            struct LabelVisitor;
+
+            impl<'de> Visitor<'de> for LabelVisitor {
+                type Value = LabelRepr;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a string of at most 16 bytes")
+                }
+
+                fn visit_str<E: Error>(self, value: &str) -> Result<LabelRepr, E> {
+                    let mut label = Label::new();
+                    label
+                        .push_str(value)
+                        .map_err(|_| E::custom("label too long"))?;
+                    Ok(LabelRepr(label))
+                }
+            }
+
+            deserializer.deserialize_str(LabelVisitor)
+        }
+    }
+
+    /// The encoded form of an `Alarm`. The order of the fields is part of
+    /// the encoding, and must not change
+    #[derive(Serialize, Deserialize)]
+    struct AlarmRepr {
+        id: AlarmId,
+        label: LabelRepr,
+        next_time: Uhr,
+        recurrence: Recurrence,
+        wall_time: u32,
+        snoozes: u8,
+        snoozed_until: Option<Uhr>,
+        expired: bool,
+        exceptions: RangeList<U4>,
+    }
+
+    impl Serialize for Alarm {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            AlarmRepr {
+                id: self.id,
+                label: LabelRepr(self.label.clone()),
+                next_time: self.next_time,
+                recurrence: self.recurrence,
+                wall_time: self.wall_time,
+                snoozes: self.snoozes,
+                snoozed_until: self.snoozed_until(),
+                expired: self.is_expired(),
+                exceptions: RangeList(self.exceptions.clone()),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Alarm {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Alarm, D::Error> {
+            let repr = AlarmRepr::deserialize(deserializer)?;
+            if repr.wall_time >= 24 * 60 * 60 {
+                return Err(D::Error::custom("wall time out of range"));
+            }
+
+            let mut alarm = Alarm {
+                id: repr.id,
+                label: repr.label.0,
+                next_time: repr.next_time,
+                recurrence: repr.recurrence,
+                snoozes: repr.snoozes,
+                wall_time: repr.wall_time,
+                exceptions: repr.exceptions.0,
+                snoozed_until: repr.next_time,
+                state: State::empty(),
+            };
+
+            alarm.set_snooze(repr.snoozed_until);
+            alarm.state.set(State::EXPIRED, repr.expired);
+            Ok(alarm)
+        }
+    }
+
+    /// A list of exceptions, encoded as a sequence
+    struct RangeList<N>(Vec<DateRange, N>)
+    where
+        N: ArrayLength<DateRange>;
+
+    impl<N> Serialize for RangeList<N>
+    where
+        N: ArrayLength<DateRange>,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter())
+        }
+    }
+
+    impl<'de, N> Deserialize<'de> for RangeList<N>
+    where
+        N: ArrayLength<DateRange>,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct ListVisitor<N>(PhantomData<N>);
+
+            impl<'de, N> Visitor<'de> for ListVisitor<N>
+            where
+                N: ArrayLength<DateRange>,
+            {
+                type Value = RangeList<N>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a sequence of date ranges")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                    let mut ranges = Vec::new();
+
+                    while let Some(range) = seq.next_element()? {
+                        ranges
+                            .push(range)
+                            .map_err(|_| A::Error::custom("too many exceptions"))?;
+                    }
+
+                    Ok(RangeList(ranges))
+                }
+            }
+
+            deserializer.deserialize_seq(ListVisitor(PhantomData))
+        }
+    }
+
+    /// The alarms of a `Wecker`, encoded as a sequence in firing order
+    struct AlarmList<'a, ALARMS>(&'a Wecker<ALARMS>)
+    where
+        ALARMS: ArrayLength<Alarm>;
+
+    impl<'a, ALARMS> Serialize for AlarmList<'a, ALARMS>
+    where
+        ALARMS: ArrayLength<Alarm>,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.alarms())
+        }
+    }
+
+    struct AlarmHeap<ALARMS>(BinaryHeap<Alarm, ALARMS, Min>)
+    where
+        ALARMS: ArrayLength<Alarm>;
+
+    impl<'de, ALARMS> Deserialize<'de> for AlarmHeap<ALARMS>
+    where
+        ALARMS: ArrayLength<Alarm>,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct HeapVisitor<ALARMS>(PhantomData<ALARMS>);
+
+            impl<'de, ALARMS> Visitor<'de> for HeapVisitor<ALARMS>
+            where
+                ALARMS: ArrayLength<Alarm>,
+            {
+                type Value = AlarmHeap<ALARMS>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a sequence of alarms")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                    let mut heap = BinaryHeap::new();
+
+                    while let Some(alarm) = seq.next_element::<Alarm>()? {
+                        if heap.iter().any(|other: &Alarm| other.id == alarm.id) {
+                            return Err(A::Error::custom("duplicate alarm id"));
+                        }
+
+                        heap.push(alarm)
+                            .map_err(|_| A::Error::custom("too many alarms"))?;
+                    }
+
+                    Ok(AlarmHeap(heap))
+                }
+            }
+
+            deserializer.deserialize_seq(HeapVisitor(PhantomData))
+        }
+    }
+
+    /// The encoded form of a `Wecker`, used for deserializing. The order of
+    /// the fields is part of the encoding, and must not change
+    #[derive(Deserialize)]
+    #[serde(bound(deserialize = ""))]
+    struct WeckerRepr<ALARMS>
+    where
+        ALARMS: ArrayLength<Alarm>,
+    {
+        time: Uhr,
+        alarms: AlarmHeap<ALARMS>,
+        next_id: u16,
+        max_snoozes: u8,
+        exceptions: RangeList<U16>,
+    }
+
+    /// A complete snapshot of the clock, all alarms, and settings
+    impl<ALARMS> Serialize for Wecker<ALARMS>
+    where
+        ALARMS: ArrayLength<Alarm>,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("WeckerRepr", 5)?;
+            state.serialize_field("time", &self.time)?;
+            state.serialize_field("alarms", &AlarmList(self))?;
+            state.serialize_field("next_id", &self.next_id)?;
+            state.serialize_field("max_snoozes", &self.max_snoozes)?;
+            state.serialize_field("exceptions", &RangeList(self.exceptions.clone()))?;
+            state.end()
+        }
+    }
+
+    impl<'de, ALARMS> Deserialize<'de> for Wecker<ALARMS>
+    where
+        ALARMS: ArrayLength<Alarm>,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let repr = WeckerRepr::deserialize(deserializer)?;
+
+            Ok(Wecker {
+                time: repr.time,
+                alarms: repr.alarms.0,
+                next_id: repr.next_id,
+                max_snoozes: repr.max_snoozes,
+                exceptions: repr.exceptions.0,
+            })
+        }
     }
 }
 
@@ -228,4 +1054,380 @@ mod tests {
             7
         );
     }
+
+    #[test]
+    fn insert_remove_update() {
+        use heapless::consts::U4;
+
+        // Sunday, 2019-03-31 14:11:26 UTC
+        let now = Uhr::from(UnixTimestamp(1554041486));
+        let mut wecker: Wecker<U4> = Wecker::from(now);
+
+        let a = wecker.insert_alarm(now.incremented(&Duration::from_secs(10)), DayFlags::SUNDAY).unwrap();
+        let b = wecker.insert_alarm(now.incremented(&Duration::from_secs(5)), DayFlags::empty()).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(wecker.alarms().count(), 2);
+
+        assert_eq!(
+            wecker.insert_alarm(now, DayFlags::MONDAY),
+            Err(Error::AlarmNotOnRepeat)
+        );
+
+        let removed = wecker.remove_alarm(b).unwrap();
+        assert_eq!(removed.id(), b);
+        assert_eq!(removed.repeat(), DayFlags::empty());
+        assert_eq!(wecker.remove_alarm(b).unwrap_err(), Error::UnknownAlarm);
+
+        let later = now.incremented(&Duration::from_secs(7 * 24 * 60 * 60));
+        wecker.update_alarm(a, later, DayFlags::WEEKENDS).unwrap();
+        let alarm = wecker.alarms().next().unwrap();
+        assert_eq!(alarm.id(), a);
+        assert_eq!(alarm.next_time(), later);
+        assert_eq!(alarm.repeat(), DayFlags::WEEKENDS);
+
+        assert_eq!(
+            wecker.update_alarm(b, later, DayFlags::empty()),
+            Err(Error::UnknownAlarm)
+        );
+        assert_eq!(
+            wecker.update_alarm(a, later, DayFlags::MONDAY),
+            Err(Error::AlarmNotOnRepeat)
+        );
+
+        for _ in 0..3 {
+            wecker.insert_alarm(later, DayFlags::empty()).unwrap();
+        }
+        assert_eq!(
+            wecker.insert_alarm(later, DayFlags::empty()),
+            Err(Error::AlarmFull)
+        );
+    }
+
+    #[test]
+    fn alarms_in_firing_order() {
+        use heapless::consts::U8;
+
+        let now = Uhr::from(UnixTimestamp(1554041486));
+        let at = |secs| now.incremented(&Duration::from_secs(secs));
+        let mut wecker: Wecker<U8> = Wecker::from(now);
+
+        let mut ids = [AlarmId(0); 6];
+        for (id, secs) in ids.iter_mut().zip([50, 10, 40, 10, 30, 20].iter()) {
+            *id = wecker.insert_alarm(at(*secs), DayFlags::empty()).unwrap();
+        }
+
+        let order: heapless::Vec<AlarmId, U8> = wecker.alarms().map(Alarm::id).collect();
+        assert_eq!(&order[..], &[ids[1], ids[3], ids[5], ids[4], ids[2], ids[0]]);
+        assert_eq!(wecker.alarms().len(), 6);
+        assert_eq!(wecker.next_alarm().map(Alarm::id), Some(ids[1]));
+
+        let alarm = wecker.get_alarm(ids[2]).unwrap();
+        assert_eq!(alarm.next_time(), at(40));
+        assert_eq!(alarm.repeat(), DayFlags::empty());
+
+        wecker.remove_alarm(ids[1]).unwrap();
+        assert!(wecker.get_alarm(ids[1]).is_none());
+        assert_eq!(wecker.next_alarm().map(Alarm::id), Some(ids[3]));
+
+        // Firing an alarm removes it from the list
+        wecker.time = at(10);
+        assert!(wecker.alarm_ready());
+        let times: heapless::Vec<Uhr, U8> = wecker.alarms().map(Alarm::next_time).collect();
+        assert_eq!(&times[..], &[at(20), at(30), at(40), at(50)]);
+    }
+
+    #[test]
+    fn snooze_and_dismiss() {
+        use heapless::consts::U4;
+
+        const MINUTE: Duration = Duration::from_secs(60);
+        const ONE_WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+        // Sunday, 2019-03-31 14:11:26 UTC
+        let start = Uhr::from(UnixTimestamp(1554041486));
+        let at = |mins| start.incremented(&(mins * MINUTE));
+        let mut wecker: Wecker<U4> = Wecker::from(start);
+
+        let weekly = wecker.insert_alarm(at(10), DayFlags::SUNDAY).unwrap();
+        let once = wecker.insert_alarm(at(20), DayFlags::empty()).unwrap();
+        wecker.set_label(weekly, Label::from("wake up")).unwrap();
+        assert_eq!(wecker.next_fired(), None);
+
+        // The weekly alarm fires, and is rescheduled for next week
+        wecker.time = at(12);
+        let fired = wecker.next_fired().unwrap();
+        assert_eq!(fired.id, weekly);
+        assert_eq!(&fired.label[..], "wake up");
+        assert_eq!(fired.scheduled, at(10));
+        assert_eq!(fired.snoozes, 0);
+        assert_eq!(wecker.next_fired(), None);
+
+        // Snoozing fires once more, without moving the weekly schedule
+        wecker.snooze(weekly, 5 * MINUTE).unwrap();
+        let alarm = wecker.get_alarm(weekly).unwrap();
+        assert_eq!(alarm.next_time(), at(10).incremented(&ONE_WEEK));
+        assert_eq!(alarm.due(), Some(at(17)));
+        assert_eq!(wecker.next_alarm().map(Alarm::id), Some(weekly));
+
+        wecker.time = at(17);
+        let fired = wecker.next_fired().unwrap();
+        assert_eq!((fired.id, fired.scheduled, fired.snoozes), (weekly, at(17), 1));
+        assert_eq!(wecker.next_fired(), None);
+
+        // Snoozing is limited
+        wecker.set_max_snoozes(2);
+        wecker.snooze(weekly, MINUTE).unwrap();
+        assert_eq!(wecker.snooze(weekly, MINUTE), Err(Error::SnoozeLimit));
+        wecker.dismiss(weekly).unwrap();
+        assert_eq!(wecker.get_alarm(weekly).unwrap().due(), Some(at(10).incremented(&ONE_WEEK)));
+        assert_eq!(wecker.get_alarm(weekly).unwrap().snoozes(), 0);
+
+        // A one-shot alarm may be snoozed after firing, until dismissed
+        wecker.time = at(20);
+        let fired = wecker.next_fired().unwrap();
+        assert_eq!((fired.id, fired.scheduled), (once, at(20)));
+        assert_eq!(wecker.get_alarm(once).unwrap().due(), None);
+        assert_eq!(wecker.alarms().last().map(Alarm::id), Some(once));
+
+        wecker.snooze(once, 5 * MINUTE).unwrap();
+        wecker.time = at(30);
+        let fired = wecker.next_fired().unwrap();
+        assert_eq!((fired.id, fired.scheduled, fired.snoozes), (once, at(25), 1));
+        assert_eq!(wecker.next_fired(), None);
+
+        wecker.dismiss(once).unwrap();
+        assert!(wecker.get_alarm(once).is_none());
+        assert_eq!(wecker.dismiss(once), Err(Error::UnknownAlarm));
+        assert_eq!(wecker.alarms().len(), 1);
+    }
+
+    fn cet_clock(time: i64) -> Uhr {
+        let mut clock = Uhr::from(UnixTimestamp(time));
+        clock.set_local_time_zone(crate::TimeZoneRule::parse_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap());
+        clock
+    }
+
+    /// Fire the next alarm at its due time, returning when it will fire next
+    fn fire_next<A: ArrayLength<Alarm>>(wecker: &mut Wecker<A>) -> UnixTimestamp {
+        wecker.time = wecker.next_alarm().unwrap().due().unwrap();
+        let fired = wecker.next_fired().unwrap();
+        assert_eq!(fired.scheduled, wecker.time);
+        wecker.get_alarm(fired.id).unwrap().next_time().timestamp()
+    }
+
+    #[test]
+    fn dst_keeps_wall_time() {
+        use heapless::consts::U1;
+        let daily = DayFlags::WEEKDAYS | DayFlags::WEEKENDS;
+
+        // 07:00 CET on Saturday 2019-03-30, the day before clocks go forward
+        let mut wecker: Wecker<U1> = Wecker::from(cet_clock(1553925600 - 60));
+        wecker.insert_alarm(cet_clock(1553925600), daily).unwrap();
+
+        // 07:00 CEST on Sunday, one hour earlier in UTC
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1554008400));
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1554008400 + 86400));
+    }
+
+    #[test]
+    fn dst_spring_forward_gap() {
+        use heapless::consts::U1;
+        let daily = DayFlags::WEEKDAYS | DayFlags::WEEKENDS;
+
+        // 02:30 CET on Saturday 2019-03-30
+        let mut wecker: Wecker<U1> = Wecker::from(cet_clock(1553909400 - 60));
+        wecker.insert_alarm(cet_clock(1553909400), daily).unwrap();
+
+        // 02:30 is skipped on Sunday, so the alarm fires at 03:30 CEST
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1553995800));
+        assert_eq!(
+            cet_clock(1553995800).into_local_date_time().hour(),
+            3
+        );
+
+        // And is back to 02:30 CEST on Monday
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1554078600));
+        let local = cet_clock(1554078600).into_local_date_time();
+        assert_eq!((local.hour(), local.minute()), (2, 30));
+    }
+
+    #[test]
+    fn dst_fall_back_overlap() {
+        use heapless::consts::U1;
+        let daily = DayFlags::WEEKDAYS | DayFlags::WEEKENDS;
+
+        // 02:30 CEST on Saturday 2019-10-26
+        let mut wecker: Wecker<U1> = Wecker::from(cet_clock(1572049800 - 60));
+        wecker.insert_alarm(cet_clock(1572049800), daily).unwrap();
+
+        // 02:30 happens twice on Sunday, the alarm only fires the first time
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1572136200));
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1572226200));
+
+        wecker.time = cet_clock(1572136200 + 3600);
+        assert_eq!(wecker.next_fired(), None);
+    }
+
+    #[test]
+    fn calendar_recurrence() {
+        use heapless::consts::U2;
+
+        // 07:00 CET on Monday 2019-03-04, the first Monday of the month
+        let mut wecker: Wecker<U2> = Wecker::from(cet_clock(1551679200 - 60));
+        let first_monday = Recurrence::monthly_on_weekday(1, DayOfTheWeek::Monday).unwrap();
+        let monthly = wecker.insert_alarm(cet_clock(1551679200), first_monday).unwrap();
+        assert_eq!(wecker.get_alarm(monthly).unwrap().recurrence(), first_monday);
+        assert!(wecker.get_alarm(monthly).unwrap().repeat().is_empty());
+
+        // The first instance must be on the schedule
+        let tuesday = cet_clock(1551679200 + 86400);
+        assert_eq!(wecker.insert_alarm(tuesday, first_monday), Err(Error::AlarmNotOnRepeat));
+        let ninety = Recurrence::interval(Duration::from_secs(90 * 60)).unwrap();
+        let interval = wecker.insert_alarm(tuesday, ninety).unwrap();
+
+        // The monthly alarm fires and moves on to 07:00 CEST on Monday
+        // 2019-04-01, then the interval alarm fires and moves on 90 minutes
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1554094800));
+        assert_eq!(fire_next(&mut wecker), UnixTimestamp(1551679200 + 86400 + 90 * 60));
+        assert_eq!(wecker.alarms().map(Alarm::id).collect::<heapless::Vec<_, U2>>(), [interval, monthly]);
+
+        // A missed interval alarm skips to its next occurrence in the future
+        wecker.time = cet_clock(1551679200 + 86400 + 10 * 3600);
+        assert_eq!(wecker.next_fired().unwrap().id, interval);
+        assert_eq!(
+            wecker.get_alarm(interval).unwrap().next_time().timestamp(),
+            UnixTimestamp(1551679200 + 86400 + 105 * 6 * 60)
+        );
+
+        // 07:00 CEST on Monday 2019-05-06
+        wecker.remove_alarm(interval).unwrap();
+        wecker.time = cet_clock(1554094800);
+        assert_eq!(wecker.next_fired().unwrap().id, monthly);
+        assert_eq!(wecker.get_alarm(monthly).unwrap().next_time().timestamp(), UnixTimestamp(1557118800));
+    }
+
+    #[test]
+    fn exceptions_and_skip() {
+        use gregor::{Month, NaiveDateTime};
+        use heapless::consts::U2;
+
+        // 07:00 UTC on the given day of April 2019, the 1st is a Monday
+        let april = |day: i64| Uhr::from(UnixTimestamp(1554102000 + (day - 1) * 86400));
+        let date = |day: u8| NaiveDateTime::new(2019, Month::April, day, 0, 0, 0);
+        let next = |wecker: &Wecker<U2>, id| wecker.get_alarm(id).unwrap().next_time();
+
+        let mut wecker: Wecker<U2> = Wecker::from(april(1).incremented(&Duration::from_secs(1)));
+        wecker.add_exception(DateRange::day(&date(3))).unwrap();
+
+        // A new alarm skips exceptions, even for its first occurrence
+        let weekdays = wecker.insert_alarm(april(3), DayFlags::WEEKDAYS).unwrap();
+        assert_eq!(next(&wecker, weekdays), april(4));
+        wecker.update_alarm(weekdays, april(2), DayFlags::WEEKDAYS).unwrap();
+
+        // One-shot alarms ignore exceptions
+        let once = wecker.insert_alarm(april(3), DayFlags::empty()).unwrap();
+        assert_eq!(next(&wecker, once), april(3));
+
+        wecker.add_alarm_exception(weekdays, DateRange::new(&date(8), &date(12)).unwrap()).unwrap();
+        assert_eq!(wecker.get_alarm(weekdays).unwrap().exceptions().len(), 1);
+
+        // Tuesday, then the holiday on Wednesday is skipped
+        assert_eq!(fire_next(&mut wecker), april(4).timestamp());
+        wecker.time = april(3);
+        assert_eq!(wecker.next_fired().unwrap().id, once);
+        wecker.dismiss(once).unwrap();
+        assert_eq!(fire_next(&mut wecker), april(5).timestamp());
+
+        // Skipping Friday, then the vacation is skipped
+        wecker.skip_next(weekdays).unwrap();
+        assert_eq!(next(&wecker, weekdays), april(15));
+
+        // Adding an exception moves the current occurrence
+        wecker.add_exception(DateRange::day(&date(15))).unwrap();
+        wecker.add_exception(DateRange::day(&date(15))).unwrap();
+        assert_eq!(wecker.exceptions(), &[DateRange::day(&date(3)), DateRange::day(&date(15))]);
+        assert_eq!(next(&wecker, weekdays), april(16));
+
+        // Removing exceptions does not restore skipped occurrences
+        wecker.remove_exception(&DateRange::day(&date(15))).unwrap();
+        assert_eq!(wecker.exceptions(), &[DateRange::day(&date(3))]);
+        assert_eq!(wecker.remove_exception(&DateRange::day(&date(15))), Err(Error::UnknownException));
+        assert_eq!(next(&wecker, weekdays), april(16));
+
+        let vacation = DateRange::new(&date(8), &date(12)).unwrap();
+        wecker.remove_alarm_exception(weekdays, &vacation).unwrap();
+        assert_eq!(wecker.remove_alarm_exception(weekdays, &vacation), Err(Error::UnknownException));
+
+        // Each alarm has room for four exceptions of its own
+        for day in 20..24 {
+            wecker.add_alarm_exception(weekdays, DateRange::day(&date(day))).unwrap();
+        }
+        assert_eq!(
+            wecker.add_alarm_exception(weekdays, DateRange::day(&date(24))),
+            Err(Error::ExceptionsFull)
+        );
+        assert_eq!(wecker.skip_next(AlarmId(42)), Err(Error::UnknownAlarm));
+
+        // Skipping a one-shot alarm means it will not fire
+        let once = wecker.insert_alarm(april(30), DayFlags::empty()).unwrap();
+        wecker.skip_next(once).unwrap();
+        assert_eq!(wecker.get_alarm(once).unwrap().due(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn postcard_round_trip() {
+        use heapless::consts::U4;
+        use postcard::{from_bytes, to_slice};
+
+        let cet = crate::TimeZoneRule::parse_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let mut now = Uhr::from_timestamp_nanos(UnixTimestamp(1554041486), 125);
+        now.set_local_time_zone(cet);
+
+        let mut wecker: Wecker<U4> = Wecker::from(now);
+        let weekly = wecker
+            .insert_alarm(now.incremented(&Duration::from_secs(60)), DayFlags::SUNDAY)
+            .unwrap();
+        let once = wecker.insert_alarm(now, DayFlags::empty()).unwrap();
+        wecker.set_label(weekly, Label::from("wake up")).unwrap();
+        wecker.set_max_snoozes(5);
+
+        let christmas = DateRange::day(&gregor::NaiveDateTime::new(2019, gregor::Month::December, 25, 0, 0, 0));
+        wecker.add_exception(christmas).unwrap();
+        wecker.add_alarm_exception(weekly, christmas).unwrap();
+
+        // An expired, snoozed alarm
+        assert_eq!(wecker.next_fired().unwrap().id, once);
+        wecker.snooze(once, Duration::from_secs(300)).unwrap();
+
+        let mut buf = [0u8; 256];
+        let used = to_slice(&wecker, &mut buf).unwrap();
+        let decoded: Wecker<U4> = from_bytes(used).unwrap();
+
+        assert_eq!(decoded.time, wecker.time);
+        assert_eq!(decoded.max_snoozes(), 5);
+        assert_eq!(decoded.exceptions(), &[christmas]);
+        assert_eq!(decoded.get_alarm(weekly).unwrap().exceptions(), &[christmas]);
+        assert!(decoded.alarms().eq(wecker.alarms()));
+        assert_eq!(decoded.get_alarm(once).unwrap().due(), wecker.get_alarm(once).unwrap().due());
+
+        // Handles are not reused after decoding
+        let mut decoded = decoded;
+        let next = decoded.insert_alarm(now, DayFlags::empty()).unwrap();
+        assert!(next != weekly && next != once);
+
+        // Individual types
+        let used = to_slice(&DayFlags::WEEKENDS, &mut buf).unwrap();
+        assert_eq!(used, &[0b0110_0000]);
+        assert!(from_bytes::<DayFlags>(&[0x80]).is_err());
+
+        let used = to_slice(&Uhr::from(UnixTimestamp(1)), &mut buf).unwrap();
+        assert_eq!(used, &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let used = to_slice(&now, &mut buf).unwrap();
+        assert_eq!(from_bytes::<Uhr>(used).unwrap(), now);
+
+        // Too many alarms for the destination
+        let used = to_slice(&wecker, &mut buf).unwrap();
+        assert!(from_bytes::<Wecker<heapless::consts::U1>>(used).is_err());
+    }
 }
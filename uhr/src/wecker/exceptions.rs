@@ -0,0 +1,150 @@
+//! Dates on which repeating alarms do not fire
+//!
+//! Exceptions are ranges of local dates, such as a single public holiday,
+//! or the days of a vacation. They may be set for a single alarm, or for
+//! all alarms of a `Wecker`. When a repeating alarm is rescheduled, any
+//! occurrences on an exception date are skipped.
+
+use gregor::{NaiveDateTime, TimeZone, UnixTimestamp, Utc};
+
+use crate::tz::{epoch_days, SECONDS_PER_DAY};
+use crate::uhr::Uhr;
+
+/// A range of local dates, including the first and last date
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash)]
+pub struct DateRange {
+    /// Days since 1970-01-01
+    first: i32,
+    last: i32,
+}
+
+impl DateRange {
+    /// A single date. Only the date of `date` is used, the time of day
+    /// is ignored
+    pub fn day(date: &NaiveDateTime) -> Self {
+        let day = epoch_days(date) as i32;
+
+        DateRange {
+            first: day,
+            last: day,
+        }
+    }
+
+    /// All dates from `first` to `last`, inclusive. Only the dates are used,
+    /// the times of day are ignored. `None` is returned if `last` is before
+    /// `first`
+    pub fn new(first: &NaiveDateTime, last: &NaiveDateTime) -> Option<Self> {
+        let first = epoch_days(first) as i32;
+        let last = epoch_days(last) as i32;
+
+        if last < first {
+            return None;
+        }
+
+        Some(DateRange { first, last })
+    }
+
+    /// The first date of the range, at midnight
+    pub fn first(&self) -> NaiveDateTime {
+        midnight(self.first)
+    }
+
+    /// The last date of the range, at midnight
+    pub fn last(&self) -> NaiveDateTime {
+        midnight(self.last)
+    }
+
+    /// Is the date of `date` within the range?
+    pub fn contains(&self, date: &NaiveDateTime) -> bool {
+        let day = epoch_days(date);
+        i64::from(self.first) <= day && day <= i64::from(self.last)
+    }
+
+    /// The last moment of the range, in the time zone of `time`
+    pub(super) fn end(&self, time: &Uhr) -> Uhr {
+        let mut end = Uhr::from_local_date_time(&midnight(self.last + 1), time.local_time_zone());
+        end.adjust(-1);
+        end
+    }
+}
+
+/// Midnight, `days` days after 1970-01-01
+fn midnight(days: i32) -> NaiveDateTime {
+    Utc.from_timestamp(UnixTimestamp(i64::from(days) * SECONDS_PER_DAY))
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::*;
+    use serde::de::{Deserializer, Error};
+    use serde::{Deserialize, Serialize, Serializer};
+
+    impl Serialize for DateRange {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            (self.first, self.last).serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for DateRange {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DateRange, D::Error> {
+            let (first, last) = <(i32, i32)>::deserialize(deserializer)?;
+            if last < first {
+                return Err(D::Error::custom("date range ends before it starts"));
+            }
+
+            Ok(DateRange { first, last })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimeZoneRule;
+    use gregor::Month;
+
+    fn date(year: i32, month: Month, day: u8) -> NaiveDateTime {
+        NaiveDateTime::new(year, month, day, 0, 0, 0)
+    }
+
+    #[test]
+    fn ranges() {
+        let christmas = DateRange::day(&NaiveDateTime::new(2019, Month::December, 25, 13, 30, 0));
+        assert_eq!(christmas.first(), date(2019, Month::December, 25));
+        assert_eq!(christmas.last(), date(2019, Month::December, 25));
+        assert!(christmas.contains(&NaiveDateTime::new(2019, Month::December, 25, 23, 59, 59)));
+        assert!(!christmas.contains(&date(2019, Month::December, 26)));
+
+        let holidays = DateRange::new(
+            &date(2019, Month::December, 23),
+            &date(2020, Month::January, 6),
+        )
+        .unwrap();
+        assert!(holidays.contains(&date(2019, Month::December, 23)));
+        assert!(holidays.contains(&date(2020, Month::January, 1)));
+        assert!(holidays.contains(&date(2020, Month::January, 6)));
+        assert!(!holidays.contains(&date(2019, Month::December, 22)));
+        assert!(!holidays.contains(&date(2020, Month::January, 7)));
+
+        assert_eq!(
+            DateRange::new(&date(2019, Month::March, 2), &date(2019, Month::March, 1)),
+            None
+        );
+        assert_eq!(
+            DateRange::day(&date(1969, Month::December, 31)).first(),
+            date(1969, Month::December, 31)
+        );
+    }
+
+    #[test]
+    fn end() {
+        // The last day of March 2019 is only 23 hours long in CET
+        let cet = TimeZoneRule::parse_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let time = Uhr::from_local_date_time(&date(2019, Month::March, 1), cet);
+
+        let range = DateRange::day(&date(2019, Month::March, 31));
+        let end = range.end(&time);
+        assert_eq!(end.timestamp(), UnixTimestamp(1554069600 - 1));
+        assert_eq!(end.subsec_nanos(), 999_999_999);
+    }
+}
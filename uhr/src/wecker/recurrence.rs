@@ -0,0 +1,707 @@
+//! Calendar based schedules for repeating alarms
+//!
+//! Besides repeating on days of the week, alarms may repeat every few days,
+//! after a fixed interval of time, on a day of each month, or on a day of
+//! each year. Apart from fixed intervals, occurrences always happen at the
+//! same local wall time, even if daylight saving time changes in between.
+
+use core::time::Duration;
+
+use gregor::{DayOfTheWeek, Month, NaiveDateTime, TimeZone, UnixTimestamp, Utc, YearKind};
+
+use super::{wall_time, DayFlags};
+use crate::tz::{epoch_days, nth_weekday, SECONDS_PER_DAY};
+use crate::uhr::{Uhr, NANOS_PER_SEC};
+
+/// How many months to search ahead for the next occurrence. February 29th
+/// may not occur for eight years, for example between 2096 and 2104
+const SEARCH_MONTHS: i32 = 9 * 12 + 1;
+
+// The kinds of recurrence. An enum is not used, see `TimeZoneRule`
+const KIND_ONCE: u8 = 0;
+const KIND_WEEKLY: u8 = 1;
+const KIND_EVERY_DAYS: u8 = 2;
+const KIND_INTERVAL: u8 = 3;
+const KIND_MONTHLY_DAY: u8 = 4;
+const KIND_MONTHLY_WEEKDAY: u8 = 5;
+const KIND_YEARLY: u8 = 6;
+
+/// The schedule an alarm repeats on. Only contains plain integers, see
+/// `TimeZoneRule`
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Recurrence {
+    kind: u8,
+    /// The bits of the `DayFlags` of a weekly schedule
+    days: u8,
+    month: u8,
+    week: u8,
+    day: u8,
+    /// Days for `KIND_EVERY_DAYS`, seconds for `KIND_INTERVAL`
+    interval: u32,
+}
+
+impl Recurrence {
+    /// Never repeat
+    pub const ONCE: Recurrence = Recurrence {
+        kind: KIND_ONCE,
+        days: 0,
+        month: 0,
+        week: 0,
+        day: 0,
+        interval: 0,
+    };
+
+    /// Repeat on each of the given days of the week. No days at all means
+    /// the alarm never repeats
+    pub fn weekly(days: DayFlags) -> Self {
+        if days.is_empty() {
+            return Recurrence::ONCE;
+        }
+
+        Recurrence {
+            kind: KIND_WEEKLY,
+            days: days.bits(),
+            ..Recurrence::ONCE
+        }
+    }
+
+    /// Repeat every `days` days, such as every 14 days for an alarm every
+    /// other week. `None` is returned if `days` is zero
+    pub fn every_days(days: u16) -> Option<Self> {
+        if days == 0 {
+            return None;
+        }
+
+        Some(Recurrence {
+            kind: KIND_EVERY_DAYS,
+            interval: u32::from(days),
+            ..Recurrence::ONCE
+        })
+    }
+
+    /// Repeat after a fixed amount of time has passed, such as every 90
+    /// minutes, ignoring any changes to the local time. `None` is returned
+    /// if `period` is not a whole, non-zero number of seconds, or does not
+    /// fit in 32 bits
+    pub fn interval(period: Duration) -> Option<Self> {
+        if period.subsec_nanos() != 0
+            || period.as_secs() == 0
+            || period.as_secs() > u64::from(u32::MAX)
+        {
+            return None;
+        }
+
+        Some(Recurrence {
+            kind: KIND_INTERVAL,
+            interval: period.as_secs() as u32,
+            ..Recurrence::ONCE
+        })
+    }
+
+    /// Repeat on the `day`th day of every month, skipping months that are
+    /// too short. `None` is returned if `day` is not between 1 and 31
+    pub fn monthly_on_day(day: u8) -> Option<Self> {
+        if !(1..=31).contains(&day) {
+            return None;
+        }
+
+        Some(Recurrence {
+            kind: KIND_MONTHLY_DAY,
+            day,
+            ..Recurrence::ONCE
+        })
+    }
+
+    /// Repeat on the `week`th `weekday` of every month, where a `week` of 5
+    /// means the last `weekday` of the month. `None` is returned if `week`
+    /// is not between 1 and 5
+    pub fn monthly_on_weekday(week: u8, weekday: DayOfTheWeek) -> Option<Self> {
+        if !(1..=5).contains(&week) {
+            return None;
+        }
+
+        Some(Recurrence {
+            kind: KIND_MONTHLY_WEEKDAY,
+            week,
+            day: weekday.to_iso_number(),
+            ..Recurrence::ONCE
+        })
+    }
+
+    /// Repeat on the same date every year. An alarm on February 29th only
+    /// repeats in leap years. `None` is returned if `month` never has `day`
+    pub fn yearly(month: Month, day: u8) -> Option<Self> {
+        if day == 0 || day > month.length(YearKind::Leap) {
+            return None;
+        }
+
+        Some(Recurrence {
+            kind: KIND_YEARLY,
+            month: month.to_number(),
+            day,
+            ..Recurrence::ONCE
+        })
+    }
+
+    /// Does this schedule never repeat?
+    pub fn is_once(&self) -> bool {
+        self.kind == KIND_ONCE
+    }
+
+    /// The days of the week a weekly schedule repeats on. Empty for all
+    /// other schedules
+    pub fn days(&self) -> DayFlags {
+        DayFlags::from_bits_truncate(self.days)
+    }
+
+    /// The kind and parameters of this schedule
+    pub fn schedule(&self) -> Schedule {
+        match self.kind {
+            KIND_WEEKLY => Schedule::Weekly(self.days()),
+            KIND_EVERY_DAYS => Schedule::EveryDays(self.interval as u16),
+            KIND_INTERVAL => Schedule::Interval(self.interval),
+            KIND_MONTHLY_DAY => Schedule::MonthlyOnDay(self.day),
+            KIND_MONTHLY_WEEKDAY => Schedule::MonthlyOnWeekday {
+                week: self.week,
+                weekday: self.day,
+            },
+            KIND_YEARLY => Schedule::Yearly {
+                month: self.month,
+                day: self.day,
+            },
+            _ => Schedule::Once,
+        }
+    }
+
+    /// A recurrence from its kind and parameters. `None` is returned if the
+    /// matching constructor would return `None`
+    pub fn from_schedule(schedule: Schedule) -> Option<Self> {
+        match schedule {
+            Schedule::Once => Some(Recurrence::ONCE),
+            Schedule::Weekly(days) => Some(Recurrence::weekly(days)),
+            Schedule::EveryDays(days) => Recurrence::every_days(days),
+            Schedule::Interval(secs) => Recurrence::interval(Duration::from_secs(u64::from(secs))),
+            Schedule::MonthlyOnDay(day) => Recurrence::monthly_on_day(day),
+            Schedule::MonthlyOnWeekday { week, weekday } => DayOfTheWeek::from_iso_number(weekday)
+                .and_then(|weekday| Recurrence::monthly_on_weekday(week, weekday)),
+            Schedule::Yearly { month, day } => {
+                Month::from_number(month).and_then(|month| Recurrence::yearly(month, day))
+            }
+        }
+    }
+
+    /// Is the local date of `time` one of the dates of this schedule?
+    /// Schedules that do not depend on the date match any time
+    pub fn matches(&self, time: &Uhr) -> bool {
+        let date = time.into_local_date_time().naive;
+
+        match self.kind {
+            KIND_WEEKLY => self.days().contains(DayFlags::from(date.day_of_the_week())),
+            KIND_MONTHLY_DAY | KIND_MONTHLY_WEEKDAY | KIND_YEARLY => {
+                self.day_in_month(date.year, date.month) == Some(date.day)
+            }
+            _ => true,
+        }
+    }
+
+    /// The first occurrence strictly after `after`, of a series of
+    /// occurrences starting at `start`. The local wall time, time zone, and
+    /// for intervals, the phase of the series are taken from `start`.
+    /// `None` is returned if there is no such occurrence
+    pub fn next_after(&self, start: &Uhr, after: &Uhr) -> Option<Uhr> {
+        self.next_occurrence(start, wall_time(start), after)
+    }
+
+    /// Like `next_after`, with the local wall time given separately, in
+    /// seconds after midnight, as `previous` may have been moved by a
+    /// daylight saving time change
+    pub(crate) fn next_occurrence(
+        &self,
+        previous: &Uhr,
+        wall_time: u32,
+        after: &Uhr,
+    ) -> Option<Uhr> {
+        match self.kind {
+            KIND_ONCE => Some(*previous).filter(|previous| previous > after),
+            KIND_INTERVAL => {
+                let period = i128::from(self.interval) * NANOS_PER_SEC;
+                let behind = after.as_nanos() - previous.as_nanos();
+                if behind < 0 {
+                    return Some(*previous);
+                }
+
+                let offset = (behind / period + 1) * period;
+                if offset > i128::from(i64::MAX) {
+                    return None;
+                }
+
+                let mut next = *previous;
+                next.adjust(offset as i64);
+                Some(next)
+            }
+            _ => self.next_date_occurrence(previous, wall_time, after),
+        }
+    }
+
+    fn next_date_occurrence(&self, previous: &Uhr, wall_time: u32, after: &Uhr) -> Option<Uhr> {
+        let tz = previous.local_time_zone();
+        let nanos = Duration::from_nanos(u64::from(previous.subsec_nanos()));
+
+        let occurrence = |days: i64| {
+            let date = Utc.from_timestamp(UnixTimestamp(days * SECONDS_PER_DAY));
+            let local = NaiveDateTime {
+                hour: (wall_time / 3600) as u8,
+                minute: (wall_time / 60 % 60) as u8,
+                second: (wall_time % 60) as u8,
+                ..date
+            };
+
+            Uhr::from_local_date_time(&local, tz).incremented(&nanos)
+        };
+
+        // Start the day before the local date of `after`, as resolving a
+        // wall time in a daylight saving gap may move it to the next day
+        let mut local_after = *after;
+        local_after.set_local_time_zone(tz);
+        let first = epoch_days(&local_after.into_local_date_time().naive) - 1;
+
+        match self.kind {
+            KIND_WEEKLY => {
+                let weekdays = self.days();
+                let mut days = first;
+                if !weekdays.contains(DayFlags::from(day_of_the_week(days))) {
+                    days += i64::from(weekdays.days_after(day_of_the_week(days)));
+                }
+
+                // A weekly schedule has an occurrence in any 8 day window
+                for _ in 0..8 {
+                    let next = occurrence(days);
+                    if next > *after {
+                        return Some(next);
+                    }
+                    days += i64::from(weekdays.days_after(day_of_the_week(days)));
+                }
+
+                None
+            }
+            KIND_EVERY_DAYS => {
+                let start = epoch_days(&previous.into_local_date_time().naive);
+                let period = i64::from(self.interval);
+                let skip = if first > start {
+                    (first - start) / period
+                } else {
+                    0
+                };
+
+                (skip..skip + 3)
+                    .map(|n| occurrence(start + n * period))
+                    .find(|next| next > after)
+            }
+            _ => {
+                let date = Utc.from_timestamp(UnixTimestamp(first * SECONDS_PER_DAY));
+                let months = date.year * 12 + i32::from(date.month.to_number()) - 1;
+
+                for months in months..months + SEARCH_MONTHS {
+                    let year = months.div_euclid(12);
+                    // Can't fail, the number is between 1 and 12
+                    let month = Month::from_number(months.rem_euclid(12) as u8 + 1).unwrap();
+
+                    if let Some(day) = self.day_in_month(year, month) {
+                        let next =
+                            occurrence(epoch_days(&NaiveDateTime::new(year, month, day, 0, 0, 0)));
+                        if next > *after {
+                            return Some(next);
+                        }
+                    }
+                }
+
+                None
+            }
+        }
+    }
+
+    /// The day of `month` this schedule occurs on, if any. Only used for
+    /// monthly and yearly schedules
+    fn day_in_month(&self, year: i32, month: Month) -> Option<u8> {
+        let length = month.length(YearKind::from(year));
+
+        match self.kind {
+            KIND_MONTHLY_DAY => Some(self.day).filter(|day| *day <= length),
+            KIND_MONTHLY_WEEKDAY => Some(nth_weekday(year, month, self.day, self.week)),
+            KIND_YEARLY if month.to_number() == self.month => {
+                Some(self.day).filter(|day| *day <= length)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A `Recurrence` taken apart into its kind and parameters, such as for
+/// sending it to another device. This is also the serde encoding of
+/// `Recurrence`, so the order of the variants must not change
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Schedule {
+    Once,
+    Weekly(DayFlags),
+    /// Every number of days
+    EveryDays(u16),
+    /// Every number of seconds
+    Interval(u32),
+    /// A day of every month
+    MonthlyOnDay(u8),
+    /// The `week`th `weekday` of every month, with the weekday given as its
+    /// ISO number
+    MonthlyOnWeekday {
+        week: u8,
+        weekday: u8,
+    },
+    /// A date of every year, with the month given as its number
+    Yearly {
+        month: u8,
+        day: u8,
+    },
+}
+
+impl Default for Recurrence {
+    fn default() -> Self {
+        Recurrence::ONCE
+    }
+}
+
+impl From<DayFlags> for Recurrence {
+    fn from(days: DayFlags) -> Self {
+        Recurrence::weekly(days)
+    }
+}
+
+fn day_of_the_week(epoch_days: i64) -> DayOfTheWeek {
+    // 1970-01-01 was a Thursday. Can't fail, the number is between 1 and 7
+    DayOfTheWeek::from_iso_number((epoch_days + 3).rem_euclid(7) as u8 + 1).unwrap()
+}
+
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::*;
+    use serde::de::{Deserializer, Error};
+    use serde::{Deserialize, Serialize, Serializer};
+
+    impl Serialize for Recurrence {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.schedule().serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Recurrence {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Recurrence, D::Error> {
+            let schedule = Schedule::deserialize(deserializer)?;
+            Recurrence::from_schedule(schedule)
+                .ok_or_else(|| D::Error::custom("invalid recurrence"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimeZoneRule;
+
+    const HOUR: i64 = 60 * 60;
+
+    fn utc(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> Uhr {
+        let local = NaiveDateTime::new(
+            year,
+            Month::from_number(month).unwrap(),
+            day,
+            hour,
+            minute,
+            0,
+        );
+        Uhr::from_local_date_time(&local, TimeZoneRule::UTC)
+    }
+
+    fn cet(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> Uhr {
+        let tz = TimeZoneRule::parse_posix("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let local = NaiveDateTime::new(
+            year,
+            Month::from_number(month).unwrap(),
+            day,
+            hour,
+            minute,
+            0,
+        );
+        Uhr::from_local_date_time(&local, tz)
+    }
+
+    /// The next `count` occurrences after `start`, as local dates
+    fn dates(recurrence: Recurrence, start: Uhr, count: usize) -> std::vec::Vec<(i32, u8, u8)> {
+        let mut current = start;
+        (0..count)
+            .map(|_| {
+                current = recurrence.next_after(&start, &current).unwrap();
+                let local = current.into_local_date_time();
+                (local.year(), local.month().to_number(), local.day())
+            })
+            .collect()
+    }
+
+    /// The next occurrence, found by checking every day in turn
+    fn brute_force(recurrence: Recurrence, start: &Uhr, after: &Uhr) -> Option<Uhr> {
+        let local = start.into_local_date_time();
+        let first = epoch_days(&after.into_local_date_time().naive) - 1;
+
+        (first..first + 366 * 9)
+            .map(|days| {
+                let date = Utc.from_timestamp(UnixTimestamp(days * SECONDS_PER_DAY));
+                let date = NaiveDateTime {
+                    hour: local.hour(),
+                    minute: local.minute(),
+                    second: local.second(),
+                    ..date
+                };
+                Uhr::from_local_date_time(&date, start.local_time_zone())
+                    .incremented(&Duration::from_nanos(u64::from(start.subsec_nanos())))
+            })
+            .filter(|next| recurrence.matches(next))
+            .find(|next| next > after)
+    }
+
+    #[test]
+    fn constructors() {
+        assert_eq!(Recurrence::weekly(DayFlags::empty()), Recurrence::ONCE);
+        assert_eq!(Recurrence::from(DayFlags::MONDAY).days(), DayFlags::MONDAY);
+        assert_eq!(Recurrence::every_days(0), None);
+        assert_eq!(Recurrence::interval(Duration::from_secs(0)), None);
+        assert_eq!(Recurrence::interval(Duration::from_millis(1500)), None);
+        assert_eq!(Recurrence::interval(Duration::from_secs(1 << 32)), None);
+        assert_eq!(Recurrence::monthly_on_day(0), None);
+        assert_eq!(Recurrence::monthly_on_day(32), None);
+        assert_eq!(
+            Recurrence::monthly_on_weekday(0, DayOfTheWeek::Monday),
+            None
+        );
+        assert_eq!(
+            Recurrence::monthly_on_weekday(6, DayOfTheWeek::Monday),
+            None
+        );
+        assert_eq!(Recurrence::yearly(Month::April, 31), None);
+        assert!(Recurrence::yearly(Month::February, 29).is_some());
+        assert!(Recurrence::ONCE.is_once());
+        assert!(Recurrence::every_days(1).unwrap().days().is_empty());
+    }
+
+    #[test]
+    fn matches() {
+        // Monday, 2019-04-01
+        let monday = utc(2019, 4, 1, 7, 0);
+        assert!(Recurrence::weekly(DayFlags::WEEKDAYS).matches(&monday));
+        assert!(!Recurrence::weekly(DayFlags::WEEKENDS).matches(&monday));
+        assert!(Recurrence::monthly_on_day(1).unwrap().matches(&monday));
+        assert!(!Recurrence::monthly_on_day(2).unwrap().matches(&monday));
+        assert!(Recurrence::monthly_on_weekday(1, DayOfTheWeek::Monday)
+            .unwrap()
+            .matches(&monday));
+        assert!(!Recurrence::monthly_on_weekday(5, DayOfTheWeek::Monday)
+            .unwrap()
+            .matches(&monday));
+        assert!(Recurrence::yearly(Month::April, 1)
+            .unwrap()
+            .matches(&monday));
+        assert!(!Recurrence::yearly(Month::May, 1).unwrap().matches(&monday));
+        assert!(Recurrence::ONCE.matches(&monday));
+        assert!(Recurrence::every_days(3).unwrap().matches(&monday));
+
+        // The local date is used, 23:30 UTC is already April 2nd in CET
+        let late = utc(2019, 4, 1, 23, 30);
+        let mut local = late;
+        local.set_local_time_zone(cet(2019, 4, 1, 0, 0).local_time_zone());
+        assert!(Recurrence::monthly_on_day(1).unwrap().matches(&late));
+        assert!(Recurrence::monthly_on_day(2).unwrap().matches(&local));
+    }
+
+    #[test]
+    fn once() {
+        let start = utc(2019, 4, 1, 7, 0);
+        assert_eq!(
+            Recurrence::ONCE.next_after(&start, &utc(2019, 4, 1, 6, 0)),
+            Some(start)
+        );
+        assert_eq!(Recurrence::ONCE.next_after(&start, &start), None);
+    }
+
+    #[test]
+    fn monthly_on_day() {
+        let day_31 = Recurrence::monthly_on_day(31).unwrap();
+        assert_eq!(
+            dates(day_31, utc(2019, 1, 31, 7, 0), 5),
+            [
+                (2019, 3, 31),
+                (2019, 5, 31),
+                (2019, 7, 31),
+                (2019, 8, 31),
+                (2019, 10, 31)
+            ]
+        );
+
+        let day_15 = Recurrence::monthly_on_day(15).unwrap();
+        assert_eq!(
+            dates(day_15, utc(2019, 11, 15, 7, 0), 3),
+            [(2019, 12, 15), (2020, 1, 15), (2020, 2, 15)]
+        );
+
+        let day_29 = Recurrence::monthly_on_day(29).unwrap();
+        assert_eq!(
+            dates(day_29, utc(2019, 1, 29, 7, 0), 2),
+            [(2019, 3, 29), (2019, 4, 29)]
+        );
+        assert_eq!(
+            dates(day_29, utc(2020, 1, 29, 7, 0), 2),
+            [(2020, 2, 29), (2020, 3, 29)]
+        );
+    }
+
+    #[test]
+    fn monthly_on_weekday() {
+        let first_monday = Recurrence::monthly_on_weekday(1, DayOfTheWeek::Monday).unwrap();
+        assert_eq!(
+            dates(first_monday, utc(2019, 1, 7, 7, 0), 4),
+            [(2019, 2, 4), (2019, 3, 4), (2019, 4, 1), (2019, 5, 6)]
+        );
+
+        let last_friday = Recurrence::monthly_on_weekday(5, DayOfTheWeek::Friday).unwrap();
+        assert_eq!(
+            dates(last_friday, utc(2019, 1, 25, 7, 0), 4),
+            [(2019, 2, 22), (2019, 3, 29), (2019, 4, 26), (2019, 5, 31)]
+        );
+
+        let fourth_sunday = Recurrence::monthly_on_weekday(4, DayOfTheWeek::Sunday).unwrap();
+        assert_eq!(
+            dates(fourth_sunday, utc(2019, 11, 24, 7, 0), 2),
+            [(2019, 12, 22), (2020, 1, 26)]
+        );
+    }
+
+    #[test]
+    fn yearly() {
+        let birthday = Recurrence::yearly(Month::July, 4).unwrap();
+        assert_eq!(
+            dates(birthday, utc(2019, 3, 1, 7, 0), 3),
+            [(2019, 7, 4), (2020, 7, 4), (2021, 7, 4)]
+        );
+
+        let leap_day = Recurrence::yearly(Month::February, 29).unwrap();
+        assert_eq!(
+            dates(leap_day, utc(2020, 2, 29, 7, 0), 2),
+            [(2024, 2, 29), (2028, 2, 29)]
+        );
+        assert_eq!(dates(leap_day, utc(2096, 2, 29, 7, 0), 1), [(2104, 2, 29)]);
+    }
+
+    #[test]
+    fn every_days() {
+        // Every other Monday, keeping the wall time over the DST change
+        let fortnightly = Recurrence::every_days(14).unwrap();
+        let start = cet(2019, 3, 18, 7, 0);
+        assert_eq!(
+            dates(fortnightly, start, 3),
+            [(2019, 4, 1), (2019, 4, 15), (2019, 4, 29)]
+        );
+
+        let next = fortnightly.next_after(&start, &start).unwrap();
+        assert_eq!(next.into_local_date_time().hour(), 7);
+        assert_eq!(
+            next.timestamp().0 - start.timestamp().0,
+            14 * 24 * HOUR - HOUR
+        );
+
+        // Far behind, the phase is kept
+        let after = cet(2019, 12, 24, 12, 0);
+        let next = fortnightly.next_after(&start, &after).unwrap();
+        let local = next.into_local_date_time();
+        assert_eq!(
+            (local.year(), local.month().to_number(), local.day()),
+            (2020, 1, 6)
+        );
+        assert_eq!(local.hour(), 7);
+
+        // Before the start, the start is the next occurrence
+        assert_eq!(
+            fortnightly.next_after(&start, &cet(2019, 3, 1, 0, 0)),
+            Some(start)
+        );
+    }
+
+    #[test]
+    fn interval() {
+        // Every 90 minutes ignores the DST change
+        let ninety = Recurrence::interval(Duration::from_secs(90 * 60)).unwrap();
+        let start = Uhr::from_timestamp_nanos(cet(2019, 3, 31, 0, 0).timestamp(), 500);
+
+        let next = ninety.next_after(&start, &start).unwrap();
+        assert_eq!(next.timestamp().0 - start.timestamp().0, 90 * 60);
+        assert_eq!(next.subsec_nanos(), 500);
+
+        let next = ninety.next_after(&start, &next).unwrap();
+        assert_eq!(next.timestamp().0 - start.timestamp().0, 180 * 60);
+
+        // Far behind, the phase is kept
+        let after = Uhr::from(UnixTimestamp(start.timestamp().0 + 100 * HOUR));
+        let next = ninety.next_after(&start, &after).unwrap();
+        assert_eq!(
+            next.timestamp().0 - start.timestamp().0,
+            100 * HOUR + 30 * 60
+        );
+
+        // Exactly on an occurrence, the following one is next
+        let after = Uhr::from_timestamp_nanos(UnixTimestamp(start.timestamp().0 + 3 * HOUR), 500);
+        let next = ninety.next_after(&start, &after).unwrap();
+        assert_eq!(next.timestamp().0 - start.timestamp().0, 3 * HOUR + 90 * 60);
+
+        assert_eq!(
+            ninety.next_after(&start, &Uhr::from(UnixTimestamp(0))),
+            Some(start)
+        );
+        assert_eq!(
+            ninety.next_after(&start, &Uhr::from(UnixTimestamp(i64::MAX / 2))),
+            None
+        );
+    }
+
+    #[test]
+    fn exhaustive_against_brute_force() {
+        let mut schedules = std::vec::Vec::new();
+        for bits in 1..=DayFlags::all().bits() {
+            schedules.push(Recurrence::weekly(DayFlags::from_bits(bits).unwrap()));
+        }
+        for day in 1..=31 {
+            schedules.push(Recurrence::monthly_on_day(day).unwrap());
+        }
+        for week in 1..=5 {
+            for weekday in 1..=7 {
+                let weekday = DayOfTheWeek::from_iso_number(weekday).unwrap();
+                schedules.push(Recurrence::monthly_on_weekday(week, weekday).unwrap());
+            }
+        }
+        for &(month, day) in &[(1, 1), (2, 28), (2, 29), (3, 31), (12, 31)] {
+            schedules.push(Recurrence::yearly(Month::from_number(month).unwrap(), day).unwrap());
+        }
+
+        // Every 13 days through 2019 and 2020, in a time zone with DST, at
+        // a wall time that is skipped once a year
+        for schedule in schedules {
+            let start = cet(2019, 1, 1, 2, 30);
+            for step in 0..(2 * 366 / 13) {
+                let after = Uhr::from(UnixTimestamp(
+                    start.timestamp().0 + step * 13 * 24 * HOUR + 5 * HOUR,
+                ));
+                assert_eq!(
+                    schedule.next_occurrence(&start, 2 * 3600 + 30 * 60, &after),
+                    brute_force(schedule, &start, &after),
+                    "{:?} after {}",
+                    schedule,
+                    after.rfc3339()
+                );
+            }
+        }
+    }
+}